@@ -0,0 +1,160 @@
+use aws_lambda_events::event::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpResponse};
+
+use lambda_utils::aws_sdk::api_gateway::{authorize_admin, resolve_group, ApiError};
+use lambda_utils::aws_sdk::aws_dynamodb::DynamoDbUtil;
+use lambda_utils::aws_sdk::router::{Request, Router};
+use lambda_utils::models::{SstSecret, SstTable};
+use lambda_utils::persistence::group_dao::GroupDao;
+use lambda_utils::persistence::image_dynamo_dao::{ImageDynamoDao, ImageDynamoDaoError};
+use lambda_utils::persistence::image_s3_dao::group_prefix;
+use serde::{Deserialize, Serialize};
+use sst_sdk::Resource;
+use tracing::{info, instrument};
+
+#[instrument(skip_all)]
+pub async fn handler<D: DynamoDbUtil>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    req: ApiGatewayV2httpRequest,
+) -> Result<ApiGatewayV2httpResponse, lambda_runtime::Error> {
+    // The request isn't logged since it holds the admin token
+    info!(route_key = ?req.route_key, "The route passed into the lambda is");
+
+    let image_dynamo_dao = ImageDynamoDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    if let Err(api_gateway_response) = authorize_admin(&req, &environment_variables.admin_token) {
+        return Ok(*api_gateway_response);
+    }
+
+    let group = match resolve_group(
+        &req,
+        environment_variables.default_group.as_deref(),
+        &group_dao,
+    )
+    .await
+    {
+        Ok(group) => group,
+        Err(api_gateway_response) => return Ok(api_gateway_response),
+    };
+
+    let router = Router::new().put("/archive", |request: Request<RequestBody>| {
+        handle_put(request.body, &group, &image_dynamo_dao)
+    });
+
+    Ok(router.handle(req).await)
+}
+
+// Body of the request to be recevied
+#[derive(Serialize, Deserialize, Debug)]
+struct RequestBody {
+    object_key: String,
+    archived: bool,
+}
+
+// Body of the response for PUT
+#[derive(Serialize, Deserialize, Debug)]
+struct ResponseBody {
+    object_key: String,
+    archived: bool,
+    was_archived: bool,
+}
+
+// Error enum for PUT
+#[derive(Debug)]
+pub enum PutHandlerError {
+    ImageDynamoDaoError(ImageDynamoDaoError),
+    InvalidObjectKey(String),
+    LocalError(String),
+}
+
+impl From<ImageDynamoDaoError> for PutHandlerError {
+    fn from(err: ImageDynamoDaoError) -> Self {
+        Self::ImageDynamoDaoError(err)
+    }
+}
+
+impl From<String> for PutHandlerError {
+    fn from(err: String) -> Self {
+        Self::LocalError(err)
+    }
+}
+
+impl From<PutHandlerError> for ApiError {
+    fn from(err: PutHandlerError) -> Self {
+        match err {
+            PutHandlerError::InvalidObjectKey(message) => ApiError::BadRequest(message),
+            err => ApiError::internal(err),
+        }
+    }
+}
+
+async fn handle_put<D: DynamoDbUtil>(
+    body: RequestBody,
+    group: &str,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+) -> Result<ResponseBody, PutHandlerError> {
+    info!(body = ?body, "The parsed body value");
+
+    // A group's images are the objects under the group's prefix
+    if !body.object_key.starts_with(&group_prefix(group)) {
+        return Err(PutHandlerError::InvalidObjectKey(format!(
+            "The image {} does not belong to group {}",
+            body.object_key, group
+        )));
+    }
+
+    let was_archived = image_dynamo_dao
+        .set_archived(group, &body.object_key, body.archived)
+        .await?;
+
+    info!(
+        was_archived = was_archived,
+        "Request to archive the image complete. Whether it was archived before was"
+    );
+
+    Ok(ResponseBody {
+        object_key: body.object_key,
+        archived: body.archived,
+        was_archived,
+    })
+}
+
+pub struct EnvironmentVariables {
+    pub default_group: Option<String>,
+    pub admin_token: String,
+    pub table_name: String,
+    pub table_primary_key: String,
+    pub table_sort_key: String,
+}
+
+impl EnvironmentVariables {
+    pub fn build() -> EnvironmentVariables {
+        let resource = Resource::init().expect("Should be able to initialize SST resource");
+        let table: SstTable = resource
+            .get("ImageTable")
+            .expect("Should be able to get ImageTable");
+        let admin_token: SstSecret = resource
+            .get("AdminApiToken")
+            .expect("Should have an AdminApiToken resource");
+
+        EnvironmentVariables {
+            default_group: std::env::var("DEFAULT_GROUP").ok(),
+            admin_token: admin_token.value,
+            table_name: table.name,
+            table_primary_key: table.primary_key,
+            table_sort_key: table.sort_key,
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use chrono::{Duration, NaiveDate};
+use lambda_utils::{
+    aws_sdk::{aws_dynamodb::DynamoDbUtil, aws_s3::S3Util},
+    models::{GroupDay, SstBucket, SstTable},
+    persistence::{
+        group_dao::{GroupDao, GroupSettings},
+        image_dynamo_dao::ImageDynamoDao,
+        image_s3_dao::ImageS3Dao,
+        user_reaction_dao::UserReactionDao,
+    },
+};
+use sst_sdk::Resource;
+use tracing::{error, info};
+
+use crate::select_and_set::{select_and_set_random_s3_object, SelectionMode};
+use crate::selection_strategy::build_selection_strategy;
+
+///
+/// Tops up the queue of upcoming images for every registered group starting from the same date. See
+/// `setup_all_groups_by_day`.
+///
+pub async fn setup_all_groups<D: DynamoDbUtil, S: S3Util>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    s3_client: &S,
+    date: NaiveDate,
+) -> Result<(), String> {
+    setup_all_groups_by_day(environment_variables, dynamodb_client, s3_client, |_| date).await
+}
+
+///
+/// Tops up the queue of upcoming images for every registered group, picking the image and setting up
+/// the reaction counts for the group's first date and the group's `queue_days - 1` days after it. Dates
+/// that are already queued keep their image. Every group is attempted even if an earlier one fails.
+///
+/// # Arguments
+/// * `start_date` - Works out the first date being set up from the group's day, e.g. the day after
+///   the group's current date
+///
+/// # Returns
+/// * `Ok(())` - Every group was set up
+/// * `Err(String)` - The groups couldn't be listed or at least one group failed to be set up
+///
+pub async fn setup_all_groups_by_day<D: DynamoDbUtil, S: S3Util, F: Fn(GroupDay) -> NaiveDate>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    s3_client: &S,
+    start_date: F,
+) -> Result<(), String> {
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let user_reaction_dao = UserReactionDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let image_dynamo_dao = ImageDynamoDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let image_s3_dao = ImageS3Dao {
+        bucket_name: &environment_variables.bucket_name,
+        s3_client,
+    };
+
+    // Crashes the lambda and retries if the groups can't be listed
+    let groups = group_dao.list_groups().await.map_err(|err| {
+        error!(error = ?err, "Failed to list the registered groups due to the following");
+        format!("Failed to list the registered groups: {:?}", err)
+    })?;
+
+    // Every group is attempted even if an earlier one fails so one bad group can't block the rest
+    let mut failed_groups = Vec::new();
+    for group in groups {
+        // Bad settings shouldn't stop the group from getting an image
+        let settings = group_dao.get_settings(&group).await.unwrap_or_else(|err| {
+            error!(group = group, error = ?err, "Failed to read the group's settings. Using the defaults");
+            GroupSettings::default()
+        });
+        let date = start_date(settings.group_day());
+
+        if let Err(err) = top_up_queue(
+            &group,
+            &settings,
+            date,
+            &image_dynamo_dao,
+            &image_s3_dao,
+            &user_reaction_dao,
+            environment_variables.selection_mode,
+        )
+        .await
+        {
+            error!(
+                group = group,
+                date = ?date,
+                error = err,
+                "Failed to set up the date for group"
+            );
+            failed_groups.push(format!("{} ({})", group, date.format("%Y-%m-%d")));
+        }
+    }
+
+    if !failed_groups.is_empty() {
+        return Err(format!("Failed to set up groups: {:?}", failed_groups));
+    }
+
+    Ok(())
+}
+
+///
+/// Picks the image and sets up the reaction counts for just the provided date for one group. Used to
+/// fill in a date the daily setup missed. The image is written conditionally so concurrent calls end up
+/// with the same image.
+///
+/// # Arguments
+/// * `group` - The group being set up
+/// * `date` - The date being set up
+///
+/// # Returns
+/// * `Ok(())` - The date has an image, either the one that was picked or one set in the meantime
+/// * `Err(String)` - The image or the reaction counts couldn't be set up
+///
+pub async fn setup_group_date<D: DynamoDbUtil, S: S3Util>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    s3_client: &S,
+    group: &str,
+    date: NaiveDate,
+) -> Result<(), String> {
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let user_reaction_dao = UserReactionDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let image_dynamo_dao = ImageDynamoDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let image_s3_dao = ImageS3Dao {
+        bucket_name: &environment_variables.bucket_name,
+        s3_client,
+    };
+
+    let settings = group_dao.get_settings(group).await.unwrap_or_else(|err| {
+        error!(group = group, error = ?err, "Failed to read the group's settings. Using the defaults");
+        GroupSettings::default()
+    });
+
+    setup_group(
+        group,
+        &settings,
+        date,
+        &image_dynamo_dao,
+        &image_s3_dao,
+        &user_reaction_dao,
+        environment_variables.selection_mode,
+    )
+    .await
+}
+
+///
+/// Sets up each day of the group's queue in order, since each day's pick and recap depend on the days
+/// before it. Stops at the first day that fails.
+///
+async fn top_up_queue<D: DynamoDbUtil, S: S3Util>(
+    group: &str,
+    settings: &GroupSettings,
+    date: NaiveDate,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+    image_s3_dao: &ImageS3Dao<'_, S>,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+    selection_mode: SelectionMode,
+) -> Result<(), String> {
+    for day in 0..settings.queue_days {
+        setup_group(
+            group,
+            settings,
+            date + Duration::days(day),
+            image_dynamo_dao,
+            image_s3_dao,
+            user_reaction_dao,
+            selection_mode,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn setup_group<D: DynamoDbUtil, S: S3Util>(
+    group: &str,
+    settings: &GroupSettings,
+    date: NaiveDate,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+    image_s3_dao: &ImageS3Dao<'_, S>,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+    selection_mode: SelectionMode,
+) -> Result<(), String> {
+    info!(group = group, date = ?date, "Setting up the date for group");
+
+    let selection_strategy =
+        build_selection_strategy(group, date, settings, image_dynamo_dao, user_reaction_dao).await;
+
+    select_and_set_random_s3_object(
+        group,
+        date,
+        image_dynamo_dao,
+        image_s3_dao,
+        selection_mode,
+        selection_strategy.as_ref(),
+        settings,
+    )
+    .await
+    .map_err(|err| {
+        format!(
+            "Failed to get a random object from the bucket due to the following: {:?}",
+            err
+        )
+    })?;
+
+    // Make request to set up counts
+    user_reaction_dao
+        .setup_counts(group, &date.format("%Y-%m-%d").to_string())
+        .await
+        .map_err(|err| format!("Failed to set up the reaction counts: {:?}", err))?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvironmentVariables {
+    pub bucket_name: String,
+    pub table_name: String,
+    pub table_primary_key: String,
+    pub table_sort_key: String,
+    pub selection_mode: SelectionMode,
+}
+
+impl EnvironmentVariables {
+    pub fn build() -> EnvironmentVariables {
+        let resource =
+            Resource::init().expect("Should be able to initialize the SST resource object");
+
+        let bucket: SstBucket = resource
+            .get("ViewableBucketListOnly")
+            .expect("Should have a ViewableBucketListOnly resource");
+        let table: SstTable = resource
+            .get("ImageTable")
+            .expect("Should have an ImageTable resource");
+
+        EnvironmentVariables {
+            bucket_name: bucket.name,
+            table_name: table.name,
+            table_primary_key: table.primary_key,
+            table_sort_key: table.sort_key,
+            // Optional override so the old list everything behaviour can be switched back on
+            selection_mode: std::env::var("SELECTION_MODE")
+                .map(|selection_mode| {
+                    selection_mode
+                        .parse()
+                        .expect("SELECTION_MODE should be list_all or reservoir_sample")
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
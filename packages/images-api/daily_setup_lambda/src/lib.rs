@@ -1 +1,3 @@
+pub mod daily_setup;
 pub mod select_and_set;
+pub mod selection_strategy;
@@ -1,19 +1,11 @@
 use aws_config::BehaviorVersion;
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 use aws_sdk_s3::Client as S3Client;
-use chrono::{Duration, NaiveDate};
-use daily_setup_lambda::select_and_set::select_and_set_random_s3_object;
+use chrono::{DateTime, Duration, Utc};
+use daily_setup_lambda::daily_setup::{setup_all_groups_by_day, EnvironmentVariables};
 use lambda_runtime::{run, service_fn, Error, LambdaEvent};
-use lambda_utils::{
-    models::{SstBucket, SstTable},
-    persistence::{
-        image_dynamo_dao::ImageDynamoDao, image_s3_dao::ImageS3Dao,
-        user_reaction_dao::UserReactionDao,
-    },
-};
 use serde::Deserialize;
-use sst_sdk::Resource;
-use tracing::{error, info};
+use tracing::info;
 
 #[derive(Deserialize, Debug)]
 struct Request {
@@ -39,8 +31,6 @@ async fn main() -> Result<(), Error> {
     .await
 }
 
-const HARDCODED_PREFIX: &str = "discord";
-
 async fn function_handler(
     environment_variables: &EnvironmentVariables,
     aws_clients: &AwsClients,
@@ -49,79 +39,22 @@ async fn function_handler(
     // Extract some useful information from the request
     info!(event = ?event, "The event passed into the lambda is");
 
-    let tomorrow_as_date =
-        NaiveDate::parse_from_str(&event.time, "%Y-%m-%dT%H:%M:%SZ")? + Duration::days(1);
-    let tomorrow_as_date_string = tomorrow_as_date.format("%Y-%m-%d").to_string();
-
-    let user_reaction_dao = UserReactionDao {
-        table_name: &environment_variables.table_name,
-        primary_key: &environment_variables.table_primary_key,
-        sort_key: &environment_variables.table_sort_key,
-        dynamodb_client: &aws_clients.dynamodb_client,
-    };
-
-    let image_dynamo_dao = ImageDynamoDao {
-        table_name: &environment_variables.table_name,
-        primary_key: &environment_variables.table_primary_key,
-        sort_key: &environment_variables.table_sort_key,
-        dynamodb_client: &aws_clients.dynamodb_client,
-    };
-
-    let image_s3_dao = ImageS3Dao {
-        bucket_name: &environment_variables.bucket_name,
-        s3_client: &aws_clients.s3_client,
-    };
+    let event_time = DateTime::parse_from_rfc3339(&event.time)?.with_timezone(&Utc);
 
-    // Crashes the lambda and retries if this fails
-    select_and_set_random_s3_object(tomorrow_as_date, &image_dynamo_dao, &image_s3_dao)
-        .await
-        .map_err(|err| {
-            error!(
-                "Failed to get a random object from the bucket due to the following: {:?}",
-                err
-            );
-        })
-        .unwrap();
-
-    // Make request to set up counts. Lambda should also crash if this fails too
-    // (May lead to image for tomorrow getting set twice but thatn's not a big deal)
-    user_reaction_dao
-        .setup_counts(HARDCODED_PREFIX, &tomorrow_as_date_string)
-        .await
-        .unwrap();
+    // Each group's queue starts from tomorrow in the group's own timezone
+    // Crashes the lambda and retries if any group failed
+    // (Dates that are already queued keep their image so a retry never changes it)
+    setup_all_groups_by_day(
+        environment_variables,
+        &aws_clients.dynamodb_client,
+        &aws_clients.s3_client,
+        |group_day| group_day.date_at(event_time) + Duration::days(1),
+    )
+    .await?;
 
     Ok(())
 }
 
-#[derive(Debug)]
-struct EnvironmentVariables {
-    bucket_name: String,
-    table_name: String,
-    table_primary_key: String,
-    table_sort_key: String,
-}
-
-impl EnvironmentVariables {
-    fn build() -> EnvironmentVariables {
-        let resource =
-            Resource::init().expect("Should be able to initialize the SST resource object");
-
-        let bucket: SstBucket = resource
-            .get("ViewableBucketListOnly")
-            .expect("Should have a ViewableBucketListOnly resource");
-        let table: SstTable = resource
-            .get("ImageTable")
-            .expect("Should have an ImageTable resource");
-
-        EnvironmentVariables {
-            bucket_name: bucket.name,
-            table_name: table.name,
-            table_primary_key: table.primary_key,
-            table_sort_key: table.sort_key,
-        }
-    }
-}
-
 #[derive(Debug)]
 struct AwsClients {
     s3_client: S3Client,
@@ -1,21 +1,59 @@
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 
+use aws_sdk_s3::types::Object;
 use chrono::NaiveDate;
-use lambda_utils::persistence::{
-    image_dynamo_dao::{ImageDynamoDao, ImageDynamoDaoError},
-    image_s3_dao::{ImageS3Dao, ImageS3DaoError},
+use lambda_utils::{
+    aws_sdk::{aws_dynamodb::DynamoDbUtil, aws_s3::S3Util},
+    persistence::{
+        group_dao::{GroupSettings, NoRepeatWindow},
+        image_dynamo_dao::{ImageDynamoDao, ImageDynamoDaoError, ImageHistory},
+        image_s3_dao::{group_prefix, ImageS3Dao, ImageS3DaoError},
+    },
 };
 
 use rand::seq::SliceRandom;
+
+use crate::selection_strategy::SelectionStrategy;
 use tracing::{
     instrument,
-    log::{error, info},
+    log::{error, info, warn},
 };
 
+///
+/// How the random object is picked from the bucket
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// List every object up front and pick from the full list
+    ListAll,
+    /// Stream the listing a page at a time and pick with reservoir sampling
+    #[default]
+    ReservoirSample,
+}
+
+impl FromStr for SelectionMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<SelectionMode, String> {
+        match value {
+            "list_all" => Ok(SelectionMode::ListAll),
+            "reservoir_sample" => Ok(SelectionMode::ReservoirSample),
+            _ => Err(format!(
+                "Unknown selection mode {}. Expected list_all or reservoir_sample",
+                value
+            )),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SelectAndSetRandomObjectError {
     ImageDynamoDaoFailure(ImageDynamoDaoError),
     ImageS3DaoFailure(ImageS3DaoError),
+    /// There were no unarchived images under the group's prefix to pick from
+    NoObjectsWithPrefix(String),
     LocalError(String),
 }
 
@@ -37,78 +75,575 @@ impl From<String> for SelectAndSetRandomObjectError {
     }
 }
 
-const HARDCODED_PREFIX: &str = "discord";
-
 #[instrument(skip_all)]
-pub async fn select_and_set_random_s3_object(
+pub async fn select_and_set_random_s3_object<D: DynamoDbUtil, S: S3Util>(
+    group: &str,
     tomorrow: NaiveDate,
-    image_dynamo_dao: &ImageDynamoDao<'_>,
-    image_s3_dao: &ImageS3Dao<'_>,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+    image_s3_dao: &ImageS3Dao<'_, S>,
+    selection_mode: SelectionMode,
+    selection_strategy: &dyn SelectionStrategy,
+    settings: &GroupSettings,
 ) -> Result<String, SelectAndSetRandomObjectError> {
-    // Get the images
+    // Retries of the daily setup must never change an image people may have already seen
+    let pinned_image = match image_dynamo_dao.get_image(group, tomorrow).await? {
+        Some(image) if image.pinned => Some(image),
+        Some(image) => {
+            info!(
+                "The image for {} is already set to {}. Leaving it as is",
+                tomorrow, image.object_key
+            );
+            return Ok(image.object_key);
+        }
+        None => None,
+    };
+
+    // Only the days since the last recap are needed to know when the next one is due
+    let recap_days = settings.recap_period_days - 1;
     let list_of_images = match image_dynamo_dao
-        .get_recents(HARDCODED_PREFIX, tomorrow)
+        .get_images_before(group, tomorrow, recap_days)
         .await
     {
         Ok(list_of_images) => list_of_images,
+        // Finding the last recap in the days that were fetched is still better than assuming none
+        Err(ImageDynamoDaoError::PartialImages {
+            images,
+            unprocessed_count,
+        }) => {
+            warn!(
+                "{} recent images couldn't be fetched. Using the ones that were",
+                unprocessed_count
+            );
+            images
+        }
         Err(err) => {
             error!("Encountered the following error while trying to find the most recent images: {:?}. Using empty set", err);
             Vec::new()
         }
     };
 
-    // Get set of all object_keys and if there has been a get_recents call
-    let last_get_recent = list_of_images.iter().find(|image| image.get_recents);
-
-    let days_since_get_recents = last_get_recent.map_or(0, |last_get_recent| {
-        tomorrow
-            .signed_duration_since(last_get_recent.date)
-            .num_days()
-    });
+    let days_before_tomorrow = |date: NaiveDate| tomorrow.signed_duration_since(date).num_days();
 
-    let set_of_recents = list_of_images
-        .iter()
-        .map(|image| image.object_key.to_owned())
-        .collect::<HashSet<String>>();
-
-    // List all objects in the bucket
-    let objects_list = image_s3_dao.list_by_prefix(HARDCODED_PREFIX).await?;
+    // Find the last get_recents call since the previous recap, if there has been one
+    let last_get_recent = list_of_images.iter().find(|image| image.get_recents);
 
-    info!("The set of recent object_keys: {:?}", set_of_recents);
+    let days_since_get_recents =
+        last_get_recent.map_or(0, |image| days_before_tomorrow(image.date));
 
-    // Chose a random object until it isn't one from the last five days (technically could loop forever? But shouldn't)
-    let random_selected_object = loop {
-        let random_object = objects_list
-            .choose(&mut rand::thread_rng())
-            .ok_or_else(|| "Randomly selected object did not exist".to_owned())?;
+    // Pinned images are kept and only have the recap filled in now that it's known whether one is due
+    if let Some(pinned_image) = pinned_image {
+        info!(
+            "The image for {} is pinned to {}. Filling in the recap",
+            tomorrow, pinned_image.object_key
+        );
 
-        info!("Randomly selected object: {:?}", random_object);
+        return image_dynamo_dao
+            .set_image(
+                group,
+                Object::builder().key(pinned_image.object_key).build(),
+                tomorrow,
+                days_since_get_recents,
+                settings.recap_period_days,
+                true,
+            )
+            .await
+            .map_err(|err| {
+                error!(
+                    "Failed to fill in the recap of the pinned image due to the following: {:?}",
+                    err
+                );
+                SelectAndSetRandomObjectError::LocalError(
+                    "Failed to fill in the recap of the pinned image".to_owned(),
+                )
+            });
+    }
 
-        if random_object.key().is_some() && !set_of_recents.contains(random_object.key().unwrap()) {
-            break random_object.to_owned();
-        }
-    };
+    let random_selected_object = select_random_object(
+        group,
+        tomorrow,
+        image_dynamo_dao,
+        image_s3_dao,
+        selection_mode,
+        selection_strategy,
+        settings,
+    )
+    .await?;
     info!("Selected a random object: {:?}", random_selected_object);
 
     let object_key = image_dynamo_dao
         .set_image(
-            HARDCODED_PREFIX,
+            group,
             random_selected_object,
             tomorrow,
             days_since_get_recents,
+            settings.recap_period_days,
+            false,
         )
-        .await
-        .map_err(|err| {
+        .await;
+
+    let object_key = match object_key {
+        Ok(object_key) => {
+            info!("Successfully wrote random object to dynamodb");
+            object_key
+        }
+        // Another run set the image in the meantime, which is just as good
+        Err(ImageDynamoDaoError::ImageAlreadySet(object_key)) => {
+            info!(
+                "The image for {} was set to {} in the meantime. Leaving it as is",
+                tomorrow, object_key
+            );
+            object_key
+        }
+        Err(err) => {
             error!(
                 "Failed to write the random object to dynamodb due to the following: {:?}",
                 err
             );
-            SelectAndSetRandomObjectError::LocalError(
+            return Err(SelectAndSetRandomObjectError::LocalError(
                 "Failed to write the random object to dynamodb".to_owned(),
+            ));
+        }
+    };
+
+    Ok(object_key)
+}
+
+///
+/// Picks a random object for tomorrow from the group's unarchived objects, avoiding the ones shown
+/// recently and the ones already queued or pinned for upcoming dates.
+///
+/// # Returns
+/// * `Ok(Object)` - The picked object
+/// * `Error(SelectAndSetRandomObjectError::NoObjectsWithPrefix)` - The group has no unarchived images
+/// * `Error(SelectAndSetRandomObjectError)` - Any failure that occurs when listing the objects
+///
+async fn select_random_object<D: DynamoDbUtil, S: S3Util>(
+    group: &str,
+    tomorrow: NaiveDate,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+    image_s3_dao: &ImageS3Dao<'_, S>,
+    selection_mode: SelectionMode,
+    selection_strategy: &dyn SelectionStrategy,
+    settings: &GroupSettings,
+) -> Result<Object, SelectAndSetRandomObjectError> {
+    let days_before_tomorrow = |date: NaiveDate| tomorrow.signed_duration_since(date).num_days();
+
+    let image_histories = get_image_histories(group, tomorrow, image_dynamo_dao).await;
+
+    // Images already queued or pinned for a later date are avoided as if they were the most recently shown
+    let upcoming_object_keys = image_histories
+        .iter()
+        .filter(|history| history.last_shown > tomorrow)
+        .map(|history| history.object_key.to_owned())
+        .collect::<Vec<String>>();
+
+    // The last time each image was shown before tomorrow, which is what the no repeat window counts from
+    let mut last_shown_before_tomorrow = image_histories
+        .iter()
+        .filter_map(|history| {
+            history
+                .dates
+                .range(..tomorrow)
+                .next_back()
+                .map(|last_shown| (history.object_key.as_str(), *last_shown))
+        })
+        .filter(|(_, last_shown)| match settings.no_repeat_window {
+            NoRepeatWindow::Days(days) => days_before_tomorrow(*last_shown) <= days,
+            NoRepeatWindow::UntilExhausted => true,
+        })
+        .collect::<Vec<(&str, NaiveDate)>>();
+    last_shown_before_tomorrow.sort_by_key(|(_, last_shown)| Reverse(*last_shown));
+
+    // Ordered from the most recently shown backwards
+    let recent_object_keys = last_shown_before_tomorrow
+        .into_iter()
+        .map(|(object_key, _)| object_key.to_owned())
+        .filter(|object_key| !upcoming_object_keys.contains(object_key))
+        .collect::<Vec<String>>();
+    let recent_object_keys = [upcoming_object_keys, recent_object_keys].concat();
+
+    info!("The recent object_keys: {:?}", recent_object_keys);
+
+    let archived_object_keys = image_dynamo_dao
+        .get_archived_object_keys(group)
+        .await
+        .unwrap_or_else(|err| {
+            error!("Encountered the following error while trying to find the archived images: {:?}. Using empty set", err);
+            HashSet::new()
+        });
+
+    // Directory markers, e.g. "{group}/" made by the S3 console, are never images
+    let is_ineligible = |object: &Object| {
+        object
+            .key()
+            .is_some_and(|key| key.ends_with('/') || archived_object_keys.contains(key))
+    };
+
+    match selection_mode {
+        SelectionMode::ListAll => {
+            // List all objects in the bucket
+            let objects_list = image_s3_dao
+                .list_by_prefix(&group_prefix(group))
+                .await?
+                .into_iter()
+                .filter(|object| !is_ineligible(object))
+                .collect::<Vec<Object>>();
+
+            select_least_recent_object(&objects_list, &recent_object_keys, selection_strategy)
+        }
+        SelectionMode::ReservoirSample => {
+            // How far back each recently shown object was shown. A higher rank was shown longer ago
+            let recent_ranks = recent_object_keys
+                .iter()
+                .enumerate()
+                .map(|(rank, key)| (key.as_str(), rank))
+                .collect::<HashMap<&str, usize>>();
+
+            // Only the least recently shown object is kept in case every object in the bucket was recently shown
+            let mut least_recent_object: Option<(usize, Object)> = None;
+
+            let sampled_object = image_s3_dao
+                .sample_by_prefix(&group_prefix(group), |object| match object.key() {
+                    Some(_) if is_ineligible(object) => 0.0,
+                    Some(key) => match recent_ranks.get(key) {
+                        Some(&rank) => {
+                            if least_recent_object
+                                .as_ref()
+                                .is_none_or(|(least_recent_rank, _)| rank > *least_recent_rank)
+                            {
+                                least_recent_object = Some((rank, object.to_owned()));
+                            }
+                            0.0
+                        }
+                        None => selection_strategy.weight(key),
+                    },
+                    None => 0.0,
+                })
+                .await?;
+
+            sampled_object.or_else(|| {
+                least_recent_object.map(|(rank, object)| {
+                    info!(
+                        "Every object was one of the last {} shown. Only avoiding the last {} shown",
+                        recent_object_keys.len(),
+                        rank
+                    );
+                    object
+                })
+            })
+        }
+    }
+    .ok_or_else(|| SelectAndSetRandomObjectError::NoObjectsWithPrefix(group.to_owned()))
+}
+
+///
+/// Gets the histories of the group's images. Groups set up before histories were kept have their
+/// histories backfilled from every image set before tomorrow the first time they are read, and any dates
+/// in the histories missing from the date index are added to it.
+///
+/// Avoiding no images is better than not picking one at all, so failures are logged and treated as
+/// no history.
+///
+async fn get_image_histories<D: DynamoDbUtil>(
+    group: &str,
+    tomorrow: NaiveDate,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+) -> Vec<ImageHistory> {
+    match image_dynamo_dao.get_image_histories(group).await {
+        Ok(histories) if !histories.is_empty() => {
+            // Histories kept before the date index was still need their dates indexed
+            if let Err(err) = image_dynamo_dao
+                .backfill_image_dates(group, &histories)
+                .await
+            {
+                error!(
+                    "Encountered the following error while trying to backfill the date index: {:?}",
+                    err
+                );
+            }
+            return histories;
+        }
+        Ok(_) => info!("The group has no image histories. Backfilling them"),
+        Err(err) => {
+            error!("Encountered the following error while trying to get the image histories: {:?}. Using empty set", err);
+            return Vec::new();
+        }
+    }
+
+    // Only a complete read is backfilled since the histories are never backfilled again once they exist
+    let images = match image_dynamo_dao
+        .get_all_images_before(group, tomorrow)
+        .await
+    {
+        Ok(images) => images,
+        Err(err) => {
+            error!("Encountered the following error while trying to read the images to backfill: {:?}. Using empty set", err);
+            return Vec::new();
+        }
+    };
+
+    if images.is_empty() {
+        return Vec::new();
+    }
+
+    if let Err(err) = image_dynamo_dao
+        .backfill_image_histories(group, &images)
+        .await
+    {
+        error!("Encountered the following error while trying to backfill the image histories: {:?}. Using empty set", err);
+        return Vec::new();
+    }
+
+    image_dynamo_dao
+        .get_image_histories(group)
+        .await
+        .unwrap_or_else(|err| {
+            error!("Encountered the following error while trying to get the backfilled image histories: {:?}. Using empty set", err);
+            Vec::new()
+        })
+}
+
+///
+/// Picks a random object that hasn't been shown recently, weighted by the selection strategy. If every object has been shown recently the
+/// no-repeat window is shrunk a day at a time, so the least recently shown object is picked once the
+/// window can't be shrunk any further without allowing a more recent one.
+///
+/// # Arguments
+/// * `objects` - The objects to pick from. Objects without keys are never picked
+/// * `recent_object_keys` - The keys of the recently shown objects, most recently shown first
+/// * `selection_strategy` - Weights the chance of each eligible object being picked
+///
+/// # Returns
+/// * `Some(Object)` - The picked object
+/// * `None` - None of the objects have keys
+///
+fn select_least_recent_object(
+    objects: &[Object],
+    recent_object_keys: &[String],
+    selection_strategy: &dyn SelectionStrategy,
+) -> Option<Object> {
+    for window in (0..=recent_object_keys.len()).rev() {
+        let excluded_keys = &recent_object_keys[..window];
+
+        let eligible_objects = objects
+            .iter()
+            .filter(|object| {
+                object
+                    .key()
+                    .is_some_and(|key| !excluded_keys.iter().any(|excluded| excluded == key))
+            })
+            .collect::<Vec<&Object>>();
+
+        // Every eligible object has a key and a weight greater than 0 so this only fails when there are none
+        let selected_object = eligible_objects
+            .choose_weighted(&mut rand::thread_rng(), |object| {
+                selection_strategy.weight(object.key().unwrap_or_default())
+            })
+            .ok();
+
+        if let Some(object) = selected_object {
+            if window < recent_object_keys.len() {
+                // Expected once every image has been shown with a no repeat window of until exhausted
+                info!(
+                    "Every object was one of the last {} shown. Only avoiding the last {} shown",
+                    recent_object_keys.len(),
+                    window
+                );
+            }
+
+            return Some((*object).to_owned());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selection_strategy::UniformStrategy;
+    use lambda_utils::aws_sdk::{in_memory_dynamodb::InMemoryDynamoDb, in_memory_s3::InMemoryS3};
+
+    const TABLE: &str = "ImageTable";
+    const BUCKET: &str = "images";
+    const GROUP: &str = "discord";
+    const SELECTION_MODES: [SelectionMode; 2] =
+        [SelectionMode::ListAll, SelectionMode::ReservoirSample];
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn build_s3_client(keys: &[&str]) -> InMemoryS3 {
+        // Another group's image keeps the bucket around when the group has none
+        keys.iter().fold(
+            InMemoryS3::new().with_object(BUCKET, "other/1.png", Vec::new()),
+            |s3_client, key| s3_client.with_object(BUCKET, key, Vec::new()),
+        )
+    }
+
+    ///
+    /// Runs the daily setup for the 10th in a table where each of the provided images was shown on its day
+    ///
+    async fn select_and_set(
+        keys: &[&str],
+        shown: &[(&str, u32)],
+        archived: &[&str],
+        selection_mode: SelectionMode,
+    ) -> Result<String, SelectAndSetRandomObjectError> {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let s3_client = build_s3_client(keys);
+        let image_dynamo_dao = ImageDynamoDao {
+            table_name: TABLE,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client: &dynamodb_client,
+        };
+        let image_s3_dao = ImageS3Dao {
+            bucket_name: BUCKET,
+            s3_client: &s3_client,
+        };
+
+        for (object_key, day) in shown {
+            image_dynamo_dao
+                .set_image(
+                    GROUP,
+                    Object::builder().key(*object_key).build(),
+                    date(*day),
+                    1,
+                    7,
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+        for object_key in archived {
+            image_dynamo_dao
+                .set_archived(GROUP, object_key, true)
+                .await
+                .unwrap();
+        }
+
+        let object_key = select_and_set_random_s3_object(
+            GROUP,
+            date(10),
+            &image_dynamo_dao,
+            &image_s3_dao,
+            selection_mode,
+            &UniformStrategy,
+            &GroupSettings::default(),
+        )
+        .await?;
+
+        // The picked image is what gets set for the date
+        assert_eq!(
+            image_dynamo_dao
+                .get_image(GROUP, date(10))
+                .await
+                .unwrap()
+                .map(|image| image.object_key),
+            Some(object_key.to_owned())
+        );
+
+        Ok(object_key)
+    }
+
+    #[tokio::test]
+    async fn empty_bucket_has_no_objects_with_prefix() {
+        for selection_mode in SELECTION_MODES {
+            let result = select_and_set(&[], &[], &[], selection_mode).await;
+
+            assert!(matches!(
+                result,
+                Err(SelectAndSetRandomObjectError::NoObjectsWithPrefix(group)) if group == GROUP
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn directory_markers_are_never_picked() {
+        for selection_mode in SELECTION_MODES {
+            let result =
+                select_and_set(&["discord/", "discord/old/"], &[], &[], selection_mode).await;
+
+            assert!(matches!(
+                result,
+                Err(SelectAndSetRandomObjectError::NoObjectsWithPrefix(group)) if group == GROUP
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn every_image_archived_has_no_objects_with_prefix() {
+        for selection_mode in SELECTION_MODES {
+            let result = select_and_set(
+                &["discord/1.png", "discord/2.png"],
+                &[],
+                &["discord/1.png", "discord/2.png"],
+                selection_mode,
+            )
+            .await;
+
+            assert!(matches!(
+                result,
+                Err(SelectAndSetRandomObjectError::NoObjectsWithPrefix(group)) if group == GROUP
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn picks_the_only_image_outside_the_no_repeat_window() {
+        for selection_mode in SELECTION_MODES {
+            let object_key = select_and_set(
+                &["discord/1.png", "discord/2.png", "discord/3.png"],
+                &[("discord/1.png", 8), ("discord/2.png", 9)],
+                &[],
+                selection_mode,
             )
-        })?;
+            .await
+            .unwrap();
 
-    info!("Successfully wrote random object to dynamodb");
+            assert_eq!(object_key, "discord/3.png");
+        }
+    }
 
-    Ok(object_key)
+    #[tokio::test]
+    async fn picks_the_least_recently_shown_once_everything_was_shown() {
+        for selection_mode in SELECTION_MODES {
+            let object_key = select_and_set(
+                &["discord/1.png", "discord/2.png", "discord/3.png"],
+                &[
+                    ("discord/2.png", 7),
+                    ("discord/1.png", 8),
+                    ("discord/3.png", 9),
+                ],
+                &[],
+                selection_mode,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(object_key, "discord/2.png");
+        }
+    }
+
+    #[tokio::test]
+    async fn archived_images_are_skipped_by_the_fallback() {
+        for selection_mode in SELECTION_MODES {
+            let object_key = select_and_set(
+                &["discord/1.png", "discord/2.png", "discord/3.png"],
+                &[
+                    ("discord/2.png", 7),
+                    ("discord/1.png", 8),
+                    ("discord/3.png", 9),
+                ],
+                &["discord/2.png"],
+                selection_mode,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(object_key, "discord/1.png");
+        }
+    }
 }
@@ -0,0 +1,344 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDate;
+use lambda_utils::{
+    aws_sdk::aws_dynamodb::DynamoDbUtil,
+    persistence::{
+        group_dao::{GroupSettings, SelectionStrategyKind, SelectionWeights},
+        image_dynamo_dao::{ImageDynamoDao, ImageDynamoDaoError},
+        user_reaction_dao::UserReactionDao,
+    },
+};
+use tracing::{error, info, instrument, warn};
+
+// Keeps a never shown weight of 0 from taking new images out of rotation entirely
+const MIN_WEIGHT: f64 = 0.01;
+
+///
+/// Decides how likely each image is to be picked as the daily image
+///
+pub trait SelectionStrategy: Send + Sync {
+    ///
+    /// The relative chance of the object with the provided key being picked. Always greater than 0
+    ///
+    fn weight(&self, object_key: &str) -> f64;
+}
+
+///
+/// Every image is equally likely to be picked
+///
+pub struct UniformStrategy;
+
+impl SelectionStrategy for UniformStrategy {
+    fn weight(&self, _object_key: &str) -> f64 {
+        1.0
+    }
+}
+
+///
+/// Images that were loved, found funny or picked as a favorite are more likely to be picked again and
+/// images that have never been shown are more likely than either
+///
+#[derive(Debug)]
+pub struct WeightedStrategy {
+    weights: SelectionWeights,
+    history: HashMap<String, ImageHistory>,
+    /// Every image shown before the date being picked for, including the ones shown before the history window
+    shown_object_keys: HashSet<String>,
+}
+
+// How an image was received over the history window
+#[derive(Debug, Default)]
+struct ImageHistory {
+    days_shown: i64,
+    reactions: HashMap<String, i64>,
+    favorites: i64,
+}
+
+impl WeightedStrategy {
+    ///
+    /// Reads the images, reaction counts and favorites for the days in the history window before the
+    /// provided date, along with the image histories to tell which images have never been shown.
+    ///
+    /// # Arguments
+    /// * `date` - The date being picked for. Only days before it are read
+    /// * `weights` - The group's selection weights, including how many days of history to read
+    ///
+    /// # Returns
+    /// * `Ok(WeightedStrategy)` - The strategy weighted by the history that was read
+    /// * `Err(String)` - The history couldn't be read
+    ///
+    #[instrument(skip_all)]
+    pub async fn build<D: DynamoDbUtil>(
+        group: &str,
+        date: NaiveDate,
+        weights: SelectionWeights,
+        image_dynamo_dao: &ImageDynamoDao<'_, D>,
+        user_reaction_dao: &UserReactionDao<'_, D>,
+    ) -> Result<WeightedStrategy, String> {
+        let images = match image_dynamo_dao
+            .get_images_before(group, date, weights.history_days)
+            .await
+        {
+            Ok(images) => images,
+            // A few missing days only make the weights slightly less accurate
+            Err(ImageDynamoDaoError::PartialImages {
+                images,
+                unprocessed_count,
+            }) => {
+                warn!(
+                    unprocessed_count = unprocessed_count,
+                    "Some days of history couldn't be read. Weighting with the ones that were"
+                );
+                images
+            }
+            Err(err) => return Err(format!("Failed to read the image history: {:?}", err)),
+        };
+
+        let dates_as_strings = images
+            .iter()
+            .map(|image| image.date.format("%Y-%m-%d").to_string())
+            .collect::<Vec<String>>();
+
+        let counts_by_date = user_reaction_dao
+            .batch_get_counts(group, &dates_as_strings)
+            .await
+            .map_err(|err| format!("Failed to read the reaction counts: {:?}", err))?;
+
+        // Favorites are only picked from the recap shown alongside a get recents image
+        let recap_dates_as_strings = images
+            .iter()
+            .zip(&dates_as_strings)
+            .filter(|(image, _)| image.get_recents)
+            .map(|(_, date_as_string)| date_as_string.to_owned())
+            .collect::<Vec<String>>();
+
+        let favorite_counts_by_date = user_reaction_dao
+            .batch_get_favorite_counts(group, &recap_dates_as_strings)
+            .await
+            .map_err(|err| format!("Failed to read the favorite counts: {:?}", err))?;
+
+        let mut history: HashMap<String, ImageHistory> = HashMap::new();
+        for (image, date_as_string) in images.iter().zip(&dates_as_strings) {
+            let image_history = history.entry(image.object_key.to_owned()).or_default();
+            image_history.days_shown += 1;
+
+            for (reaction, count) in counts_by_date.get(date_as_string).into_iter().flatten() {
+                *image_history
+                    .reactions
+                    .entry(reaction.to_owned())
+                    .or_default() += count.parse::<i64>().unwrap_or(0);
+            }
+        }
+
+        for favorite_counts in favorite_counts_by_date.into_values() {
+            for (object_key, count) in favorite_counts {
+                history.entry(object_key).or_default().favorites +=
+                    count.parse::<i64>().unwrap_or(0).max(0);
+            }
+        }
+
+        let shown_object_keys = image_dynamo_dao
+            .get_image_histories(group)
+            .await
+            .map_err(|err| format!("Failed to read the image histories: {:?}", err))?
+            .into_iter()
+            .filter(|image_history| image_history.first_shown < date)
+            .map(|image_history| image_history.object_key)
+            .collect::<HashSet<String>>();
+
+        info!(
+            images = history.len(),
+            days = images.len(),
+            shown_images = shown_object_keys.len(),
+            "Built the weighted selection strategy"
+        );
+
+        Ok(WeightedStrategy {
+            weights,
+            history,
+            shown_object_keys,
+        })
+    }
+}
+
+impl SelectionStrategy for WeightedStrategy {
+    fn weight(&self, object_key: &str) -> f64 {
+        let history = match self.history.get(object_key) {
+            Some(history) => history,
+            // Shown before the history window, so there is nothing to weight it by
+            None if self.shown_object_keys.contains(object_key) => return 1.0,
+            None => return self.weights.never_shown.max(MIN_WEIGHT),
+        };
+
+        // Reactions are averaged so an image isn't favored just for having been shown more often
+        let reaction_weight = if history.days_shown > 0 {
+            self.weights
+                .reactions
+                .iter()
+                .map(|(reaction, weight)| {
+                    weight * history.reactions.get(reaction).copied().unwrap_or(0) as f64
+                })
+                .sum::<f64>()
+                / history.days_shown as f64
+        } else {
+            0.0
+        };
+
+        let favorite_weight = self.weights.favorite * history.favorites as f64;
+
+        (1.0 + reaction_weight + favorite_weight).max(MIN_WEIGHT)
+    }
+}
+
+///
+/// Builds the strategy configured for the group. Falls back to picking uniformly if the history needed
+/// for the weighted strategy can't be read, since a less interesting pick beats no pick.
+///
+pub async fn build_selection_strategy<D: DynamoDbUtil>(
+    group: &str,
+    date: NaiveDate,
+    settings: &GroupSettings,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+) -> Box<dyn SelectionStrategy> {
+    match settings.selection_strategy {
+        SelectionStrategyKind::Uniform => Box::new(UniformStrategy),
+        SelectionStrategyKind::Weighted => match WeightedStrategy::build(
+            group,
+            date,
+            settings.selection_weights.clone(),
+            image_dynamo_dao,
+            user_reaction_dao,
+        )
+        .await
+        {
+            Ok(strategy) => Box::new(strategy),
+            Err(err) => {
+                error!(
+                    error = err,
+                    "Failed to build the weighted selection strategy. Picking uniformly"
+                );
+                Box::new(UniformStrategy)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_utils::aws_sdk::in_memory_dynamodb::InMemoryDynamoDb;
+
+    const TABLE: &str = "ImageTable";
+    const GROUP: &str = "discord";
+
+    fn build_strategy(
+        weights: SelectionWeights,
+        history: Vec<(&str, ImageHistory)>,
+        shown_object_keys: &[&str],
+    ) -> WeightedStrategy {
+        WeightedStrategy {
+            weights,
+            history: history
+                .into_iter()
+                .map(|(object_key, image_history)| (object_key.to_owned(), image_history))
+                .collect(),
+            shown_object_keys: shown_object_keys
+                .iter()
+                .map(|object_key| object_key.to_string())
+                .collect(),
+        }
+    }
+
+    async fn build_weighted_strategy(table_name: &str) -> Box<dyn SelectionStrategy> {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let image_dynamo_dao = ImageDynamoDao {
+            table_name,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client: &dynamodb_client,
+        };
+        let user_reaction_dao = UserReactionDao {
+            table_name,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client: &dynamodb_client,
+        };
+        let settings = GroupSettings {
+            selection_strategy: SelectionStrategyKind::Weighted,
+            ..Default::default()
+        };
+
+        build_selection_strategy(
+            GROUP,
+            NaiveDate::from_ymd_opt(2024, 1, 10).unwrap(),
+            &settings,
+            &image_dynamo_dao,
+            &user_reaction_dao,
+        )
+        .await
+    }
+
+    #[test]
+    fn reactions_are_averaged_over_the_days_shown_and_favorites_added() {
+        let strategy = build_strategy(
+            SelectionWeights::default(),
+            vec![(
+                "discord/1.png",
+                ImageHistory {
+                    days_shown: 2,
+                    reactions: HashMap::from([
+                        ("Love".to_owned(), 3),
+                        ("Funny".to_owned(), 1),
+                        ("Wow".to_owned(), 5),
+                    ]),
+                    favorites: 1,
+                },
+            )],
+            &["discord/1.png"],
+        );
+
+        // 1 + (3 Love + 1 Funny) / 2 days + 2 per favorite. Wow isn't weighted by default
+        assert_eq!(strategy.weight("discord/1.png"), 5.0);
+    }
+
+    #[test]
+    fn never_shown_images_get_the_never_shown_weight() {
+        let strategy = build_strategy(SelectionWeights::default(), Vec::new(), &[]);
+
+        assert_eq!(strategy.weight("discord/1.png"), 3.0);
+    }
+
+    #[test]
+    fn images_shown_before_the_history_window_get_the_base_weight() {
+        let strategy = build_strategy(SelectionWeights::default(), Vec::new(), &["discord/1.png"]);
+
+        assert_eq!(strategy.weight("discord/1.png"), 1.0);
+    }
+
+    #[test]
+    fn weights_are_floored_at_the_min_weight() {
+        let weights = SelectionWeights {
+            never_shown: 0.0,
+            ..Default::default()
+        };
+        let strategy = build_strategy(weights, Vec::new(), &[]);
+
+        assert_eq!(strategy.weight("discord/1.png"), MIN_WEIGHT);
+    }
+
+    #[tokio::test]
+    async fn weighted_strategy_is_built_from_the_history() {
+        let strategy = build_weighted_strategy(TABLE).await;
+
+        assert_eq!(strategy.weight("discord/1.png"), 3.0);
+    }
+
+    #[tokio::test]
+    async fn unreadable_history_falls_back_to_uniform() {
+        let strategy = build_weighted_strategy("MissingTable").await;
+
+        assert_eq!(strategy.weight("discord/1.png"), 1.0);
+    }
+}
@@ -0,0 +1,538 @@
+use std::collections::HashMap;
+
+use aws_lambda_events::event::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpResponse};
+use chrono::{Duration, NaiveDate, ParseError};
+use daily_setup_lambda::daily_setup::{
+    setup_group_date, EnvironmentVariables as DailySetupEnvironmentVariables,
+};
+use lambda_utils::aws_sdk::api_gateway::{resolve_group, ApiError};
+use lambda_utils::aws_sdk::aws_dynamodb::DynamoDbUtil;
+use lambda_utils::aws_sdk::aws_s3::S3Util;
+use lambda_utils::aws_sdk::router::{Request, Router};
+use lambda_utils::models::{Reactions, SstTable};
+use lambda_utils::persistence::group_dao::{GroupDao, GroupSettings};
+use lambda_utils::persistence::image_dynamo_dao::{Image, ImageDynamoDao, ImageDynamoDaoError};
+use lambda_utils::persistence::user_reaction_dao::{UserReactionDao, UserReactionDaoError};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use sst_sdk::Resource;
+use tracing::instrument;
+use tracing::log::{error, info, warn};
+
+#[derive(Serialize, Default)]
+struct ResponseBody {
+    url: String,
+    days_until_get_recents: i64,
+    weekly_recap: Option<WeeklyRecap>,
+}
+
+// The images since the last recap, shown alongside a get recents image
+#[derive(Serialize, Debug)]
+struct WeeklyRecap {
+    // Ordered from the oldest date forwards
+    images: Vec<RecapImage>,
+    // The image most people picked as their favorite. None if nobody has picked one yet
+    image_of_the_week: Option<RecapImage>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct RecapImage {
+    date: String,
+    url: String,
+    counts: HashMap<String, String>,
+    favorites: i64,
+}
+
+#[instrument(skip_all)]
+pub async fn handler<D: DynamoDbUtil, S: S3Util>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    s3_client: &S,
+    req: ApiGatewayV2httpRequest,
+) -> Result<ApiGatewayV2httpResponse, lambda_runtime::Error> {
+    info!("handling a request: {:?}", req);
+
+    let image_dao = ImageDynamoDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let user_reaction_dao = UserReactionDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let group = match resolve_group(
+        &req,
+        environment_variables.default_group.as_deref(),
+        &group_dao,
+    )
+    .await
+    {
+        Ok(group) => group,
+        Err(api_gateway_response) => return Ok(api_gateway_response),
+    };
+
+    let router = Router::new()
+        .get("/todays-image", |request: Request<IgnoredAny, GetQuery>| {
+            handle_get(
+                request.query,
+                environment_variables,
+                s3_client,
+                &image_dao,
+                &user_reaction_dao,
+                &group_dao,
+                &group,
+            )
+        })
+        .get("/history", |request: Request<IgnoredAny, HistoryQuery>| {
+            handle_get_history(
+                request.query,
+                environment_variables,
+                &image_dao,
+                &user_reaction_dao,
+                &group_dao,
+                &group,
+            )
+        });
+
+    Ok(router.handle(req).await)
+}
+
+// Query parameters of the GET
+#[derive(Deserialize, Debug)]
+struct GetQuery {
+    // Formatted as YYYY-MM-DD. Today if it isn't provided
+    date: Option<String>,
+}
+
+// Error enum for GET
+#[derive(Debug)]
+pub enum GetHandlerError {
+    DateParseError(ParseError),
+    ImageDynamoDaoError(ImageDynamoDaoError),
+    InvalidRequest(String),
+    ImageNotFound(NaiveDate),
+}
+
+impl From<ParseError> for GetHandlerError {
+    fn from(err: ParseError) -> Self {
+        Self::DateParseError(err)
+    }
+}
+
+impl From<ImageDynamoDaoError> for GetHandlerError {
+    fn from(err: ImageDynamoDaoError) -> Self {
+        Self::ImageDynamoDaoError(err)
+    }
+}
+
+impl From<GetHandlerError> for ApiError {
+    fn from(err: GetHandlerError) -> Self {
+        match err {
+            GetHandlerError::DateParseError(err) => ApiError::BadRequest(format!(
+                "The date should be formatted as YYYY-MM-DD: {}",
+                err
+            )),
+            GetHandlerError::InvalidRequest(message) => ApiError::BadRequest(message),
+            GetHandlerError::ImageNotFound(date) => {
+                ApiError::NotFound(format!("There is no image for {}", date))
+            }
+            err => ApiError::internal(err),
+        }
+    }
+}
+
+async fn handle_get<D: DynamoDbUtil, S: S3Util>(
+    query: GetQuery,
+    environment_variables: &EnvironmentVariables,
+    s3_client: &S,
+    image_dao: &ImageDynamoDao<'_, D>,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+    group_dao: &GroupDao<'_, D>,
+    group: &str,
+) -> Result<ResponseBody, GetHandlerError> {
+    let settings = group_dao.get_settings(group).await.unwrap_or_else(|err| {
+        error!(
+            "Failed to read the group's settings. Using the defaults: {:?}",
+            err
+        );
+        GroupSettings::default()
+    });
+
+    let today = settings.group_day().today();
+
+    info!("Today is {:?}", today);
+
+    let date = match query.date {
+        Some(date) => NaiveDate::parse_from_str(&date, "%Y-%m-%d")?,
+        None => today,
+    };
+
+    // Upcoming images are queued ahead of time but shouldn't be seen before their day
+    if date > today {
+        return Err(GetHandlerError::InvalidRequest(format!(
+            "Only dates up to {} can be requested",
+            today
+        )));
+    }
+
+    let image = if date == today {
+        get_or_setup_image(environment_variables, s3_client, image_dao, group, today)
+            .await
+            .map_err(|err| {
+                error!(
+                    "Object is not already set for today {} for reason {:?}",
+                    today, err
+                );
+                err
+            })?
+    } else {
+        // Past days are never set up after the fact
+        image_dao
+            .get_image(group, date)
+            .await?
+            .ok_or(GetHandlerError::ImageNotFound(date))?
+    };
+
+    info!("The currently set image object is: {:?}", image);
+
+    // Fetch weekly recap images if necessary
+    let weekly_recap = if image.get_recents {
+        build_weekly_recap(
+            environment_variables,
+            image_dao,
+            user_reaction_dao,
+            group,
+            date,
+            settings.recap_period_days,
+        )
+        .await
+        .map_err(|err| error!("Failed to build the weekly recap for reason {:?}", err))
+        .ok()
+    } else {
+        None
+    };
+
+    Ok(ResponseBody {
+        url: format_image_url(&environment_variables.image_domain, &image.object_key),
+        days_until_get_recents: image.days_until_get_recents,
+        weekly_recap,
+    })
+}
+
+// Query parameters of the history GET
+#[derive(Deserialize, Debug)]
+struct HistoryQuery {
+    // The next_cursor of the previous page. The first page starts with today
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+// Body of the response for the history GET
+#[derive(Serialize, Debug)]
+struct HistoryResponseBody {
+    images: Vec<HistoryImage>,
+    // None once there are no older images
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct HistoryImage {
+    date: String,
+    url: String,
+    counts: HashMap<String, String>,
+    // None if nobody reacted to the image
+    top_reaction: Option<String>,
+}
+
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+const MAX_HISTORY_LIMIT: usize = 100;
+
+// Error enum for the history GET
+#[derive(Debug)]
+pub enum HistoryHandlerError {
+    DateParseError(ParseError),
+    ImageDynamoDaoError(ImageDynamoDaoError),
+    UserReactionDaoError(UserReactionDaoError),
+    InvalidRequest(String),
+}
+
+impl From<ParseError> for HistoryHandlerError {
+    fn from(err: ParseError) -> Self {
+        Self::DateParseError(err)
+    }
+}
+
+impl From<ImageDynamoDaoError> for HistoryHandlerError {
+    fn from(err: ImageDynamoDaoError) -> Self {
+        Self::ImageDynamoDaoError(err)
+    }
+}
+
+impl From<UserReactionDaoError> for HistoryHandlerError {
+    fn from(err: UserReactionDaoError) -> Self {
+        Self::UserReactionDaoError(err)
+    }
+}
+
+impl From<HistoryHandlerError> for ApiError {
+    fn from(err: HistoryHandlerError) -> Self {
+        match err {
+            HistoryHandlerError::DateParseError(err) => {
+                ApiError::BadRequest(format!("The cursor is invalid: {}", err))
+            }
+            HistoryHandlerError::InvalidRequest(message) => ApiError::BadRequest(message),
+            err => ApiError::internal(err),
+        }
+    }
+}
+
+async fn handle_get_history<D: DynamoDbUtil>(
+    query: HistoryQuery,
+    environment_variables: &EnvironmentVariables,
+    image_dao: &ImageDynamoDao<'_, D>,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+    group_dao: &GroupDao<'_, D>,
+    group: &str,
+) -> Result<HistoryResponseBody, HistoryHandlerError> {
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    if limit == 0 || limit > MAX_HISTORY_LIMIT {
+        return Err(HistoryHandlerError::InvalidRequest(format!(
+            "The limit should be between 1 and {}",
+            MAX_HISTORY_LIMIT
+        )));
+    }
+
+    // Queued images are already set for upcoming dates so the history never goes past today
+    let tomorrow = group_dao.get_group_day(group).await.today() + Duration::days(1);
+    let before = match query.cursor {
+        Some(cursor) => NaiveDate::parse_from_str(&cursor, "%Y-%m-%d")?.min(tomorrow),
+        None => tomorrow,
+    };
+
+    let page = image_dao.get_image_page(group, before, limit).await?;
+
+    let dates_as_strings = page
+        .images
+        .iter()
+        .map(|image| image.date.format("%Y-%m-%d").to_string())
+        .collect::<Vec<String>>();
+    let mut counts_by_date = if dates_as_strings.is_empty() {
+        HashMap::new()
+    } else {
+        user_reaction_dao
+            .batch_get_counts(group, &dates_as_strings)
+            .await?
+    };
+
+    let images = page
+        .images
+        .iter()
+        .zip(dates_as_strings)
+        .map(|(image, date)| {
+            let counts = counts_by_date.remove(&date).unwrap_or_default();
+            HistoryImage {
+                top_reaction: top_reaction(&counts),
+                url: format_image_url(&environment_variables.image_domain, &image.object_key),
+                date,
+                counts,
+            }
+        })
+        .collect::<Vec<HistoryImage>>();
+
+    info!("Returning {} images of the history", images.len());
+
+    Ok(HistoryResponseBody {
+        images,
+        next_cursor: page
+            .next_cursor
+            .map(|cursor| cursor.format("%Y-%m-%d").to_string()),
+    })
+}
+
+///
+/// Gets how many times the image was reacted to, not counting users without a reaction.
+///
+fn total_reactions(counts: &HashMap<String, String>) -> i64 {
+    counts
+        .iter()
+        .filter(|(reaction, _)| **reaction != Reactions::NoReaction.to_string())
+        .filter_map(|(_, count)| count.parse::<i64>().ok())
+        .sum()
+}
+
+///
+/// Gets the active reaction with the highest count. Ties go to the reaction listed first.
+///
+fn top_reaction(counts: &HashMap<String, String>) -> Option<String> {
+    let mut top: Option<(String, i64)> = None;
+    for reaction in Reactions::active_reactions() {
+        if reaction == Reactions::NoReaction.to_string() {
+            continue;
+        }
+
+        let count = counts
+            .get(&reaction)
+            .and_then(|count| count.parse::<i64>().ok())
+            .unwrap_or_default();
+        if count > top.as_ref().map_or(0, |(_, top_count)| *top_count) {
+            top = Some((reaction, count));
+        }
+    }
+
+    top.map(|(reaction, _)| reaction)
+}
+
+///
+/// Builds the recap shown on the date from the images since the previous recap. Favorites are picked
+/// from the recap on the day it is shown, so they are all counted in that date's "FavoriteCounts" record.
+///
+async fn build_weekly_recap<D: DynamoDbUtil>(
+    environment_variables: &EnvironmentVariables,
+    image_dao: &ImageDynamoDao<'_, D>,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+    group: &str,
+    date: NaiveDate,
+    recap_period_days: i64,
+) -> Result<WeeklyRecap, String> {
+    let mut recent_images = image_dao
+        .get_recents(group, date, recap_period_days)
+        .await
+        .map_err(|err| format!("Failed to read the recent images: {:?}", err))?;
+    recent_images.sort_by_key(|image| image.date);
+
+    let dates_as_strings = recent_images
+        .iter()
+        .map(|image| image.date.format("%Y-%m-%d").to_string())
+        .collect::<Vec<String>>();
+    let mut counts_by_date = if dates_as_strings.is_empty() {
+        HashMap::new()
+    } else {
+        user_reaction_dao
+            .batch_get_counts(group, &dates_as_strings)
+            .await
+            .map_err(|err| format!("Failed to read the reaction counts: {:?}", err))?
+    };
+
+    let favorite_counts = user_reaction_dao
+        .get_favorite_counts(group, &date.format("%Y-%m-%d").to_string())
+        .await
+        .map_err(|err| format!("Failed to read the favorite counts: {:?}", err))?;
+
+    let images = recent_images
+        .iter()
+        .zip(dates_as_strings)
+        .map(|(image, date)| RecapImage {
+            counts: counts_by_date.remove(&date).unwrap_or_default(),
+            favorites: favorite_counts
+                .get(&image.object_key)
+                .and_then(|count| count.parse::<i64>().ok())
+                .unwrap_or_default(),
+            url: format_image_url(&environment_variables.image_domain, &image.object_key),
+            date,
+        })
+        .collect::<Vec<RecapImage>>();
+
+    // Ties go to the image with the most reactions and then to the most recent one
+    let image_of_the_week = images
+        .iter()
+        .filter(|image| image.favorites > 0)
+        .max_by_key(|image| (image.favorites, total_reactions(&image.counts), &image.date))
+        .cloned();
+
+    info!(
+        "Built a recap of {} images. The image of the week is {:?}",
+        images.len(),
+        image_of_the_week
+    );
+
+    Ok(WeeklyRecap {
+        images,
+        image_of_the_week,
+    })
+}
+
+///
+/// Gets the image set for the date. If the daily setup missed the date the image is picked and set the
+/// same way the daily setup would, and whichever image ends up set is returned so concurrent requests
+/// all get the same one.
+///
+async fn get_or_setup_image<D: DynamoDbUtil, S: S3Util>(
+    environment_variables: &EnvironmentVariables,
+    s3_client: &S,
+    image_dao: &ImageDynamoDao<'_, D>,
+    group: &str,
+    date: NaiveDate,
+) -> Result<Image, ImageDynamoDaoError> {
+    if let Some(image) = image_dao.get_image(group, date).await? {
+        return Ok(image);
+    }
+
+    warn!(
+        "No image is set for {} in group {}. Setting one up now",
+        date, group
+    );
+
+    setup_group_date(
+        &environment_variables.daily_setup_environment_variables,
+        image_dao.dynamodb_client,
+        s3_client,
+        group,
+        date,
+    )
+    .await?;
+
+    image_dao
+        .get_image(group, date)
+        .await?
+        .ok_or_else(|| format!("No image is set for {} even after setting one up", date).into())
+}
+
+fn format_image_url(domain: &str, object_key: &str) -> String {
+    format!("https://{}/{}", domain, object_key)
+}
+
+pub struct EnvironmentVariables {
+    pub image_domain: String,
+    pub default_group: Option<String>,
+    pub table_name: String,
+    pub table_primary_key: String,
+    pub table_sort_key: String,
+    // Used to set up the image when the daily setup missed today
+    pub daily_setup_environment_variables: DailySetupEnvironmentVariables,
+}
+
+impl EnvironmentVariables {
+    pub fn build() -> EnvironmentVariables {
+        let image_domain = std::env::var("IMAGE_DOMAIN")
+            .expect("A IMAGE_DOMAIN must be set in this app's Lambda environment variables.");
+        let default_group = std::env::var("DEFAULT_GROUP").ok();
+
+        let resource = Resource::init().expect("Should be able to initialize SST resource object");
+
+        let table: SstTable = resource
+            .get("ImageTable")
+            .expect("Should have an ImageTable resource");
+
+        EnvironmentVariables {
+            image_domain,
+            default_group,
+            table_name: table.name,
+            table_primary_key: table.primary_key,
+            table_sort_key: table.sort_key,
+            daily_setup_environment_variables: DailySetupEnvironmentVariables::build(),
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, ParseError};
+
+use lambda_utils::{
+    aws_sdk::{
+        api_gateway::{resolve_group, ApiError},
+        aws_dynamodb::{DynamoDbUtil, DynamoDbUtilError},
+        router::{Request, Router},
+    },
+    models::{ReactionError, Reactions, SstTable},
+    persistence::{
+        group_dao::GroupDao,
+        user_reaction_dao::{UserReactionDao, UserReactionDaoError},
+    },
+};
+use log::info;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use aws_lambda_events::event::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpResponse};
+use sst_sdk::Resource;
+use uuid::Uuid;
+
+pub async fn handler<D: DynamoDbUtil>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    req: ApiGatewayV2httpRequest,
+) -> Result<ApiGatewayV2httpResponse, lambda_runtime::Error> {
+    info!("handling a request: {:?}", req);
+
+    let user_reaction_dao = UserReactionDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let group = match resolve_group(
+        &req,
+        environment_variables.default_group.as_deref(),
+        &group_dao,
+    )
+    .await
+    {
+        Ok(group) => group,
+        Err(api_gateway_response) => return Ok(api_gateway_response),
+    };
+
+    let today = group_dao.get_group_day(&group).await.today();
+    let today_as_string = today.format("%Y-%m-%d").to_string();
+
+    info!("Today is {}", today_as_string);
+
+    // TODO: Break up these get and put functions into their own library files like is done for get-image-lambda
+    //  Also just consider making one get_metadata_lambda and then a seperate set_reaction_lambda
+    //  I think with the current direction of the API that makes more sense
+
+    let router = Router::new()
+        .get(
+            "/todays-metadata",
+            |request: Request<IgnoredAny, GetQuery>| {
+                handler_get(request.query, &group, today, &user_reaction_dao)
+            },
+        )
+        .put("/todays-metadata", |request: Request<RequestBody>| {
+            handler_put(request.body, &group, &today_as_string, &user_reaction_dao)
+        });
+
+    Ok(router.handle(req).await)
+}
+
+// Query parameters of the GET
+#[derive(Serialize, Deserialize, Debug)]
+struct GetQuery {
+    uuid: Option<String>,
+    // Formatted as YYYY-MM-DD. Today if it isn't provided
+    date: Option<String>,
+}
+
+// Body of the response for both GET
+#[derive(Serialize, Deserialize, Debug)]
+struct GetResponseBody {
+    uuid: String,
+    reaction: String,
+    favorite_image: String,
+    counts: HashMap<String, String>,
+    // How many users picked each image as their favorite, keyed by the image's key
+    favorite_counts: HashMap<String, String>,
+}
+
+// Error enum for GET
+#[derive(Debug)]
+pub enum GetHandlerError {
+    DateParseError(ParseError),
+    InvalidRequest(String),
+    UserReactionDaoError(UserReactionDaoError),
+    LocalError(String),
+}
+
+impl From<UserReactionDaoError> for GetHandlerError {
+    fn from(err: UserReactionDaoError) -> Self {
+        Self::UserReactionDaoError(err)
+    }
+}
+
+impl From<ParseError> for GetHandlerError {
+    fn from(err: ParseError) -> Self {
+        Self::DateParseError(err)
+    }
+}
+
+impl From<String> for GetHandlerError {
+    fn from(err: String) -> Self {
+        Self::LocalError(err)
+    }
+}
+
+impl From<GetHandlerError> for ApiError {
+    fn from(err: GetHandlerError) -> Self {
+        match err {
+            GetHandlerError::DateParseError(err) => ApiError::BadRequest(format!(
+                "The date should be formatted as YYYY-MM-DD: {}",
+                err
+            )),
+            GetHandlerError::InvalidRequest(message) => ApiError::BadRequest(message),
+            err => ApiError::internal(err),
+        }
+    }
+}
+
+async fn handler_get<D: DynamoDbUtil>(
+    query: GetQuery,
+    group: &str,
+    today: NaiveDate,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+) -> Result<GetResponseBody, GetHandlerError> {
+    let curr_uuid = query.uuid.unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let date = match query.date {
+        Some(date) => NaiveDate::parse_from_str(&date, "%Y-%m-%d")?,
+        None => today,
+    };
+
+    // Nobody can react to an upcoming day yet
+    if date > today {
+        return Err(GetHandlerError::InvalidRequest(format!(
+            "Only dates up to {} can be requested",
+            today
+        )));
+    }
+
+    let date_as_string = date.format("%Y-%m-%d").to_string();
+
+    // Get the current user items
+    let user_items = user_reaction_dao
+        .get(group, &date_as_string, &curr_uuid)
+        .await?;
+
+    // Get the current state of all reaction counts
+    let numeric_counts = match user_reaction_dao.get_counts(group, &date_as_string).await {
+        Ok(numeric_counts) => numeric_counts,
+        // Nobody could react on a day whose counts were never set up
+        Err(UserReactionDaoError::DynamoDbError(DynamoDbUtilError::NotFound(_))) => HashMap::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let favorite_counts = user_reaction_dao
+        .get_favorite_counts(group, &date_as_string)
+        .await?;
+
+    Ok(GetResponseBody {
+        uuid: curr_uuid,
+        reaction: user_items.reaction,
+        favorite_image: user_items.favorite_image,
+        counts: numeric_counts,
+        favorite_counts,
+    })
+}
+
+// Body of the response for the PUT
+#[derive(Serialize, Deserialize, Debug)]
+struct PutResponseBody {
+    uuid: String,
+    reaction: String,
+    counts: HashMap<String, String>,
+}
+
+// Body of the request to be recevied
+#[derive(Serialize, Deserialize, Debug)]
+struct RequestBody {
+    uuid: String,
+    reaction: String,
+}
+
+// Error enum for PUT
+#[derive(Debug)]
+pub enum PutHandlerError {
+    ReactionError(ReactionError),
+    UserReactionDaoError(UserReactionDaoError),
+    LocalError(String),
+}
+
+impl From<ReactionError> for PutHandlerError {
+    fn from(err: ReactionError) -> Self {
+        Self::ReactionError(err)
+    }
+}
+
+impl From<UserReactionDaoError> for PutHandlerError {
+    fn from(err: UserReactionDaoError) -> Self {
+        Self::UserReactionDaoError(err)
+    }
+}
+
+impl From<String> for PutHandlerError {
+    fn from(err: String) -> Self {
+        Self::LocalError(err)
+    }
+}
+
+impl From<PutHandlerError> for ApiError {
+    fn from(err: PutHandlerError) -> Self {
+        match err {
+            PutHandlerError::ReactionError(_)
+            | PutHandlerError::UserReactionDaoError(UserReactionDaoError::DeprecatedReaction(_)) => {
+                ApiError::BadRequest(format!(
+                    "The reaction should be one of: {}",
+                    Reactions::active_reactions().join(", ")
+                ))
+            }
+            // Another request kept updating the user's reaction at the same time
+            PutHandlerError::UserReactionDaoError(UserReactionDaoError::DynamoDbError(
+                DynamoDbUtilError::ConditionalCheckFailure(_),
+            )) => ApiError::Conflict(
+                "The reaction was changed by another request, try again".to_owned(),
+            ),
+            err => ApiError::internal(err),
+        }
+    }
+}
+
+async fn handler_put<D: DynamoDbUtil>(
+    body: RequestBody,
+    group: &str,
+    today_as_string: &str,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+) -> Result<PutResponseBody, PutHandlerError> {
+    info!("body: {:?}", body);
+
+    let uuid = &body.uuid;
+    let reaction = Reactions::get_reaction(&body.reaction)?;
+
+    // Deprecated reactions are still stored on old days but can't be picked anymore
+    if !reaction.is_active() {
+        return Err(UserReactionDaoError::DeprecatedReaction(reaction.to_string()).into());
+    }
+
+    // Set the reaction
+    let old_reaction = user_reaction_dao
+        .set_reaction(group, today_as_string, uuid, &reaction)
+        .await?;
+
+    info!(
+        "Request to update reaction completed. The old reaction was {}",
+        old_reaction
+    );
+
+    // The counts were updated along with the reaction so they only need to be fetched
+    let numeric_counts = user_reaction_dao.get_counts(group, today_as_string).await?;
+
+    info!("The counts are: {:?}", numeric_counts);
+
+    Ok(PutResponseBody {
+        reaction: reaction.to_string(),
+        uuid: uuid.to_owned(),
+        counts: numeric_counts,
+    })
+}
+
+/** Environment Variables */
+pub struct EnvironmentVariables {
+    pub default_group: Option<String>,
+    pub table_name: String,
+    pub table_primary_key: String,
+    pub table_sort_key: String,
+}
+
+impl EnvironmentVariables {
+    pub fn build() -> EnvironmentVariables {
+        let resource = Resource::init().expect("Should be able to initialize SST resource");
+        let table: SstTable = resource
+            .get("ImageTable")
+            .expect("Should be able t get ImageTable");
+
+        EnvironmentVariables {
+            default_group: std::env::var("DEFAULT_GROUP").ok(),
+            table_name: table.name,
+            table_primary_key: table.primary_key,
+            table_sort_key: table.sort_key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_utils::aws_sdk::in_memory_dynamodb::InMemoryDynamoDb;
+
+    use super::*;
+
+    const TABLE: &str = "ImageTable";
+    const GROUP: &str = "discord";
+    const DATE: &str = "2024-01-07";
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, 7).unwrap()
+    }
+
+    fn build_dao(dynamodb_client: &InMemoryDynamoDb) -> UserReactionDao<'_, InMemoryDynamoDb> {
+        UserReactionDao {
+            table_name: TABLE,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client,
+        }
+    }
+
+    fn body(reaction: &str) -> RequestBody {
+        RequestBody {
+            uuid: "user-1".to_owned(),
+            reaction: reaction.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn deprecated_reaction_is_a_bad_request() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let user_reaction_dao = build_dao(&dynamodb_client);
+
+        let err = handler_put(body("Eesh"), GROUP, DATE, &user_reaction_dao)
+            .await
+            .unwrap_err();
+        let user_items = user_reaction_dao.get(GROUP, DATE, "user-1").await.unwrap();
+
+        assert_eq!(ApiError::from(err).status_code(), 400);
+        assert_eq!(user_items.reaction, Reactions::NoReaction.to_string());
+    }
+
+    fn query(date: &str) -> GetQuery {
+        GetQuery {
+            uuid: Some("user-1".to_owned()),
+            date: Some(date.to_owned()),
+        }
+    }
+
+    #[tokio::test]
+    async fn day_without_counts_has_empty_counts() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let user_reaction_dao = build_dao(&dynamodb_client);
+
+        let response = handler_get(query(DATE), GROUP, today(), &user_reaction_dao)
+            .await
+            .unwrap();
+
+        assert!(response.counts.is_empty());
+        assert_eq!(response.reaction, Reactions::NoReaction.to_string());
+    }
+
+    #[tokio::test]
+    async fn failed_counts_read_is_an_internal_error() {
+        // Reading from a table that doesn't exist fails like DynamoDB being unavailable
+        let dynamodb_client = InMemoryDynamoDb::new();
+        let user_reaction_dao = build_dao(&dynamodb_client);
+
+        let err = handler_get(query(DATE), GROUP, today(), &user_reaction_dao)
+            .await
+            .unwrap_err();
+
+        assert_eq!(ApiError::from(err).status_code(), 500);
+    }
+
+    #[tokio::test]
+    async fn active_reaction_is_counted() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let user_reaction_dao = build_dao(&dynamodb_client);
+        user_reaction_dao.setup_counts(GROUP, DATE).await.unwrap();
+
+        let response = handler_put(body("Love"), GROUP, DATE, &user_reaction_dao)
+            .await
+            .unwrap();
+
+        assert_eq!(response.reaction, "Love");
+        assert_eq!(response.counts.get("Love"), Some(&"1".to_owned()));
+    }
+}
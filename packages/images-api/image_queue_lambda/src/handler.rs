@@ -0,0 +1,371 @@
+use aws_lambda_events::event::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpResponse};
+
+use chrono::NaiveDate;
+use lambda_utils::aws_sdk::api_gateway::{authorize_admin, resolve_group, ApiError};
+use lambda_utils::aws_sdk::aws_dynamodb::{DynamoDbUtil, DynamoDbUtilError};
+use lambda_utils::aws_sdk::router::{Request, Router};
+use lambda_utils::models::{SstSecret, SstTable};
+use lambda_utils::persistence::group_dao::GroupDao;
+use lambda_utils::persistence::image_dynamo_dao::{Image, ImageDynamoDao, ImageDynamoDaoError};
+use serde::{Deserialize, Serialize};
+use sst_sdk::Resource;
+use tracing::{info, instrument};
+
+#[instrument(skip_all)]
+pub async fn handler<D: DynamoDbUtil>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    req: ApiGatewayV2httpRequest,
+) -> Result<ApiGatewayV2httpResponse, lambda_runtime::Error> {
+    // The request isn't logged since it holds the admin token
+    info!(route_key = ?req.route_key, "The route passed into the lambda is");
+
+    let image_dynamo_dao = ImageDynamoDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    // The queue spoils the upcoming images so only admins can see it
+    if let Err(api_gateway_response) = authorize_admin(&req, &environment_variables.admin_token) {
+        return Ok(*api_gateway_response);
+    }
+
+    let group = match resolve_group(
+        &req,
+        environment_variables.default_group.as_deref(),
+        &group_dao,
+    )
+    .await
+    {
+        Ok(group) => group,
+        Err(api_gateway_response) => return Ok(api_gateway_response),
+    };
+
+    // Today's image may already have been seen so the queue starts tomorrow
+    let today = group_dao.get_group_day(&group).await.today();
+
+    let router = Router::new()
+        .get("/queue", |_: Request| {
+            handle_get(&group, today, &image_dynamo_dao)
+        })
+        .put("/queue", |request: Request<RequestBody>| {
+            handle_put(request.body, &group, today, &image_dynamo_dao)
+        });
+
+    Ok(router.handle(req).await)
+}
+
+// Body of the request to be recevied for PUT
+#[derive(Serialize, Deserialize, Debug)]
+struct RequestBody {
+    // Every queued image's key in the order they should be shown
+    object_keys: Vec<String>,
+}
+
+// Body of the response for both GET and PUT
+#[derive(Serialize, Deserialize, Debug)]
+struct ResponseBody {
+    queue: Vec<QueuedImage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct QueuedImage {
+    date: String,
+    object_key: String,
+    pinned: bool,
+}
+
+impl From<Vec<Image>> for ResponseBody {
+    fn from(queue: Vec<Image>) -> Self {
+        ResponseBody {
+            queue: queue.into_iter().map(QueuedImage::from).collect(),
+        }
+    }
+}
+
+impl From<Image> for QueuedImage {
+    fn from(image: Image) -> Self {
+        QueuedImage {
+            date: image.date.format("%Y-%m-%d").to_string(),
+            object_key: image.object_key,
+            pinned: image.pinned,
+        }
+    }
+}
+
+// Error enum for both GET and PUT
+#[derive(Debug)]
+pub enum QueueHandlerError {
+    ImageDynamoDaoError(ImageDynamoDaoError),
+    InvalidOrder(String),
+    LocalError(String),
+}
+
+impl From<ImageDynamoDaoError> for QueueHandlerError {
+    fn from(err: ImageDynamoDaoError) -> Self {
+        Self::ImageDynamoDaoError(err)
+    }
+}
+
+impl From<String> for QueueHandlerError {
+    fn from(err: String) -> Self {
+        Self::LocalError(err)
+    }
+}
+
+impl From<QueueHandlerError> for ApiError {
+    fn from(err: QueueHandlerError) -> Self {
+        match err {
+            QueueHandlerError::InvalidOrder(message) => ApiError::BadRequest(message),
+            // The queue changed while it was being reordered
+            QueueHandlerError::ImageDynamoDaoError(ImageDynamoDaoError::DynamoDbError(
+                DynamoDbUtilError::ConditionalCheckFailure(_),
+            )) => ApiError::Conflict(
+                "The queue was changed by another request, fetch it and try again".to_owned(),
+            ),
+            err => ApiError::internal(err),
+        }
+    }
+}
+
+async fn handle_get<D: DynamoDbUtil>(
+    group: &str,
+    today: NaiveDate,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+) -> Result<ResponseBody, QueueHandlerError> {
+    let queue = image_dynamo_dao.get_upcoming_images(group, today).await?;
+
+    Ok(ResponseBody::from(queue))
+}
+
+///
+/// Reorders the queue. The dates stay where they are and the images are shuffled between them, so the
+/// new order has to hold exactly the images already queued. Pinned images have to stay on their date.
+///
+async fn handle_put<D: DynamoDbUtil>(
+    body: RequestBody,
+    group: &str,
+    today: NaiveDate,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+) -> Result<ResponseBody, QueueHandlerError> {
+    info!(body = ?body, "The parsed body value");
+
+    let queue = image_dynamo_dao.get_upcoming_images(group, today).await?;
+
+    let mut queued_object_keys = queue
+        .iter()
+        .map(|image| image.object_key.as_str())
+        .collect::<Vec<&str>>();
+    let mut reordered_object_keys = body
+        .object_keys
+        .iter()
+        .map(|object_key| object_key.as_str())
+        .collect::<Vec<&str>>();
+    queued_object_keys.sort_unstable();
+    reordered_object_keys.sort_unstable();
+    if queued_object_keys != reordered_object_keys {
+        return Err(QueueHandlerError::InvalidOrder(format!(
+            "The new order should hold exactly the queued images: {:?}",
+            queue
+                .iter()
+                .map(|image| image.object_key.as_str())
+                .collect::<Vec<&str>>()
+        )));
+    }
+
+    if let Some(moved_pin) = queue
+        .iter()
+        .zip(&body.object_keys)
+        .find(|(image, object_key)| image.pinned && image.object_key != **object_key)
+        .map(|(image, _)| image)
+    {
+        return Err(QueueHandlerError::InvalidOrder(format!(
+            "The image {} is pinned to {} and can't be moved",
+            moved_pin.object_key, moved_pin.date
+        )));
+    }
+
+    // Every move is written together so a conflict never leaves the queue half reordered
+    image_dynamo_dao
+        .reorder_images(group, &queue, &body.object_keys)
+        .await?;
+
+    info!("Request to reorder the queue complete");
+
+    let queue = image_dynamo_dao.get_upcoming_images(group, today).await?;
+
+    Ok(ResponseBody::from(queue))
+}
+
+pub struct EnvironmentVariables {
+    pub default_group: Option<String>,
+    pub admin_token: String,
+    pub table_name: String,
+    pub table_primary_key: String,
+    pub table_sort_key: String,
+}
+
+impl EnvironmentVariables {
+    pub fn build() -> EnvironmentVariables {
+        let resource = Resource::init().expect("Should be able to initialize SST resource");
+        let table: SstTable = resource
+            .get("ImageTable")
+            .expect("Should be able to get ImageTable");
+        let admin_token: SstSecret = resource
+            .get("AdminApiToken")
+            .expect("Should have an AdminApiToken resource");
+
+        EnvironmentVariables {
+            default_group: std::env::var("DEFAULT_GROUP").ok(),
+            admin_token: admin_token.value,
+            table_name: table.name,
+            table_primary_key: table.primary_key,
+            table_sort_key: table.sort_key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+    use aws_sdk_s3::types::Object;
+    use lambda_utils::aws_sdk::aws_dynamodb::KeyAndAttribute;
+    use lambda_utils::aws_sdk::in_memory_dynamodb::InMemoryDynamoDb;
+
+    use super::*;
+
+    const TABLE: &str = "ImageTable";
+    const GROUP: &str = "discord";
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    async fn build_queue(dynamodb_client: &InMemoryDynamoDb) {
+        let image_dynamo_dao = ImageDynamoDao {
+            table_name: TABLE,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client,
+        };
+        for day in [2, 3] {
+            image_dynamo_dao
+                .set_image(
+                    GROUP,
+                    Object::builder()
+                        .key(format!("discord/{}.png", day))
+                        .build(),
+                    date(day),
+                    1,
+                    6,
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    fn queued_keys(response: &ResponseBody) -> Vec<&str> {
+        response
+            .queue
+            .iter()
+            .map(|image| image.object_key.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn put_reorders_the_queue() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        build_queue(&dynamodb_client).await;
+        let image_dynamo_dao = ImageDynamoDao {
+            table_name: TABLE,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client: &dynamodb_client,
+        };
+
+        let response = handle_put(
+            RequestBody {
+                object_keys: vec!["discord/3.png".to_owned(), "discord/2.png".to_owned()],
+            },
+            GROUP,
+            date(1),
+            &image_dynamo_dao,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            queued_keys(&response),
+            vec!["discord/3.png", "discord/2.png"]
+        );
+    }
+
+    #[tokio::test]
+    async fn pinned_image_cant_be_moved() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        build_queue(&dynamodb_client).await;
+        dynamodb_client
+            .update_item_with_keys(
+                TABLE,
+                vec![
+                    KeyAndAttribute {
+                        key: "pk",
+                        attribute: AttributeValue::S(format!("{}_2024-01-02", GROUP)),
+                    },
+                    KeyAndAttribute {
+                        key: "sk",
+                        attribute: AttributeValue::S("Image".to_owned()),
+                    },
+                ],
+                "SET pinned = :pinned".to_owned(),
+                ReturnValue::None,
+                None,
+                vec![KeyAndAttribute {
+                    key: ":pinned",
+                    attribute: AttributeValue::Bool(true),
+                }],
+            )
+            .await
+            .unwrap();
+        let image_dynamo_dao = ImageDynamoDao {
+            table_name: TABLE,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client: &dynamodb_client,
+        };
+
+        let err = handle_put(
+            RequestBody {
+                object_keys: vec!["discord/3.png".to_owned(), "discord/2.png".to_owned()],
+            },
+            GROUP,
+            date(1),
+            &image_dynamo_dao,
+        )
+        .await
+        .unwrap_err();
+        let queue = handle_get(GROUP, date(1), &image_dynamo_dao).await.unwrap();
+
+        assert_eq!(ApiError::from(err).status_code(), 400);
+        assert_eq!(queued_keys(&queue), vec!["discord/2.png", "discord/3.png"]);
+    }
+
+    #[test]
+    fn conflicting_reorder_is_a_conflict() {
+        let err = QueueHandlerError::ImageDynamoDaoError(ImageDynamoDaoError::DynamoDbError(
+            DynamoDbUtilError::ConditionalCheckFailure("The queue changed".to_owned()),
+        ));
+
+        assert_eq!(ApiError::from(err).status_code(), 409);
+    }
+}
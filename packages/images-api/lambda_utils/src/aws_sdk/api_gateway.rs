@@ -2,10 +2,16 @@ use aws_lambda_events::event::apigw::ApiGatewayV2httpRequest;
 use aws_lambda_events::{
     encodings::Body,
     event::apigw::{ApiGatewayProxyResponse, ApiGatewayV2httpResponse},
-    http::HeaderMap,
+    http::{HeaderMap, Method},
 };
 use base64::engine::general_purpose::STANDARD as BASE64;
 use base64::Engine as _;
+use serde::Serialize;
+use std::fmt::Debug;
+use tracing::{error, warn};
+
+use crate::aws_sdk::aws_dynamodb::DynamoDbUtil;
+use crate::persistence::group_dao::{GroupDao, GroupDaoError};
 
 /**
  * Struct used to create API Gateway response headers.
@@ -48,6 +54,121 @@ fn create_cross_origin_headers() -> HeaderMap {
     header_map
 }
 
+/**
+ * Error that can be returned from any of the API handlers. Each variant maps to a status code and is
+ * returned as a JSON body with a stable `code`, so clients can branch on it without parsing the message.
+ */
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request is malformed or failed validation (400)
+    BadRequest(String),
+    /// The request is missing valid credentials (401)
+    Unauthorized(String),
+    /// The request's credentials don't allow it (403)
+    Forbidden(String),
+    /// Something the request refers to doesn't exist (404)
+    NotFound(String),
+    /// The route doesn't handle the request's method (405)
+    MethodNotAllowed(Method),
+    /// The request lost a race with another write and can be retried (409)
+    Conflict(String),
+    /// Anything else. Holds the internal details, which are only logged and never returned (500)
+    Internal(String),
+}
+
+// Body of every error response
+#[derive(Serialize, Debug)]
+struct ErrorResponseBody {
+    code: &'static str,
+    message: String,
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    /**
+     * Creates an internal error from any error. Its Debug output is kept for the logs only.
+     */
+    pub fn internal<E: Debug>(err: E) -> Self {
+        Self::Internal(format!("{:?}", err))
+    }
+
+    pub fn status_code(&self) -> i64 {
+        match self {
+            Self::BadRequest(_) => 400,
+            Self::Unauthorized(_) => 401,
+            Self::Forbidden(_) => 403,
+            Self::NotFound(_) => 404,
+            Self::MethodNotAllowed(_) => 405,
+            Self::Conflict(_) => 409,
+            Self::Internal(_) => 500,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest(_) => "bad_request",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::NotFound(_) => "not_found",
+            Self::MethodNotAllowed(_) => "method_not_allowed",
+            Self::Conflict(_) => "conflict",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::BadRequest(message)
+            | Self::Unauthorized(message)
+            | Self::Forbidden(message)
+            | Self::NotFound(message)
+            | Self::Conflict(message) => message.to_owned(),
+            Self::MethodNotAllowed(method) => {
+                format!("The {} method is not supported by this route", method)
+            }
+            Self::Internal(_) => "Failed to process the request".to_owned(),
+        }
+    }
+
+    /**
+     * Builds the JSON error response.
+     *
+     * # Arguments
+     *
+     * * `request_id` - The API Gateway request id, returned so a failure can be matched to its logs
+     */
+    pub fn build_v2_response(self, request_id: Option<&str>) -> ApiGatewayV2httpResponse {
+        if let Self::Internal(details) = &self {
+            error!(
+                request_id = request_id,
+                details = details,
+                "Returning an internal error"
+            );
+        }
+
+        let response_body = ErrorResponseBody {
+            code: self.code(),
+            message: self.message(),
+            request_id: request_id.map(|request_id| request_id.to_owned()),
+        };
+
+        // The body only holds strings so serializing it can't fail
+        let body = serde_json::to_string(&response_body)
+            .expect("Should be able to serialize the error response body");
+
+        let mut response = ApiGatewayProxyResponseWithoutHeaders {
+            status_code: self.status_code(),
+            body: Body::Text(body),
+            is_base_64_encoded: false,
+        }
+        .build_v2_response();
+        response
+            .headers
+            .insert("Content-Type", "application/json".parse().unwrap());
+        response
+    }
+}
+
 /**
  * Extracts the body from an API Gateway V2 HTTP request, handling base64 decoding if necessary.
  *
@@ -79,3 +200,230 @@ pub fn extract_body_from_request(req: &ApiGatewayV2httpRequest) -> Result<String
         None => Err("Body does not exist".to_owned()),
     }
 }
+
+pub const GROUP_PATH_PARAMETER: &str = "group";
+pub const GROUP_CLAIM: &str = "group";
+
+/**
+ * Resolves the group a request is being made for. A `group` claim provided by a JWT or Lambda
+ * authorizer is authoritative, so a `{group}` path parameter naming a different group is rejected.
+ * Unauthenticated requests use the `{group}` path parameter, falling back to the provided default group.
+ *
+ * # Arguments
+ *
+ * * `req` - The API Gateway V2 HTTP request to resolve the group from
+ * * `default_group` - Group to fall back to when the request does not specify one
+ *
+ * # Returns
+ *
+ * A Result containing either the name of the group, or the error to return because no group could
+ * be resolved (400) or the path names a group other than the claim's (403). The group is not
+ * validated against the registry.
+ */
+pub fn extract_group_from_request(
+    req: &ApiGatewayV2httpRequest,
+    default_group: Option<&str>,
+) -> Result<String, ApiError> {
+    let path_group = req.path_parameters.get(GROUP_PATH_PARAMETER);
+
+    let claim_group = req
+        .request_context
+        .authorizer
+        .as_ref()
+        .and_then(|authorizer| {
+            let jwt_claim = authorizer
+                .jwt
+                .as_ref()
+                .and_then(|jwt| jwt.claims.get(GROUP_CLAIM))
+                .map(|group| group.to_owned());
+            let lambda_claim = authorizer
+                .lambda
+                .get(GROUP_CLAIM)
+                .and_then(|group| group.as_str())
+                .map(|group| group.to_owned());
+
+            jwt_claim.or(lambda_claim)
+        });
+
+    match (claim_group, path_group) {
+        (Some(claim_group), Some(path_group)) if claim_group != *path_group => {
+            Err(ApiError::Forbidden(format!(
+                "The request is not authorized for group {}",
+                path_group
+            )))
+        }
+        (Some(claim_group), _) => Ok(claim_group),
+        (None, Some(path_group)) => Ok(path_group.to_owned()),
+        (None, None) => default_group.map(|group| group.to_owned()).ok_or_else(|| {
+            ApiError::BadRequest("No group was provided with the request".to_owned())
+        }),
+    }
+}
+
+/**
+ * Resolves the group for a request and checks that it is registered.
+ *
+ * # Arguments
+ *
+ * * `req` - The API Gateway V2 HTTP request to resolve the group from
+ * * `default_group` - Group to fall back to when the request does not specify one
+ * * `group_dao` - DAO used to validate the group against the registry
+ *
+ * # Returns
+ *
+ * A Result containing either the name of the group, or the response that should be returned
+ * because the group was missing or invalid (400), not allowed by the request's claim (403),
+ * unknown (404) or could not be validated (500).
+ */
+pub async fn resolve_group<D: DynamoDbUtil>(
+    req: &ApiGatewayV2httpRequest,
+    default_group: Option<&str>,
+    group_dao: &GroupDao<'_, D>,
+) -> Result<String, ApiGatewayV2httpResponse> {
+    let request_id = req.request_context.request_id.as_deref();
+
+    let group = extract_group_from_request(req, default_group).map_err(|err| {
+        error!(error = ?err, "Failed to resolve the group for the request");
+        err.build_v2_response(request_id)
+    })?;
+
+    group_dao.validate_group(&group).await.map_err(|err| {
+        error!(group = group, error = ?err, "Failed to validate the group");
+        match err {
+            GroupDaoError::UnknownGroup(group) => {
+                ApiError::NotFound(format!("Unknown group: {}", group))
+            }
+            GroupDaoError::InvalidGroupName(group) => {
+                ApiError::BadRequest(format!("Invalid group name: {}", group))
+            }
+            err => ApiError::internal(err),
+        }
+        .build_v2_response(request_id)
+    })?;
+
+    Ok(group)
+}
+
+pub const AUTHORIZATION_HEADER: &str = "authorization";
+
+/**
+ * Checks that the request was made by an admin. Admins send the admin token as a bearer token in
+ * the `Authorization` header.
+ *
+ * # Arguments
+ *
+ * * `req` - The API Gateway V2 HTTP request to check
+ * * `admin_token` - The token admins authenticate with
+ *
+ * # Returns
+ *
+ * A Result containing either nothing if the request was made by an admin, or the 401 response that
+ * should be returned because the token was missing or wrong.
+ */
+pub fn authorize_admin(
+    req: &ApiGatewayV2httpRequest,
+    admin_token: &str,
+) -> Result<(), Box<ApiGatewayV2httpResponse>> {
+    let provided_token = req
+        .headers
+        .get(AUTHORIZATION_HEADER)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+
+    // Compares every byte so the time taken doesn't give away how much of the token was right
+    let authorized = provided_token.is_some_and(|provided_token| {
+        !admin_token.is_empty()
+            && provided_token.len() == admin_token.len()
+            && provided_token
+                .bytes()
+                .zip(admin_token.bytes())
+                .fold(0, |difference, (provided, expected)| {
+                    difference | (provided ^ expected)
+                })
+                == 0
+    });
+
+    if authorized {
+        return Ok(());
+    }
+
+    warn!("Rejected a request without a valid admin token");
+    Err(Box::new(
+        ApiError::Unauthorized("A valid admin token is required".to_owned())
+            .build_v2_response(req.request_context.request_id.as_deref()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_lambda_events::event::apigw::{
+        ApiGatewayV2httpRequestContextAuthorizerDescription,
+        ApiGatewayV2httpRequestContextAuthorizerJwtDescription,
+    };
+
+    use super::*;
+
+    fn build_request(
+        path_group: Option<&str>,
+        claim_group: Option<&str>,
+    ) -> ApiGatewayV2httpRequest {
+        let mut req = ApiGatewayV2httpRequest::default();
+        if let Some(path_group) = path_group {
+            req.path_parameters
+                .insert(GROUP_PATH_PARAMETER.to_owned(), path_group.to_owned());
+        }
+        if let Some(claim_group) = claim_group {
+            req.request_context.authorizer =
+                Some(ApiGatewayV2httpRequestContextAuthorizerDescription {
+                    jwt: Some(ApiGatewayV2httpRequestContextAuthorizerJwtDescription {
+                        claims: HashMap::from([(GROUP_CLAIM.to_owned(), claim_group.to_owned())]),
+                        scopes: None,
+                    }),
+                    ..Default::default()
+                });
+        }
+        req
+    }
+
+    #[test]
+    fn claim_is_used_over_the_default_group() {
+        let req = build_request(None, Some("friends"));
+
+        let group = extract_group_from_request(&req, Some("discord")).unwrap();
+
+        assert_eq!(group, "friends");
+    }
+
+    #[test]
+    fn path_matching_the_claim_is_allowed() {
+        let req = build_request(Some("friends"), Some("friends"));
+
+        let group = extract_group_from_request(&req, Some("discord")).unwrap();
+
+        assert_eq!(group, "friends");
+    }
+
+    #[test]
+    fn path_for_another_group_than_the_claim_is_forbidden() {
+        let req = build_request(Some("discord"), Some("friends"));
+
+        let err = extract_group_from_request(&req, Some("discord")).unwrap_err();
+
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        assert_eq!(err.status_code(), 403);
+    }
+
+    #[test]
+    fn unauthenticated_requests_use_the_path_then_the_default() {
+        let path_group =
+            extract_group_from_request(&build_request(Some("friends"), None), Some("discord"));
+        let default_group = extract_group_from_request(&build_request(None, None), Some("discord"));
+        let no_group = extract_group_from_request(&build_request(None, None), None);
+
+        assert_eq!(path_group.unwrap(), "friends");
+        assert_eq!(default_group.unwrap(), "discord");
+        assert!(matches!(no_group, Err(ApiError::BadRequest(_))));
+    }
+}
@@ -1,4 +1,7 @@
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use aws_sdk_dynamodb::error::BuildError;
@@ -8,17 +11,27 @@ use aws_sdk_dynamodb::{
         batch_get_item::{builders::BatchGetItemFluentBuilder, BatchGetItemError},
         get_item::{builders::GetItemFluentBuilder, GetItemError},
         put_item::{builders::PutItemFluentBuilder, PutItemError},
+        query::QueryError,
+        transact_write_items::TransactWriteItemsError,
         update_item::{builders::UpdateItemFluentBuilder, UpdateItemError},
     },
-    types::{AttributeValue, KeysAndAttributes, ReturnValue},
+    types::{AttributeValue, KeysAndAttributes, ReturnValue, TransactWriteItem, Update},
     Client as DynamoDbClient,
 };
+use futures::{stream, Stream, TryStreamExt};
+use rand::Rng;
+use tracing::warn;
 
 /**
  * Shared constants
  */
 pub const PK: &str = "pk";
 
+// BatchGetItem rejects requests with more than 100 keys
+pub(crate) const BATCH_GET_ITEM_MAX_KEYS: usize = 100;
+const MAX_BATCH_GET_ITEM_ATTEMPTS: u32 = 5;
+const BATCH_GET_ITEM_BASE_BACKOFF_MILLIS: u64 = 50;
+
 /**
  * Util Functions for making calls to DynamoDB
  */
@@ -34,14 +47,200 @@ pub struct KeyAndAttributeName<'a> {
     pub attribute_name: &'a str,
 }
 
+///
+/// A single update within a transaction. Every update in the transaction is applied or none are.
+///
+#[derive(Debug)]
+pub struct TransactUpdate<'a> {
+    pub keys_and_attributes: Vec<KeyAndAttribute<'a>>,
+    pub update_expression: String,
+    pub condition_expression: Option<String>,
+    pub expression_attribute_names: Option<Vec<KeyAndAttributeName<'a>>>,
+    pub expression_attribute_values: Vec<KeyAndAttribute<'a>>,
+}
+
+///
+/// Condition on the sort key of a partition query
+///
+#[derive(Debug)]
+pub enum SortKeyCondition {
+    BeginsWith(String),
+    Between(AttributeValue, AttributeValue),
+    LessThan(AttributeValue),
+    GreaterThan(AttributeValue),
+}
+
+///
+/// A query for the items in a single partition. Build with `new` and narrow it down with the other methods.
+///
+#[derive(Debug)]
+pub struct PartitionQuery<'a> {
+    pub partition_key: KeyAndAttribute<'a>,
+    pub sort_key_condition: Option<(&'a str, SortKeyCondition)>,
+    pub projection: Option<Vec<&'a str>>,
+    pub limit: Option<i32>,
+    pub exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+    pub scan_index_forward: bool,
+}
+
+impl<'a> PartitionQuery<'a> {
+    pub fn new(partition_key: KeyAndAttribute<'a>) -> Self {
+        PartitionQuery {
+            partition_key,
+            sort_key_condition: None,
+            projection: None,
+            limit: None,
+            exclusive_start_key: None,
+            scan_index_forward: true,
+        }
+    }
+
+    pub fn sort_key_condition(mut self, sort_key: &'a str, condition: SortKeyCondition) -> Self {
+        self.sort_key_condition = Some((sort_key, condition));
+        self
+    }
+
+    /// Only the provided top level attributes are returned
+    pub fn projection(mut self, attributes: Vec<&'a str>) -> Self {
+        self.projection = Some(attributes);
+        self
+    }
+
+    /// The maximum number of items evaluated per page
+    pub fn limit(mut self, limit: i32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Starts after the provided key, i.e. the `last_evaluated_key` of a previous page
+    pub fn exclusive_start_key(mut self, key: HashMap<String, AttributeValue>) -> Self {
+        self.exclusive_start_key = Some(key);
+        self
+    }
+
+    /// Whether items are returned in ascending sort key order, which is the default
+    pub fn scan_index_forward(mut self, scan_index_forward: bool) -> Self {
+        self.scan_index_forward = scan_index_forward;
+        self
+    }
+
+    ///
+    /// Builds the key condition and projection expressions along with the names and values they use.
+    /// Shared so every DynamoDbUtil sends the exact same expressions.
+    ///
+    pub fn build_expressions(&self) -> QueryExpressions {
+        let mut names = vec![(
+            "#partition_key".to_owned(),
+            self.partition_key.key.to_owned(),
+        )];
+        let mut values = vec![(
+            ":partition_key".to_owned(),
+            self.partition_key.attribute.to_owned(),
+        )];
+
+        let mut key_condition_expression = "#partition_key = :partition_key".to_owned();
+        if let Some((sort_key, condition)) = &self.sort_key_condition {
+            names.push(("#sort_key".to_owned(), sort_key.to_string()));
+
+            match condition {
+                SortKeyCondition::BeginsWith(prefix) => {
+                    key_condition_expression
+                        .push_str(" AND begins_with(#sort_key, :sort_key_prefix)");
+                    values.push((
+                        ":sort_key_prefix".to_owned(),
+                        AttributeValue::S(prefix.to_owned()),
+                    ));
+                }
+                SortKeyCondition::Between(start, end) => {
+                    key_condition_expression
+                        .push_str(" AND #sort_key BETWEEN :sort_key_start AND :sort_key_end");
+                    values.push((":sort_key_start".to_owned(), start.to_owned()));
+                    values.push((":sort_key_end".to_owned(), end.to_owned()));
+                }
+                SortKeyCondition::LessThan(end) => {
+                    key_condition_expression.push_str(" AND #sort_key < :sort_key_end");
+                    values.push((":sort_key_end".to_owned(), end.to_owned()));
+                }
+                SortKeyCondition::GreaterThan(start) => {
+                    key_condition_expression.push_str(" AND #sort_key > :sort_key_start");
+                    values.push((":sort_key_start".to_owned(), start.to_owned()));
+                }
+            }
+        }
+
+        // Projected attributes always go through names so reserved words can be projected
+        let projection_expression = self.projection.as_ref().map(|attributes| {
+            attributes
+                .iter()
+                .enumerate()
+                .map(|(index, attribute)| {
+                    let name = format!("#projection_{}", index);
+                    names.push((name.to_owned(), attribute.to_string()));
+                    name
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        });
+
+        QueryExpressions {
+            key_condition_expression,
+            projection_expression,
+            expression_attribute_names: names,
+            expression_attribute_values: values,
+        }
+    }
+}
+
+///
+/// The expressions for a `PartitionQuery`. Names and values are (placeholder, name/value) pairs
+///
+#[derive(Debug)]
+pub struct QueryExpressions {
+    pub key_condition_expression: String,
+    pub projection_expression: Option<String>,
+    pub expression_attribute_names: Vec<(String, String)>,
+    pub expression_attribute_values: Vec<(String, AttributeValue)>,
+}
+
+///
+/// A single page of query results. `last_evaluated_key` is set when there may be more pages
+///
+#[derive(Debug)]
+pub struct QueryPage {
+    pub items: Vec<HashMap<String, AttributeValue>>,
+    pub last_evaluated_key: Option<HashMap<String, AttributeValue>>,
+}
+
+pub type QueryPages<'a> =
+    Pin<Box<dyn Stream<Item = Result<QueryPage, DynamoDbUtilError>> + Send + 'a>>;
+
+type BatchGetResponses = HashMap<String, Vec<HashMap<String, AttributeValue>>>;
+type UnprocessedKeys = HashMap<String, KeysAndAttributes>;
+/// The items fetched by a single BatchGetItem request on one table and the keys it left unprocessed
+pub(crate) type BatchGetItemOutput = (
+    Vec<HashMap<String, AttributeValue>>,
+    Vec<HashMap<String, AttributeValue>>,
+);
+
 #[derive(Debug)]
 pub enum DynamoDbUtilError {
     GetItemFailure(Box<DynamoDbSdkError<GetItemError>>),
     BatchGetItemFailure(Box<DynamoDbSdkError<BatchGetItemError>>),
+    /// Some keys were still unprocessed after retrying. `items` holds everything that was fetched
+    BatchGetItemPartialFailure {
+        items: Vec<HashMap<String, AttributeValue>>,
+        unprocessed_keys: Vec<HashMap<String, AttributeValue>>,
+    },
     PutItemFailure(Box<DynamoDbSdkError<PutItemError>>),
     UpdateItemFailure(Box<DynamoDbSdkError<UpdateItemError>>),
+    QueryFailure(Box<DynamoDbSdkError<QueryError>>),
+    TransactWriteItemsFailure(Box<DynamoDbSdkError<TransactWriteItemsError>>),
+    ConditionalCheckFailure(String),
+    /// An item that has to exist doesn't
+    NotFound(String),
     AttributeValueConversionFailure(AttributeValue),
     OperationConstructionFailure(BuildError),
+    InMemoryFailure(String),
     LocalError(String),
 }
 
@@ -59,6 +258,17 @@ impl From<DynamoDbSdkError<BatchGetItemError>> for DynamoDbUtilError {
 
 impl From<DynamoDbSdkError<PutItemError>> for DynamoDbUtilError {
     fn from(err: DynamoDbSdkError<PutItemError>) -> Self {
+        // Surface a failed condition separately so callers can tell it apart from a failed put
+        if let DynamoDbSdkError::ServiceError(service_err) = &err {
+            if let PutItemError::ConditionalCheckFailedException(condition_failed) =
+                service_err.err()
+            {
+                return Self::ConditionalCheckFailure(
+                    condition_failed.message().unwrap_or_default().to_owned(),
+                );
+            }
+        }
+
         Self::PutItemFailure(Box::new(err))
     }
 }
@@ -69,6 +279,36 @@ impl From<DynamoDbSdkError<UpdateItemError>> for DynamoDbUtilError {
     }
 }
 
+impl From<DynamoDbSdkError<QueryError>> for DynamoDbUtilError {
+    fn from(err: DynamoDbSdkError<QueryError>) -> Self {
+        Self::QueryFailure(Box::new(err))
+    }
+}
+
+impl From<DynamoDbSdkError<TransactWriteItemsError>> for DynamoDbUtilError {
+    fn from(err: DynamoDbSdkError<TransactWriteItemsError>) -> Self {
+        // A failed condition cancels the whole transaction. Surface it separately so callers can retry
+        if let DynamoDbSdkError::ServiceError(service_err) = &err {
+            if let TransactWriteItemsError::TransactionCanceledException(canceled) =
+                service_err.err()
+            {
+                let condition_failed = canceled
+                    .cancellation_reasons()
+                    .iter()
+                    .any(|reason| reason.code() == Some("ConditionalCheckFailed"));
+
+                if condition_failed {
+                    return Self::ConditionalCheckFailure(
+                        canceled.message().unwrap_or_default().to_owned(),
+                    );
+                }
+            }
+        }
+
+        Self::TransactWriteItemsFailure(Box::new(err))
+    }
+}
+
 impl From<BuildError> for DynamoDbUtilError {
     fn from(err: BuildError) -> Self {
         Self::OperationConstructionFailure(err)
@@ -85,19 +325,25 @@ impl From<String> for DynamoDbUtilError {
 // or have both of these methods re-use the same internal code and the equivalen *_keys method.
 // They don't both need to exist
 #[async_trait]
-pub trait DynamoDbUtil {
+pub trait DynamoDbUtil: Send + Sync {
+    ///
+    /// Gets the item with the provided primary key. Returns None if there is no such item.
+    ///
     async fn get_item_from_key(
         &self,
         table_name: &str,
         table_primary_key: &str,
         key: String,
-    ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError>;
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError>;
 
+    ///
+    /// Gets the item with the provided keys. Returns None if there is no such item.
+    ///
     async fn get_item_from_keys<'a>(
         &self,
         table_name: &str,
         keys_and_attributes: Vec<KeyAndAttribute<'a>>,
-    ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError>;
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError>;
 
     async fn batch_get_item_from_key<'a>(
         &self,
@@ -111,12 +357,23 @@ pub trait DynamoDbUtil {
         keys_and_attributes: Vec<Vec<KeyAndAttribute<'a>>>,
     ) -> Result<Vec<HashMap<String, AttributeValue>>, DynamoDbUtilError>;
 
+    ///
+    /// Puts the item, replacing any item with the same keys. If a condition expression is provided the
+    /// item is only put when it holds, otherwise `ConditionalCheckFailure` is returned.
+    ///
     async fn put_item_from_keys<'a>(
         &self,
         table_name: &str,
         keys_and_attributes: Vec<KeyAndAttribute<'a>>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<Vec<KeyAndAttributeName<'a>>>,
+        expression_attribute_values: Vec<KeyAndAttribute<'a>>,
     ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError>;
 
+    ///
+    /// Updates the item, creating it if it doesn't exist. Returns None if there are no attributes to
+    /// return, e.g. the old attributes of an item that was just created.
+    ///
     async fn update_item_with_keys<'a>(
         &self,
         table_name: &str,
@@ -125,7 +382,41 @@ pub trait DynamoDbUtil {
         return_value: ReturnValue,
         expression_attribute_names: Option<Vec<KeyAndAttributeName<'a>>>,
         expression_attribute_values: Vec<KeyAndAttribute<'a>>,
-    ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError>;
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError>;
+
+    ///
+    /// Queries a single partition one page at a time, following `LastEvaluatedKey` until every page
+    /// has been read. Stop polling the stream to stop making requests.
+    ///
+    fn query_by_partition<'a>(
+        &'a self,
+        table_name: &'a str,
+        query: PartitionQuery<'a>,
+    ) -> QueryPages<'a>;
+
+    ///
+    /// Reads every page of a partition query and returns all of the items
+    ///
+    async fn query_all_by_partition<'a>(
+        &'a self,
+        table_name: &'a str,
+        query: PartitionQuery<'a>,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
+        let mut pages = self.query_by_partition(table_name, query);
+
+        let mut items = Vec::new();
+        while let Some(page) = pages.try_next().await? {
+            items.extend(page.items);
+        }
+
+        Ok(items)
+    }
+
+    async fn transact_update_items_with_keys<'a>(
+        &self,
+        table_name: &str,
+        updates: Vec<TransactUpdate<'a>>,
+    ) -> Result<(), DynamoDbUtilError>;
 }
 
 #[async_trait]
@@ -135,7 +426,7 @@ impl DynamoDbUtil for DynamoDbClient {
         table_name: &str,
         table_primary_key: &str,
         key: String,
-    ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError> {
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
         let get_item_request = self
             .get_item()
             .table_name(table_name)
@@ -148,7 +439,7 @@ impl DynamoDbUtil for DynamoDbClient {
         &self,
         table_name: &str,
         keys_and_attributes: Vec<KeyAndAttribute<'a>>,
-    ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError> {
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
         let mut get_item_request = self.get_item().table_name(table_name);
 
         for key_and_attribute in keys_and_attributes {
@@ -168,25 +459,20 @@ impl DynamoDbUtil for DynamoDbClient {
         table_name: &str,
         keys_and_attributes: Vec<KeyAndAttribute<'a>>,
     ) -> Result<Vec<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
-        // Create BatchGetItem object that will be used in the request
-        let mut batch_get_keys_and_attributes = KeysAndAttributes::builder();
-        for key_and_attribute in keys_and_attributes {
-            batch_get_keys_and_attributes = batch_get_keys_and_attributes.keys(HashMap::from([(
-                key_and_attribute.key.to_owned(),
-                key_and_attribute.attribute,
-            )]));
-        }
-        let batch_get_keys_and_attributes = batch_get_keys_and_attributes.build()?;
-
-        let batch_get_item_request = self
-            .batch_get_item()
-            .request_items(table_name, batch_get_keys_and_attributes);
-
-        Ok(batch_get_item_request
-            .send_request()
-            .await?
-            .remove(table_name) // Remove transfers ownership so the Vec doesn't have to be copied. Probably not necessary but feels cleaner
-            .ok_or_else(|| "The desired table name returned no responses".to_owned())?)
+        let keys = keys_and_attributes
+            .into_iter()
+            .map(|key_and_attribute| {
+                HashMap::from([(
+                    key_and_attribute.key.to_owned(),
+                    key_and_attribute.attribute,
+                )])
+            })
+            .collect::<Vec<HashMap<String, AttributeValue>>>();
+
+        batch_get_items(keys, |pending_keys| {
+            send_batch_get_item(self, table_name, pending_keys)
+        })
+        .await
     }
 
     async fn batch_get_item_from_keys<'a>(
@@ -194,40 +480,52 @@ impl DynamoDbUtil for DynamoDbClient {
         table_name: &str,
         keys_and_attributes: Vec<Vec<KeyAndAttribute<'a>>>,
     ) -> Result<Vec<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
-        // Create BatchGetItem object for each set of keys
-        let mut batch_get_keys_and_attributes = KeysAndAttributes::builder();
-        for key_and_attribute_list in keys_and_attributes {
-            let key_and_attribute_map = build_multi_key_and_attribute_map(key_and_attribute_list);
-
-            batch_get_keys_and_attributes =
-                batch_get_keys_and_attributes.keys(key_and_attribute_map);
-        }
-        let batch_get_keys_and_attributes = batch_get_keys_and_attributes.build()?;
-
-        let batch_get_item_request = self
-            .batch_get_item()
-            .request_items(table_name, batch_get_keys_and_attributes);
-
-        Ok(batch_get_item_request
-            .send_request()
-            .await?
-            .remove(table_name) // Remove transfers ownership so the Vec doesn't have to be copied. Probably not necessary but feels cleaner
-            .ok_or_else(|| "The desired table name returned no responses".to_owned())?)
+        let keys = keys_and_attributes
+            .into_iter()
+            .map(build_multi_key_and_attribute_map)
+            .collect::<Vec<HashMap<String, AttributeValue>>>();
+
+        batch_get_items(keys, |pending_keys| {
+            send_batch_get_item(self, table_name, pending_keys)
+        })
+        .await
     }
 
     async fn put_item_from_keys<'a>(
         &self,
         table_name: &str,
         keys_and_attributes: Vec<KeyAndAttribute<'a>>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<Vec<KeyAndAttributeName<'a>>>,
+        expression_attribute_values: Vec<KeyAndAttribute<'a>>,
     ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError> {
-        let mut put_item_request = self.put_item().table_name(table_name);
+        let mut put_item_request = self
+            .put_item()
+            .table_name(table_name)
+            .set_condition_expression(condition_expression);
         for key_and_attribute in keys_and_attributes {
             put_item_request =
                 put_item_request.item(key_and_attribute.key, key_and_attribute.attribute);
         }
+
+        // Set the expression attribute names used in the condition expression
+        if let Some(names) = expression_attribute_names {
+            for key_and_attribute in names {
+                put_item_request = put_item_request.expression_attribute_names(
+                    key_and_attribute.key,
+                    key_and_attribute.attribute_name,
+                );
+            }
+        }
+
+        // Set the expression attribute values used in the condition expression
+        for key_and_attribute in expression_attribute_values {
+            put_item_request = put_item_request
+                .expression_attribute_values(key_and_attribute.key, key_and_attribute.attribute)
+        }
         let put_item_request = put_item_request;
 
-        Ok(put_item_request.send_request().await?)
+        Ok(put_item_request.send_request().await?.unwrap_or_default())
     }
 
     async fn update_item_with_keys<'a>(
@@ -238,7 +536,7 @@ impl DynamoDbUtil for DynamoDbClient {
         return_value: ReturnValue,
         expression_attribute_names: Option<Vec<KeyAndAttributeName<'a>>>,
         expression_attribute_values: Vec<KeyAndAttribute<'a>>,
-    ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError> {
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
         let mut update_item_request = self
             .update_item()
             .table_name(table_name)
@@ -269,9 +567,250 @@ impl DynamoDbUtil for DynamoDbClient {
 
         Ok(update_item_request.send_request().await?)
     }
+
+    fn query_by_partition<'a>(
+        &'a self,
+        table_name: &'a str,
+        query: PartitionQuery<'a>,
+    ) -> QueryPages<'a> {
+        let expressions = query.build_expressions();
+
+        let mut query_request = self
+            .query()
+            .table_name(table_name)
+            .key_condition_expression(expressions.key_condition_expression)
+            .set_projection_expression(expressions.projection_expression)
+            .set_limit(query.limit)
+            .scan_index_forward(query.scan_index_forward);
+
+        for (placeholder, name) in expressions.expression_attribute_names {
+            query_request = query_request.expression_attribute_names(placeholder, name);
+        }
+
+        for (placeholder, value) in expressions.expression_attribute_values {
+            query_request = query_request.expression_attribute_values(placeholder, value);
+        }
+
+        // The state is the key to start the next page from. None once the last page has been read
+        Box::pin(stream::try_unfold(
+            Some(query.exclusive_start_key),
+            move |exclusive_start_key| {
+                let query_request = query_request.clone();
+                async move {
+                    let exclusive_start_key = match exclusive_start_key {
+                        Some(exclusive_start_key) => exclusive_start_key,
+                        None => return Ok(None),
+                    };
+
+                    let query_result = query_request
+                        .set_exclusive_start_key(exclusive_start_key)
+                        .send()
+                        .await?;
+
+                    let page = QueryPage {
+                        items: query_result.items().to_owned(),
+                        last_evaluated_key: query_result.last_evaluated_key().cloned(),
+                    };
+                    let next_start_key = page.last_evaluated_key.clone().map(Some);
+
+                    Ok(Some((page, next_start_key)))
+                }
+            },
+        ))
+    }
+
+    ///
+    /// Applies all of the updates in a single transaction on one table.
+    /// If any condition fails nothing is written and `ConditionalCheckFailure` is returned.
+    ///
+    async fn transact_update_items_with_keys<'a>(
+        &self,
+        table_name: &str,
+        updates: Vec<TransactUpdate<'a>>,
+    ) -> Result<(), DynamoDbUtilError> {
+        let mut transact_write_items_request = self.transact_write_items();
+
+        for transact_update in updates {
+            let mut update = Update::builder()
+                .table_name(table_name)
+                .update_expression(transact_update.update_expression)
+                .set_condition_expression(transact_update.condition_expression);
+
+            for key_and_attribute in transact_update.keys_and_attributes {
+                update = update.key(key_and_attribute.key, key_and_attribute.attribute);
+            }
+
+            if let Some(names) = transact_update.expression_attribute_names {
+                for key_and_attribute in names {
+                    update = update.expression_attribute_names(
+                        key_and_attribute.key,
+                        key_and_attribute.attribute_name,
+                    );
+                }
+            }
+
+            for key_and_attribute in transact_update.expression_attribute_values {
+                update = update.expression_attribute_values(
+                    key_and_attribute.key,
+                    key_and_attribute.attribute,
+                );
+            }
+
+            transact_write_items_request = transact_write_items_request
+                .transact_items(TransactWriteItem::builder().update(update.build()?).build());
+        }
+
+        transact_write_items_request.send().await?;
+
+        Ok(())
+    }
 }
 
 // Helper Functions
+///
+/// Fetches every key in chunks of at most `BATCH_GET_ITEM_MAX_KEYS`, retrying any keys returned as
+/// unprocessed with jittered exponential backoff.
+///
+/// # Arguments
+/// * `keys` - The keys of the items to fetch
+/// * `send_batch` - Sends a single BatchGetItem request, returning the items fetched and the unprocessed keys
+///
+/// # Returns
+/// * `Ok(Vec<HashMap<String, AttributeValue>>)` - The items that exist, in the order of the requested keys
+/// * `Err(DynamoDbUtilError::BatchGetItemPartialFailure)` - Some keys were still unprocessed after every attempt
+/// * `Err(DynamoDbUtilError)` - Any other failure calling DynamoDB
+///
+pub(crate) async fn batch_get_items<F, Fut>(
+    keys: Vec<HashMap<String, AttributeValue>>,
+    mut send_batch: F,
+) -> Result<Vec<HashMap<String, AttributeValue>>, DynamoDbUtilError>
+where
+    F: FnMut(Vec<HashMap<String, AttributeValue>>) -> Fut,
+    Fut: Future<Output = Result<BatchGetItemOutput, DynamoDbUtilError>>,
+{
+    let mut items = Vec::new();
+    let mut unprocessed_keys = Vec::new();
+
+    for chunk in keys.chunks(BATCH_GET_ITEM_MAX_KEYS) {
+        let mut pending_keys = chunk.to_vec();
+
+        for attempt in 0..MAX_BATCH_GET_ITEM_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(batch_get_item_backoff(attempt)).await;
+            }
+
+            let (mut fetched_items, remaining_keys) = send_batch(pending_keys).await?;
+
+            items.append(&mut fetched_items);
+            pending_keys = remaining_keys;
+
+            if pending_keys.is_empty() {
+                break;
+            }
+
+            warn!(
+                attempt = attempt + 1,
+                unprocessed = pending_keys.len(),
+                "BatchGetItem returned unprocessed keys"
+            );
+        }
+
+        unprocessed_keys.append(&mut pending_keys);
+    }
+
+    // DynamoDB returns items in an arbitrary order so put them back in the order they were requested
+    let items = order_items_by_keys(&keys, items);
+
+    if unprocessed_keys.is_empty() {
+        Ok(items)
+    } else {
+        Err(DynamoDbUtilError::BatchGetItemPartialFailure {
+            items,
+            unprocessed_keys,
+        })
+    }
+}
+
+async fn send_batch_get_item(
+    dynamodb_client: &DynamoDbClient,
+    table_name: &str,
+    keys: Vec<HashMap<String, AttributeValue>>,
+) -> Result<BatchGetItemOutput, DynamoDbUtilError> {
+    let batch_get_keys_and_attributes =
+        KeysAndAttributes::builder().set_keys(Some(keys)).build()?;
+
+    let (mut responses, unprocessed_keys) = dynamodb_client
+        .batch_get_item()
+        .request_items(table_name, batch_get_keys_and_attributes)
+        .send_request()
+        .await?;
+
+    let unprocessed_keys = unprocessed_keys
+        .get(table_name)
+        .map(|keys_and_attributes| keys_and_attributes.keys().to_vec())
+        .unwrap_or_default();
+
+    Ok((
+        responses.remove(table_name).unwrap_or_default(),
+        unprocessed_keys,
+    ))
+}
+
+///
+/// Exponential backoff with full jitter so that concurrent callers don't retry in lockstep
+///
+fn batch_get_item_backoff(attempt: u32) -> Duration {
+    let max_backoff_millis = BATCH_GET_ITEM_BASE_BACKOFF_MILLIS * 2u64.pow(attempt - 1);
+
+    Duration::from_millis(rand::thread_rng().gen_range(0..=max_backoff_millis))
+}
+
+///
+/// Key attributes can only be strings, numbers or binary, which unlike `AttributeValue` can be hashed
+///
+#[derive(PartialEq, Eq, Hash)]
+enum KeyValue {
+    S(String),
+    N(String),
+    B(Vec<u8>),
+}
+
+fn build_item_key(
+    key_names: &[&String],
+    item: &HashMap<String, AttributeValue>,
+) -> Option<Vec<KeyValue>> {
+    key_names
+        .iter()
+        .map(|name| match item.get(*name)? {
+            AttributeValue::S(value) => Some(KeyValue::S(value.to_owned())),
+            AttributeValue::N(value) => Some(KeyValue::N(value.to_owned())),
+            AttributeValue::B(value) => Some(KeyValue::B(value.as_ref().to_vec())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn order_items_by_keys(
+    keys: &[HashMap<String, AttributeValue>],
+    items: Vec<HashMap<String, AttributeValue>>,
+) -> Vec<HashMap<String, AttributeValue>> {
+    // Every key in a request is for the same table so they share the same key attributes
+    let mut key_names = keys
+        .first()
+        .map(|key| key.keys().collect::<Vec<&String>>())
+        .unwrap_or_default();
+    key_names.sort();
+
+    let mut items_by_key = items
+        .into_iter()
+        .filter_map(|item| Some((build_item_key(&key_names, &item)?, item)))
+        .collect::<HashMap<Vec<KeyValue>, HashMap<String, AttributeValue>>>();
+
+    keys.iter()
+        .filter_map(|key| items_by_key.remove(&build_item_key(&key_names, key)?))
+        .collect()
+}
+
 fn build_multi_key_and_attribute_map(
     key_and_attribute_list: Vec<KeyAndAttribute>,
 ) -> HashMap<String, AttributeValue> {
@@ -284,76 +823,190 @@ fn build_multi_key_and_attribute_map(
         );
     }
 
-    
-
     key_and_attribute_map
 }
 
 // Overidden Send Functions
 #[async_trait]
 trait DynamoDbSend {
-    async fn send_request(self) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError>;
+    async fn send_request(
+        self,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError>;
 }
 
 #[async_trait]
 trait BatchDynamoDbSend {
-    async fn send_request(
-        self,
-    ) -> Result<HashMap<String, Vec<HashMap<String, AttributeValue>>>, DynamoDbUtilError>;
+    async fn send_request(self) -> Result<(BatchGetResponses, UnprocessedKeys), DynamoDbUtilError>;
 }
 
 #[async_trait]
 impl DynamoDbSend for GetItemFluentBuilder {
-    async fn send_request(self) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError> {
+    async fn send_request(
+        self,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
         let get_item_result = self.send().await?;
 
-        let item = get_item_result
-            .item()
-            .ok_or_else(|| "Getting the set object failed".to_owned())?;
-
-        Ok(item.to_owned())
+        // DynamoDB leaves out the item when there isn't one with the keys
+        Ok(get_item_result.item().map(|item| item.to_owned()))
     }
 }
 
 #[async_trait]
 impl DynamoDbSend for UpdateItemFluentBuilder {
-    async fn send_request(self) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError> {
+    async fn send_request(
+        self,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
         let update_item_result = self.send().await?;
 
-        let attributes = update_item_result
+        // DynamoDB leaves out the attributes when there are none to return, e.g. the old attributes of a new item
+        Ok(update_item_result
             .attributes()
-            .ok_or_else(|| "Updating the item failed".to_owned())?;
-
-        Ok(attributes.to_owned())
+            .map(|attributes| attributes.to_owned()))
     }
 }
 
 #[async_trait]
 impl DynamoDbSend for PutItemFluentBuilder {
-    async fn send_request(self) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError> {
+    async fn send_request(
+        self,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
         let put_item_result = self.send().await?;
 
-        let attributes = put_item_result
+        Ok(put_item_result
             .attributes()
-            .map_or(HashMap::<String, AttributeValue>::default(), |attributes| {
-                attributes.to_owned()
-            });
-
-        Ok(attributes)
+            .map(|attributes| attributes.to_owned()))
     }
 }
 
 #[async_trait]
 impl BatchDynamoDbSend for BatchGetItemFluentBuilder {
-    async fn send_request(
-        self,
-    ) -> Result<HashMap<String, Vec<HashMap<String, AttributeValue>>>, DynamoDbUtilError> {
+    async fn send_request(self) -> Result<(BatchGetResponses, UnprocessedKeys), DynamoDbUtilError> {
         let batch_get_item_result = self.send().await?;
 
         let table_to_list_of_attributes = batch_get_item_result
             .responses()
-            .ok_or_else(|| "Updating the item failed".to_owned())?;
+            .map_or(HashMap::default(), |responses| responses.to_owned());
+
+        let unprocessed_keys = batch_get_item_result
+            .unprocessed_keys()
+            .map_or(HashMap::default(), |unprocessed_keys| {
+                unprocessed_keys.to_owned()
+            });
+
+        Ok((table_to_list_of_attributes, unprocessed_keys))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws_sdk::in_memory_dynamodb::InMemoryDynamoDb;
+
+    const TABLE: &str = "ImageTable";
+
+    fn key(sk: usize) -> Vec<KeyAndAttribute<'static>> {
+        vec![
+            KeyAndAttribute {
+                key: PK,
+                attribute: AttributeValue::S("discord_ImageHistory".to_owned()),
+            },
+            KeyAndAttribute {
+                key: "sk",
+                attribute: AttributeValue::S(format!("discord/{}.png", sk)),
+            },
+        ]
+    }
 
-        Ok(table_to_list_of_attributes.to_owned())
+    async fn build_client(item_count: usize) -> InMemoryDynamoDb {
+        let client = InMemoryDynamoDb::new().with_table(TABLE, PK, Some("sk"));
+        for sk in 0..item_count {
+            client
+                .put_item_from_keys(TABLE, key(sk), None, None, Vec::new())
+                .await
+                .unwrap();
+        }
+
+        client
+    }
+
+    fn sort_keys(items: &[HashMap<String, AttributeValue>]) -> Vec<String> {
+        items
+            .iter()
+            .map(|item| item.get("sk").unwrap().as_s().unwrap().to_owned())
+            .collect()
+    }
+
+    fn expected_sort_keys(sks: impl IntoIterator<Item = usize>) -> Vec<String> {
+        sks.into_iter()
+            .map(|sk| format!("discord/{}.png", sk))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn batch_get_keeps_the_requested_order() {
+        let client = build_client(5).await;
+
+        let items = client
+            .batch_get_item_from_keys(TABLE, [3, 0, 4, 1].into_iter().map(key).collect())
+            .await
+            .unwrap();
+
+        assert_eq!(sort_keys(&items), expected_sort_keys([3, 0, 4, 1]));
+    }
+
+    #[tokio::test]
+    async fn batch_get_leaves_out_missing_items() {
+        let client = build_client(3).await;
+
+        let items = client
+            .batch_get_item_from_keys(TABLE, [2, 7, 0].into_iter().map(key).collect())
+            .await
+            .unwrap();
+
+        assert_eq!(sort_keys(&items), expected_sort_keys([2, 0]));
+    }
+
+    #[tokio::test]
+    async fn batch_get_chunks_more_keys_than_a_request_allows() {
+        let client = build_client(250).await;
+
+        let items = client
+            .batch_get_item_from_keys(TABLE, (0..250).rev().map(key).collect())
+            .await
+            .unwrap();
+
+        assert_eq!(sort_keys(&items), expected_sort_keys((0..250).rev()));
+    }
+
+    #[tokio::test]
+    async fn batch_get_retries_unprocessed_keys() {
+        let client = build_client(10).await.with_batch_get_item_limit(3);
+
+        let items = client
+            .batch_get_item_from_keys(TABLE, (0..10).map(key).collect())
+            .await
+            .unwrap();
+
+        assert_eq!(sort_keys(&items), expected_sort_keys(0..10));
+    }
+
+    #[tokio::test]
+    async fn batch_get_keys_still_unprocessed_after_every_attempt_is_a_partial_failure() {
+        let client = build_client(7).await.with_batch_get_item_limit(1);
+
+        let result = client
+            .batch_get_item_from_keys(TABLE, (0..7).map(key).collect())
+            .await;
+
+        match result {
+            Err(DynamoDbUtilError::BatchGetItemPartialFailure {
+                items,
+                unprocessed_keys,
+            }) => {
+                assert_eq!(sort_keys(&items), expected_sort_keys(0..5));
+                assert_eq!(sort_keys(&unprocessed_keys), expected_sort_keys(5..7));
+            }
+            other => panic!("Expected a partial failure but got {:?}", other),
+        }
     }
 }
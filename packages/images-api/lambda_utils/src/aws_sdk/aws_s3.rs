@@ -1,29 +1,45 @@
 use std::io::Read;
+use std::pin::Pin;
 
 use async_trait::async_trait;
 use aws_sdk_dynamodb::error::SdkError as S3SdkError;
 use aws_sdk_s3::{
     operation::{
-        get_object::GetObjectError, list_objects::ListObjectsError,
+        get_object::GetObjectError, head_object::HeadObjectError,
+        list_objects_v2::ListObjectsV2Error,
         write_get_object_response::WriteGetObjectResponseError,
     },
     primitives::ByteStream,
     types::Object,
     Client as S3Client,
 };
+use futures::{stream, Stream, TryStreamExt};
 use tracing::{info, instrument};
 
+///
+/// A single page of listed objects. `continuation_token` is set when there are more pages
+///
+#[derive(Debug)]
+pub struct ListPage {
+    pub objects: Vec<Object>,
+    pub continuation_token: Option<String>,
+}
+
+pub type ListPages<'a> = Pin<Box<dyn Stream<Item = Result<ListPage, S3UtilError>> + Send + 'a>>;
+
 #[derive(Debug)]
 pub enum S3UtilError {
-    ListObjectsFailure(Box<S3SdkError<ListObjectsError>>),
+    ListObjectsFailure(Box<S3SdkError<ListObjectsV2Error>>),
     GetObjectFailure(Box<S3SdkError<GetObjectError>>),
-    DownloadPresignedUrlFailure(ureq::Error),
+    HeadObjectFailure(Box<S3SdkError<HeadObjectError>>),
+    DownloadPresignedUrlFailure(Box<ureq::Error>),
     WriteGetObjectResponseFailure(Box<S3SdkError<WriteGetObjectResponseError>>),
+    InMemoryFailure(String),
     LocalError(String),
 }
 
-impl From<S3SdkError<ListObjectsError>> for S3UtilError {
-    fn from(err: S3SdkError<ListObjectsError>) -> S3UtilError {
+impl From<S3SdkError<ListObjectsV2Error>> for S3UtilError {
+    fn from(err: S3SdkError<ListObjectsV2Error>) -> S3UtilError {
         S3UtilError::ListObjectsFailure(Box::new(err))
     }
 }
@@ -34,9 +50,15 @@ impl From<S3SdkError<GetObjectError>> for S3UtilError {
     }
 }
 
+impl From<S3SdkError<HeadObjectError>> for S3UtilError {
+    fn from(err: S3SdkError<HeadObjectError>) -> S3UtilError {
+        S3UtilError::HeadObjectFailure(Box::new(err))
+    }
+}
+
 impl From<ureq::Error> for S3UtilError {
     fn from(err: ureq::Error) -> S3UtilError {
-        S3UtilError::DownloadPresignedUrlFailure(err)
+        S3UtilError::DownloadPresignedUrlFailure(Box::new(err))
     }
 }
 
@@ -53,12 +75,41 @@ impl From<String> for S3UtilError {
 }
 
 #[async_trait]
-pub trait S3Util {
+pub trait S3Util: Send + Sync {
+    ///
+    /// Lists the objects in the provided bucket one page at a time. Optionally filters based on the
+    /// provided prefix. Pages are only requested as the stream is polled.
+    ///
+    fn list_pages<'a>(&'a self, bucket_name: &'a str, prefix: Option<&'a str>) -> ListPages<'a>;
+
+    ///
+    /// Reads every page of the listing and returns all of the objects
+    ///
     async fn list_items(
         &self,
         bucket_name: &str,
         prefix: Option<&str>,
-    ) -> Result<Vec<Object>, S3UtilError>;
+    ) -> Result<Vec<Object>, S3UtilError> {
+        let mut pages = self.list_pages(bucket_name, prefix);
+
+        let mut objects = Vec::new();
+        while let Some(page) = pages.try_next().await? {
+            objects.extend(page.objects);
+        }
+
+        info!("Found {} objects", objects.len());
+
+        Ok(objects)
+    }
+
+    ///
+    /// Reads the metadata of a single object without downloading it. Returns None if it doesn't exist
+    ///
+    async fn head_object_from_key(
+        &self,
+        bucket_name: &str,
+        key: &str,
+    ) -> Result<Option<Object>, S3UtilError>;
 
     async fn get_file_from_s3_url(&self, url: &str) -> Result<Vec<u8>, S3UtilError>;
 
@@ -73,8 +124,8 @@ pub trait S3Util {
 #[async_trait]
 impl S3Util for S3Client {
     ///
-    /// Lists the objects in the provided bucket. Optionally filters based on the provided prefix.
-    /// Returns a vector of Object's containing metadata about the S3 objects listed.
+    /// Lists the objects in the provided bucket using ListObjectsV2, following continuation tokens
+    /// until every page has been read. Optionally filters based on the provided prefix.
     ///
     /// # Arguments
     ///
@@ -82,31 +133,88 @@ impl S3Util for S3Client {
     /// * `prefix` - An optional string to filter the contents of the bucket on
     ///
     /// # Result
-    /// * `Ok(Vec<Object>)` - Array of Objects's that contain metadata about the S3 objects listed
+    /// * `ListPages` - Stream of pages of Objects's that contain metadata about the S3 objects listed
+    ///
+    fn list_pages<'a>(&'a self, bucket_name: &'a str, prefix: Option<&'a str>) -> ListPages<'a> {
+        // Build request to list all objects in the bucket adding the prefix if it exists
+        let list_objects_request = self
+            .list_objects_v2()
+            .bucket(bucket_name)
+            .set_prefix(prefix.map(|prefix| prefix.to_owned()));
+
+        // The state is the token to continue from. None once the last page has been read
+        Box::pin(stream::try_unfold(
+            Some(None),
+            move |continuation_token: Option<Option<String>>| {
+                let list_objects_request = list_objects_request.clone();
+                async move {
+                    let continuation_token = match continuation_token {
+                        Some(continuation_token) => continuation_token,
+                        None => return Ok(None),
+                    };
+
+                    let list_objects_output = list_objects_request
+                        .set_continuation_token(continuation_token)
+                        .send()
+                        .await?;
+
+                    let page = ListPage {
+                        objects: list_objects_output.contents().to_owned(),
+                        continuation_token: list_objects_output
+                            .next_continuation_token()
+                            .map(|token| token.to_owned()),
+                    };
+                    info!("Listed a page of {} objects", page.objects.len());
+
+                    let next_continuation_token = page.continuation_token.clone().map(Some);
+
+                    Ok(Some((page, next_continuation_token)))
+                }
+            },
+        ))
+    }
+
+    ///
+    /// Reads the metadata of the object using HeadObject. The metadata is returned as an Object so it
+    /// can be used the same way as a listed object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The bucket the object is in
+    /// * `key` - The key of the object
+    ///
+    /// # Result
+    /// * `Ok(Some(Object))` - The object's metadata
+    /// * `Ok(None)` - The object doesn't exist
     /// * `Err(S3UtilError)` - Error in case an S3 call fails or some other issue occurs
     ///
     #[instrument(skip_all)]
-    async fn list_items(
+    async fn head_object_from_key(
         &self,
         bucket_name: &str,
-        prefix: Option<&str>,
-    ) -> Result<Vec<Object>, S3UtilError> {
-        // Build request to list all objects in the bucket adding the prefix if it exists
-        let list_objects_request = self.list_objects().bucket(bucket_name);
+        key: &str,
+    ) -> Result<Option<Object>, S3UtilError> {
+        let head_object_result = self.head_object().bucket(bucket_name).key(key).send().await;
 
-        let list_objects_request = match prefix {
-            Some(prefix_str) => list_objects_request.prefix(prefix_str),
-            None => list_objects_request,
+        let head_object_output = match head_object_result {
+            Ok(head_object_output) => head_object_output,
+            Err(S3SdkError::ServiceError(service_err))
+                if matches!(service_err.err(), HeadObjectError::NotFound(_)) =>
+            {
+                info!(key = key, "The object does not exist");
+                return Ok(None);
+            }
+            Err(err) => return Err(err.into()),
         };
 
-        // Make the request to list objects and copy the metadata into a Vec
-        let list_objects_output = list_objects_request.send().await?;
-
-        let objects_list = list_objects_output.contents();
-
-        info!("Found {} objects", objects_list.len());
-
-        Ok(objects_list.to_owned())
+        Ok(Some(
+            Object::builder()
+                .key(key)
+                .set_size(head_object_output.content_length())
+                .set_e_tag(head_object_output.e_tag().map(|e_tag| e_tag.to_owned()))
+                .set_last_modified(head_object_output.last_modified().cloned())
+                .build(),
+        ))
     }
 
     ///
@@ -0,0 +1,1575 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use futures::{future, stream};
+
+use crate::aws_sdk::aws_dynamodb::{
+    batch_get_items, BatchGetItemOutput, DynamoDbUtil, DynamoDbUtilError, KeyAndAttribute,
+    KeyAndAttributeName, PartitionQuery, QueryPage, QueryPages, TransactUpdate,
+    BATCH_GET_ITEM_MAX_KEYS,
+};
+
+/*
+ * In-memory stand in for DynamoDB that can be handed to any of the DAOs in place of a real client.
+ *
+ * Only the parts of DynamoDB the DAOs rely on are supported. Items are stored per table keyed on the
+ * table's primary and sort key. Update expressions support `SET` and `REMOVE` clauses made up of
+ * attribute paths (including nested map paths and `#name` placeholders), `:value` placeholders,
+ * `if_not_exists`, `list_append` and `+`/`-` arithmetic. Condition expressions support comparisons,
+ * `BETWEEN`, `attribute_exists`, `attribute_not_exists`, `begins_with` and `AND`/`OR`/`NOT`.
+ * Queries return items in sort key order, reversed when `scan_index_forward` is false, and only split
+ * into pages when a limit is provided. Batch gets go through the same chunking and retries as the real
+ * client, and like DynamoDB each request returns its items in a different order than the keys.
+ *
+ * Errors intentionally mirror the real client so DAOs behave the same way against both. For example a
+ * missing item on a get returns `Ok(None)` just like the real client.
+ */
+
+type Item = HashMap<String, AttributeValue>;
+
+#[derive(Debug, Default)]
+pub struct InMemoryDynamoDb {
+    tables: Mutex<HashMap<String, InMemoryTable>>,
+    conditional_check_failures: Mutex<usize>,
+    batch_get_item_limit: Option<usize>,
+}
+
+#[derive(Debug)]
+struct InMemoryTable {
+    primary_key: String,
+    sort_key: Option<String>,
+    items: BTreeMap<(String, String), Item>,
+}
+
+impl InMemoryDynamoDb {
+    pub fn new() -> InMemoryDynamoDb {
+        InMemoryDynamoDb::default()
+    }
+
+    ///
+    /// Creates a table and returns self so tables can be chained onto `new()`.
+    ///
+    pub fn with_table(self, table_name: &str, primary_key: &str, sort_key: Option<&str>) -> Self {
+        self.create_table(table_name, primary_key, sort_key);
+        self
+    }
+
+    ///
+    /// Makes the next `count` transactions fail with `ConditionalCheckFailure` without writing anything, as if
+    /// another request changed the items between them being read and written. Used to exercise retries.
+    ///
+    pub fn with_conditional_check_failures(self, count: usize) -> Self {
+        *self.conditional_check_failures.lock().unwrap() = count;
+        self
+    }
+
+    ///
+    /// Makes every BatchGetItem request only process the first `limit` keys and return the rest as
+    /// unprocessed, like DynamoDB does when a response is too large or the table is throttled.
+    ///
+    pub fn with_batch_get_item_limit(mut self, limit: usize) -> Self {
+        self.batch_get_item_limit = Some(limit);
+        self
+    }
+
+    ///
+    /// Creates an empty table with the provided key schema. Replaces the table if it already exists.
+    ///
+    pub fn create_table(&self, table_name: &str, primary_key: &str, sort_key: Option<&str>) {
+        self.tables.lock().unwrap().insert(
+            table_name.to_owned(),
+            InMemoryTable {
+                primary_key: primary_key.to_owned(),
+                sort_key: sort_key.map(|sort_key| sort_key.to_owned()),
+                items: BTreeMap::new(),
+            },
+        );
+    }
+
+    ///
+    /// Returns a copy of every item in the table ordered by primary key and then sort key.
+    /// Useful for asserting on the state of the table after calling a DAO.
+    ///
+    pub fn items(&self, table_name: &str) -> Result<Vec<Item>, DynamoDbUtilError> {
+        self.on_table(table_name, |table| {
+            Ok(table.items.values().cloned().collect())
+        })
+    }
+
+    fn on_table<T>(
+        &self,
+        table_name: &str,
+        operation: impl FnOnce(&mut InMemoryTable) -> Result<T, DynamoDbUtilError>,
+    ) -> Result<T, DynamoDbUtilError> {
+        let mut tables = self.tables.lock().unwrap();
+        let table = tables.get_mut(table_name).ok_or_else(|| {
+            DynamoDbUtilError::InMemoryFailure(format!("Table {} does not exist", table_name))
+        })?;
+
+        operation(table)
+    }
+
+    fn send_batch_get_item(
+        &self,
+        table_name: &str,
+        mut keys: Vec<Item>,
+    ) -> Result<BatchGetItemOutput, DynamoDbUtilError> {
+        if keys.len() > BATCH_GET_ITEM_MAX_KEYS {
+            return Err(DynamoDbUtilError::InMemoryFailure(format!(
+                "BatchGetItem requested {} keys but at most {} are allowed",
+                keys.len(),
+                BATCH_GET_ITEM_MAX_KEYS
+            )));
+        }
+
+        let limit = self
+            .batch_get_item_limit
+            .unwrap_or(keys.len())
+            .min(keys.len());
+        let unprocessed_keys = keys.split_off(limit);
+
+        let mut items = self.on_table(table_name, |table| table.batch_get(&keys))?;
+        items.reverse();
+
+        Ok((items, unprocessed_keys))
+    }
+}
+
+#[async_trait]
+impl DynamoDbUtil for InMemoryDynamoDb {
+    async fn get_item_from_key(
+        &self,
+        table_name: &str,
+        table_primary_key: &str,
+        key: String,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
+        let key = HashMap::from([(table_primary_key.to_owned(), AttributeValue::S(key))]);
+
+        self.on_table(table_name, |table| table.get(&key))
+    }
+
+    async fn get_item_from_keys<'a>(
+        &self,
+        table_name: &str,
+        keys_and_attributes: Vec<KeyAndAttribute<'a>>,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
+        let key = build_item(keys_and_attributes);
+
+        self.on_table(table_name, |table| table.get(&key))
+    }
+
+    async fn batch_get_item_from_key<'a>(
+        &self,
+        table_name: &str,
+        keys_and_attributes: Vec<KeyAndAttribute<'a>>,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
+        let keys = keys_and_attributes
+            .into_iter()
+            .map(|key_and_attribute| build_item(vec![key_and_attribute]))
+            .collect::<Vec<Item>>();
+
+        batch_get_items(keys, |pending_keys| {
+            future::ready(self.send_batch_get_item(table_name, pending_keys))
+        })
+        .await
+    }
+
+    async fn batch_get_item_from_keys<'a>(
+        &self,
+        table_name: &str,
+        keys_and_attributes: Vec<Vec<KeyAndAttribute<'a>>>,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
+        let keys = keys_and_attributes
+            .into_iter()
+            .map(build_item)
+            .collect::<Vec<Item>>();
+
+        batch_get_items(keys, |pending_keys| {
+            future::ready(self.send_batch_get_item(table_name, pending_keys))
+        })
+        .await
+    }
+
+    async fn put_item_from_keys<'a>(
+        &self,
+        table_name: &str,
+        keys_and_attributes: Vec<KeyAndAttribute<'a>>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<Vec<KeyAndAttributeName<'a>>>,
+        expression_attribute_values: Vec<KeyAndAttribute<'a>>,
+    ) -> Result<HashMap<String, AttributeValue>, DynamoDbUtilError> {
+        let item = build_item(keys_and_attributes);
+        let context = ExpressionContext::new(
+            expression_attribute_names.unwrap_or_default(),
+            expression_attribute_values,
+        );
+
+        self.on_table(table_name, |table| {
+            table.check_condition(&item, condition_expression.as_deref(), &context)?;
+            table.put(item)?;
+            // The real client is never asked for return values on a put so nothing is returned
+            Ok(HashMap::default())
+        })
+    }
+
+    async fn update_item_with_keys<'a>(
+        &self,
+        table_name: &str,
+        keys_and_attributes: Vec<KeyAndAttribute<'a>>,
+        update_expression: String,
+        return_value: ReturnValue,
+        expression_attribute_names: Option<Vec<KeyAndAttributeName<'a>>>,
+        expression_attribute_values: Vec<KeyAndAttribute<'a>>,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynamoDbUtilError> {
+        let key = build_item(keys_and_attributes);
+        let context = ExpressionContext::new(
+            expression_attribute_names.unwrap_or_default(),
+            expression_attribute_values,
+        );
+
+        self.on_table(table_name, |table| {
+            let (old_item, new_item, updated_attributes) =
+                table.update(&key, &update_expression, &context)?;
+
+            let attributes = match return_value {
+                ReturnValue::AllOld => old_item,
+                ReturnValue::AllNew => Some(new_item),
+                ReturnValue::UpdatedOld => {
+                    old_item.map(|old_item| filter_attributes(old_item, &updated_attributes))
+                }
+                ReturnValue::UpdatedNew => Some(filter_attributes(new_item, &updated_attributes)),
+                _ => None,
+            };
+
+            Ok(attributes)
+        })
+    }
+
+    fn query_by_partition<'a>(
+        &'a self,
+        table_name: &'a str,
+        query: PartitionQuery<'a>,
+    ) -> QueryPages<'a> {
+        let expressions = query.build_expressions();
+        let context = ExpressionContext {
+            names: expressions.expression_attribute_names.into_iter().collect(),
+            values: expressions
+                .expression_attribute_values
+                .into_iter()
+                .collect(),
+        };
+
+        // Key conditions are evaluated like any other condition
+        let key_condition =
+            parse_condition_expression(&expressions.key_condition_expression, &context);
+        let projection = query.projection.map(|attributes| {
+            attributes
+                .into_iter()
+                .map(|attribute| attribute.to_owned())
+                .collect::<Vec<String>>()
+        });
+        let limit = query.limit;
+        let scan_index_forward = query.scan_index_forward;
+
+        // The state is the key to start the next page from. None once the last page has been read
+        Box::pin(stream::try_unfold(
+            Some(query.exclusive_start_key),
+            move |exclusive_start_key| {
+                let page = match (exclusive_start_key, &key_condition) {
+                    (None, _) => Ok(None),
+                    (Some(_), Err(err)) => Err(DynamoDbUtilError::InMemoryFailure(format!(
+                        "Failed to parse the key condition: {:?}",
+                        err
+                    ))),
+                    (Some(exclusive_start_key), Ok(key_condition)) => {
+                        self.on_table(table_name, |table| {
+                            let page = table.query_page(
+                                key_condition,
+                                projection.as_deref(),
+                                limit,
+                                exclusive_start_key.as_ref(),
+                                scan_index_forward,
+                            )?;
+                            let next_start_key = page.last_evaluated_key.clone().map(Some);
+
+                            Ok(Some((page, next_start_key)))
+                        })
+                    }
+                };
+
+                future::ready(page)
+            },
+        ))
+    }
+
+    async fn transact_update_items_with_keys<'a>(
+        &self,
+        table_name: &str,
+        updates: Vec<TransactUpdate<'a>>,
+    ) -> Result<(), DynamoDbUtilError> {
+        let updates = updates
+            .into_iter()
+            .map(|update| {
+                (
+                    build_item(update.keys_and_attributes),
+                    update.update_expression,
+                    update.condition_expression,
+                    ExpressionContext::new(
+                        update.expression_attribute_names.unwrap_or_default(),
+                        update.expression_attribute_values,
+                    ),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        {
+            let mut conditional_check_failures = self.conditional_check_failures.lock().unwrap();
+            if *conditional_check_failures > 0 {
+                *conditional_check_failures -= 1;
+                return Err(DynamoDbUtilError::ConditionalCheckFailure(
+                    "Transaction cancelled by an injected conditional check failure".to_owned(),
+                ));
+            }
+        }
+
+        self.on_table(table_name, |table| {
+            // Like DynamoDB a transaction can only touch each item once
+            let mut table_keys = HashSet::new();
+            for (key, _, _, _) in &updates {
+                if !table_keys.insert(table.build_key(key)?) {
+                    return Err(DynamoDbUtilError::InMemoryFailure(
+                        "ValidationException: Transaction request cannot include multiple operations on one item"
+                            .to_owned(),
+                    ));
+                }
+            }
+
+            // Every condition is evaluated against the items as they were before the transaction
+            for (key, _, condition_expression, context) in &updates {
+                table.check_condition(key, condition_expression.as_deref(), context)?;
+            }
+
+            // Any failure restores the table to how it was before the transaction
+            let snapshot = table.items.clone();
+            for (key, update_expression, _, context) in &updates {
+                if let Err(err) = table.update(key, update_expression, context) {
+                    table.items = snapshot;
+                    return Err(err);
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl InMemoryTable {
+    fn build_key(&self, item: &Item) -> Result<(String, String), DynamoDbUtilError> {
+        let primary_key = key_attribute_to_string(item, &self.primary_key)?;
+        let sort_key = match &self.sort_key {
+            Some(sort_key) => key_attribute_to_string(item, sort_key)?,
+            None => "".to_owned(),
+        };
+
+        Ok((primary_key, sort_key))
+    }
+
+    fn get(&self, key: &Item) -> Result<Option<Item>, DynamoDbUtilError> {
+        Ok(self.items.get(&self.build_key(key)?).cloned())
+    }
+
+    fn batch_get(&self, keys: &[Item]) -> Result<Vec<Item>, DynamoDbUtilError> {
+        let mut items = Vec::new();
+        for key in keys {
+            if let Some(item) = self.get(key)? {
+                items.push(item);
+            }
+        }
+
+        Ok(items)
+    }
+
+    fn put(&mut self, item: Item) -> Result<Option<Item>, DynamoDbUtilError> {
+        let key = self.build_key(&item)?;
+
+        Ok(self.items.insert(key, item))
+    }
+
+    ///
+    /// Returns the items matching the key condition in sort key order, or in reverse when not scanning
+    /// forward, starting after the exclusive start key. Like DynamoDB the last evaluated key is set
+    /// whenever the limit is reached.
+    ///
+    fn query_page(
+        &self,
+        key_condition: &Condition,
+        projection: Option<&[String]>,
+        limit: Option<i32>,
+        exclusive_start_key: Option<&Item>,
+        scan_index_forward: bool,
+    ) -> Result<QueryPage, DynamoDbUtilError> {
+        let start_key = match exclusive_start_key {
+            Some(exclusive_start_key) => Some(self.build_key(exclusive_start_key)?),
+            None => None,
+        };
+
+        let mut items = Vec::new();
+        let mut last_evaluated_key = None;
+        let table_items: Box<dyn Iterator<Item = (&(String, String), &Item)>> =
+            if scan_index_forward {
+                Box::new(self.items.iter())
+            } else {
+                Box::new(self.items.iter().rev())
+            };
+
+        for (table_key, item) in table_items {
+            let before_start = match &start_key {
+                Some(start_key) if scan_index_forward => table_key <= start_key,
+                Some(start_key) => table_key >= start_key,
+                None => false,
+            };
+            if before_start || !key_condition.evaluate(item)? {
+                continue;
+            }
+
+            items.push(match projection {
+                Some(projection) => filter_attributes(item.to_owned(), projection),
+                None => item.to_owned(),
+            });
+
+            if limit.is_some_and(|limit| items.len() >= limit as usize) {
+                last_evaluated_key = Some(self.key_attributes(item));
+                break;
+            }
+        }
+
+        Ok(QueryPage {
+            items,
+            last_evaluated_key,
+        })
+    }
+
+    fn key_attributes(&self, item: &Item) -> Item {
+        item.iter()
+            .filter(|(key, _)| **key == self.primary_key || Some(*key) == self.sort_key.as_ref())
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    ///
+    /// Evaluates the condition expression against the item with the provided key (or an empty item
+    /// if it doesn't exist). Returns `ConditionalCheckFailure` if the condition is false.
+    ///
+    fn check_condition(
+        &self,
+        key: &Item,
+        condition_expression: Option<&str>,
+        context: &ExpressionContext,
+    ) -> Result<(), DynamoDbUtilError> {
+        let condition_expression = match condition_expression {
+            Some(condition_expression) => condition_expression,
+            None => return Ok(()),
+        };
+
+        let item = self.get(key)?.unwrap_or_default();
+        if parse_condition_expression(condition_expression, context)?.evaluate(&item)? {
+            Ok(())
+        } else {
+            Err(DynamoDbUtilError::ConditionalCheckFailure(format!(
+                "The conditional request failed: {}",
+                condition_expression
+            )))
+        }
+    }
+
+    ///
+    /// Applies the update expression to the item with the provided key, creating it if necessary.
+    /// Returns the old item (if there was one), the new item and the top level attributes that were updated.
+    ///
+    fn update(
+        &mut self,
+        key: &Item,
+        update_expression: &str,
+        context: &ExpressionContext,
+    ) -> Result<(Option<Item>, Item, Vec<String>), DynamoDbUtilError> {
+        let table_key = self.build_key(key)?;
+        let old_item = self.items.get(&table_key).cloned();
+
+        // Updates always start from the key attributes so a missing item gets created
+        let mut new_item = old_item.clone().unwrap_or_else(|| key.to_owned());
+        let empty_item = Item::new();
+        let original_item = old_item.as_ref().unwrap_or(&empty_item);
+
+        let actions = parse_update_expression(update_expression, context)?;
+        let mut updated_attributes = Vec::new();
+        for action in actions {
+            match action {
+                UpdateAction::Set(path, value) => {
+                    // All values are calculated against the item as it was before the update
+                    let value = value.evaluate(original_item)?;
+                    set_path(&mut new_item, &path, value)?;
+                    updated_attributes.push(path[0].to_owned());
+                }
+                UpdateAction::Remove(path) => {
+                    remove_path(&mut new_item, &path);
+                    updated_attributes.push(path[0].to_owned());
+                }
+            }
+        }
+
+        if self.build_key(&new_item)? != table_key {
+            return Err(DynamoDbUtilError::InMemoryFailure(
+                "Cannot update attributes that are part of the key".to_owned(),
+            ));
+        }
+
+        self.items.insert(table_key, new_item.clone());
+
+        Ok((old_item, new_item, updated_attributes))
+    }
+}
+
+// Helper Functions
+fn build_item(keys_and_attributes: Vec<KeyAndAttribute>) -> Item {
+    keys_and_attributes
+        .into_iter()
+        .map(|key_and_attribute| {
+            (
+                key_and_attribute.key.to_owned(),
+                key_and_attribute.attribute,
+            )
+        })
+        .collect()
+}
+
+fn key_attribute_to_string(item: &Item, key: &str) -> Result<String, DynamoDbUtilError> {
+    match item.get(key) {
+        Some(AttributeValue::S(value)) | Some(AttributeValue::N(value)) => Ok(value.to_owned()),
+        Some(attribute) => Err(DynamoDbUtilError::AttributeValueConversionFailure(
+            attribute.to_owned(),
+        )),
+        None => Err(DynamoDbUtilError::InMemoryFailure(format!(
+            "The provided key is missing the {} attribute",
+            key
+        ))),
+    }
+}
+
+fn filter_attributes(item: Item, attributes: &[String]) -> Item {
+    item.into_iter()
+        .filter(|(key, _)| attributes.contains(key))
+        .collect()
+}
+
+/**
+ * Expression evaluation
+ */
+struct ExpressionContext {
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl ExpressionContext {
+    fn new(names: Vec<KeyAndAttributeName>, values: Vec<KeyAndAttribute>) -> ExpressionContext {
+        ExpressionContext {
+            names: names
+                .into_iter()
+                .map(|name| (name.key.to_owned(), name.attribute_name.to_owned()))
+                .collect(),
+            values: build_item(values),
+        }
+    }
+
+    fn resolve_name(&self, name: &str) -> Result<String, DynamoDbUtilError> {
+        if name.starts_with('#') {
+            self.names
+                .get(name)
+                .map(|name| name.to_owned())
+                .ok_or_else(|| {
+                    DynamoDbUtilError::InMemoryFailure(format!(
+                        "Expression attribute name {} was not provided",
+                        name
+                    ))
+                })
+        } else {
+            Ok(name.to_owned())
+        }
+    }
+
+    fn resolve_value(&self, value: &str) -> Result<AttributeValue, DynamoDbUtilError> {
+        self.values.get(value).cloned().ok_or_else(|| {
+            DynamoDbUtilError::InMemoryFailure(format!(
+                "Expression attribute value {} was not provided",
+                value
+            ))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Value(String),
+    Dot,
+    Comma,
+    OpenParen,
+    CloseParen,
+    Plus,
+    Minus,
+    Equals,
+    NotEquals,
+    LessThan,
+    LessThanOrEquals,
+    GreaterThan,
+    GreaterThanOrEquals,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, DynamoDbUtilError> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&next) = chars.peek() {
+        let token = match next {
+            ' ' | '\t' | '\n' => {
+                chars.next();
+                continue;
+            }
+            '.' => Token::Dot,
+            ',' => Token::Comma,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '=' => Token::Equals,
+            '<' | '>' => {
+                chars.next();
+                let token = match (next, chars.peek()) {
+                    ('<', Some('>')) => Token::NotEquals,
+                    ('<', Some('=')) => Token::LessThanOrEquals,
+                    ('>', Some('=')) => Token::GreaterThanOrEquals,
+                    ('<', _) => Token::LessThan,
+                    _ => Token::GreaterThan,
+                };
+
+                // Single character comparators have already been fully consumed
+                if matches!(token, Token::LessThan | Token::GreaterThan) {
+                    tokens.push(token);
+                    continue;
+                }
+                token
+            }
+            ':' | '#' | 'a'..='z' | 'A'..='Z' | '_' => {
+                let mut word = String::new();
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' || next == ':' || next == '#' {
+                        word.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                tokens.push(if word.starts_with(':') {
+                    Token::Value(word)
+                } else {
+                    Token::Identifier(word)
+                });
+                continue;
+            }
+            unexpected => {
+                return Err(DynamoDbUtilError::InMemoryFailure(format!(
+                    "Unsupported character {} in expression {}",
+                    unexpected, expression
+                )))
+            }
+        };
+
+        tokens.push(token);
+        chars.next();
+    }
+
+    Ok(tokens)
+}
+
+enum UpdateAction {
+    Set(Vec<String>, ValueExpression),
+    Remove(Vec<String>),
+}
+
+enum Operand {
+    Path(Vec<String>),
+    Value(AttributeValue),
+    IfNotExists(Vec<String>, Box<Operand>),
+    ListAppend(Box<Operand>, Box<Operand>),
+}
+
+enum ValueExpression {
+    Operand(Operand),
+    Add(Operand, Operand),
+    Subtract(Operand, Operand),
+}
+
+enum Condition {
+    Compare(Operand, Token, Operand),
+    Between(Operand, Operand, Operand),
+    AttributeExists(Vec<String>),
+    AttributeNotExists(Vec<String>),
+    BeginsWith(Operand, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    context: &'a ExpressionContext,
+}
+
+fn parse_update_expression(
+    expression: &str,
+    context: &ExpressionContext,
+) -> Result<Vec<UpdateAction>, DynamoDbUtilError> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        context,
+    };
+
+    let mut actions = Vec::new();
+    while parser.position < parser.tokens.len() {
+        match parser.next_identifier()?.to_uppercase().as_str() {
+            "SET" => loop {
+                let path = parser.parse_path()?;
+                parser.expect(Token::Equals)?;
+                actions.push(UpdateAction::Set(path, parser.parse_value_expression()?));
+
+                if !parser.consume(&Token::Comma) {
+                    break;
+                }
+            },
+            "REMOVE" => loop {
+                actions.push(UpdateAction::Remove(parser.parse_path()?));
+
+                if !parser.consume(&Token::Comma) {
+                    break;
+                }
+            },
+            clause => {
+                return Err(DynamoDbUtilError::InMemoryFailure(format!(
+                    "Unsupported update expression clause {}",
+                    clause
+                )))
+            }
+        }
+    }
+
+    Ok(actions)
+}
+
+fn parse_condition_expression(
+    expression: &str,
+    context: &ExpressionContext,
+) -> Result<Condition, DynamoDbUtilError> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        context,
+    };
+
+    let condition = parser.parse_condition()?;
+    if parser.position < parser.tokens.len() {
+        return Err(DynamoDbUtilError::InMemoryFailure(format!(
+            "Unexpected {:?} in condition expression {}",
+            parser.peek(),
+            expression
+        )));
+    }
+
+    Ok(condition)
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn consume(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), DynamoDbUtilError> {
+        if self.consume(&token) {
+            Ok(())
+        } else {
+            Err(DynamoDbUtilError::InMemoryFailure(format!(
+                "Expected {:?} but found {:?}",
+                token,
+                self.peek()
+            )))
+        }
+    }
+
+    fn next_identifier(&mut self) -> Result<String, DynamoDbUtilError> {
+        match self.tokens.get(self.position) {
+            Some(Token::Identifier(identifier)) => {
+                self.position += 1;
+                Ok(identifier.to_owned())
+            }
+            token => Err(DynamoDbUtilError::InMemoryFailure(format!(
+                "Expected an attribute name but found {:?}",
+                token
+            ))),
+        }
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(identifier)) if identifier.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // OR binds the loosest, then AND, then NOT
+    fn parse_condition(&mut self) -> Result<Condition, DynamoDbUtilError> {
+        let mut condition = self.parse_and_condition()?;
+        while self.consume_keyword("OR") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.parse_and_condition()?));
+        }
+
+        Ok(condition)
+    }
+
+    fn parse_and_condition(&mut self) -> Result<Condition, DynamoDbUtilError> {
+        let mut condition = self.parse_unary_condition()?;
+        while self.consume_keyword("AND") {
+            condition =
+                Condition::And(Box::new(condition), Box::new(self.parse_unary_condition()?));
+        }
+
+        Ok(condition)
+    }
+
+    fn parse_unary_condition(&mut self) -> Result<Condition, DynamoDbUtilError> {
+        if self.consume_keyword("NOT") {
+            return Ok(Condition::Not(Box::new(self.parse_unary_condition()?)));
+        }
+
+        if self.consume(&Token::OpenParen) {
+            let condition = self.parse_condition()?;
+            self.expect(Token::CloseParen)?;
+            return Ok(condition);
+        }
+
+        let function = match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(Token::Identifier(function)), Some(Token::OpenParen)) => {
+                Some(function.to_owned())
+            }
+            _ => None,
+        };
+
+        let condition = match function.as_deref() {
+            Some("attribute_exists") => {
+                self.position += 2;
+                Condition::AttributeExists(self.parse_path()?)
+            }
+            Some("attribute_not_exists") => {
+                self.position += 2;
+                Condition::AttributeNotExists(self.parse_path()?)
+            }
+            Some("begins_with") => {
+                self.position += 2;
+                let value = self.parse_operand()?;
+                self.expect(Token::Comma)?;
+                Condition::BeginsWith(value, self.parse_operand()?)
+            }
+            // Anything else is a comparison, possibly with a function like if_not_exists as an operand
+            _ => return self.parse_comparison(),
+        };
+        self.expect(Token::CloseParen)?;
+
+        Ok(condition)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Condition, DynamoDbUtilError> {
+        let left = self.parse_operand()?;
+
+        if self.consume_keyword("BETWEEN") {
+            let low = self.parse_operand()?;
+            if !self.consume_keyword("AND") {
+                return Err(DynamoDbUtilError::InMemoryFailure(format!(
+                    "Expected AND in BETWEEN but found {:?}",
+                    self.peek()
+                )));
+            }
+
+            return Ok(Condition::Between(left, low, self.parse_operand()?));
+        }
+        let comparator = match self.peek() {
+            Some(
+                comparator @ (Token::Equals
+                | Token::NotEquals
+                | Token::LessThan
+                | Token::LessThanOrEquals
+                | Token::GreaterThan
+                | Token::GreaterThanOrEquals),
+            ) => comparator.to_owned(),
+            token => {
+                return Err(DynamoDbUtilError::InMemoryFailure(format!(
+                    "Expected a comparison but found {:?}",
+                    token
+                )))
+            }
+        };
+        self.position += 1;
+
+        Ok(Condition::Compare(left, comparator, self.parse_operand()?))
+    }
+
+    fn parse_path(&mut self) -> Result<Vec<String>, DynamoDbUtilError> {
+        let mut path = vec![self.context.resolve_name(&self.next_identifier()?)?];
+        while self.consume(&Token::Dot) {
+            path.push(self.context.resolve_name(&self.next_identifier()?)?);
+        }
+
+        Ok(path)
+    }
+
+    fn parse_value_expression(&mut self) -> Result<ValueExpression, DynamoDbUtilError> {
+        let left = self.parse_operand()?;
+
+        if self.consume(&Token::Plus) {
+            Ok(ValueExpression::Add(left, self.parse_operand()?))
+        } else if self.consume(&Token::Minus) {
+            Ok(ValueExpression::Subtract(left, self.parse_operand()?))
+        } else {
+            Ok(ValueExpression::Operand(left))
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, DynamoDbUtilError> {
+        if let Some(Token::Value(value)) = self.peek() {
+            let value = self.context.resolve_value(value)?;
+            self.position += 1;
+            return Ok(Operand::Value(value));
+        }
+
+        let is_function = matches!(self.tokens.get(self.position + 1), Some(Token::OpenParen));
+        if !is_function {
+            return Ok(Operand::Path(self.parse_path()?));
+        }
+
+        let function = self.next_identifier()?;
+        self.expect(Token::OpenParen)?;
+        let operand = match function.as_str() {
+            "if_not_exists" => {
+                let path = self.parse_path()?;
+                self.expect(Token::Comma)?;
+                Operand::IfNotExists(path, Box::new(self.parse_operand()?))
+            }
+            "list_append" => {
+                let first = self.parse_operand()?;
+                self.expect(Token::Comma)?;
+                Operand::ListAppend(Box::new(first), Box::new(self.parse_operand()?))
+            }
+            function => {
+                return Err(DynamoDbUtilError::InMemoryFailure(format!(
+                    "Unsupported function {}",
+                    function
+                )))
+            }
+        };
+        self.expect(Token::CloseParen)?;
+
+        Ok(operand)
+    }
+}
+
+impl Operand {
+    fn evaluate_if_exists(&self, item: &Item) -> Result<Option<AttributeValue>, DynamoDbUtilError> {
+        match self {
+            Operand::Path(path) => Ok(get_path(item, path).cloned()),
+            operand => operand.evaluate(item).map(Some),
+        }
+    }
+
+    fn evaluate(&self, item: &Item) -> Result<AttributeValue, DynamoDbUtilError> {
+        match self {
+            Operand::Path(path) => get_path(item, path).cloned().ok_or_else(|| {
+                DynamoDbUtilError::InMemoryFailure(format!(
+                    "The provided expression refers to an attribute that does not exist in the item: {}",
+                    path.join(".")
+                ))
+            }),
+            Operand::Value(value) => Ok(value.to_owned()),
+            Operand::IfNotExists(path, default) => match get_path(item, path) {
+                Some(value) => Ok(value.to_owned()),
+                None => default.evaluate(item),
+            },
+            Operand::ListAppend(first, second) => {
+                let mut first = as_list(first.evaluate(item)?)?;
+                first.extend(as_list(second.evaluate(item)?)?);
+                Ok(AttributeValue::L(first))
+            }
+        }
+    }
+}
+
+impl Condition {
+    fn evaluate(&self, item: &Item) -> Result<bool, DynamoDbUtilError> {
+        match self {
+            Condition::Compare(left, comparator, right) => {
+                // Comparing against an attribute that doesn't exist is always false
+                match (
+                    left.evaluate_if_exists(item)?,
+                    right.evaluate_if_exists(item)?,
+                ) {
+                    (Some(left), Some(right)) => Ok(compare(&left, comparator, &right)),
+                    _ => Ok(false),
+                }
+            }
+            Condition::Between(value, low, high) => match (
+                value.evaluate_if_exists(item)?,
+                low.evaluate_if_exists(item)?,
+                high.evaluate_if_exists(item)?,
+            ) {
+                (Some(value), Some(low), Some(high)) => {
+                    Ok(compare(&value, &Token::GreaterThanOrEquals, &low)
+                        && compare(&value, &Token::LessThanOrEquals, &high))
+                }
+                _ => Ok(false),
+            },
+            Condition::AttributeExists(path) => Ok(get_path(item, path).is_some()),
+            Condition::AttributeNotExists(path) => Ok(get_path(item, path).is_none()),
+            Condition::BeginsWith(value, prefix) => {
+                match (
+                    value.evaluate_if_exists(item)?,
+                    prefix.evaluate_if_exists(item)?,
+                ) {
+                    (Some(AttributeValue::S(value)), Some(AttributeValue::S(prefix))) => {
+                        Ok(value.starts_with(&prefix))
+                    }
+                    _ => Ok(false),
+                }
+            }
+            Condition::And(left, right) => Ok(left.evaluate(item)? && right.evaluate(item)?),
+            Condition::Or(left, right) => Ok(left.evaluate(item)? || right.evaluate(item)?),
+            Condition::Not(condition) => Ok(!condition.evaluate(item)?),
+        }
+    }
+}
+
+fn compare(left: &AttributeValue, comparator: &Token, right: &AttributeValue) -> bool {
+    let ordering = match (left, right) {
+        (AttributeValue::S(left), AttributeValue::S(right)) => left.partial_cmp(right),
+        (AttributeValue::N(left), AttributeValue::N(right)) => {
+            match (left.parse::<f64>(), right.parse::<f64>()) {
+                (Ok(left), Ok(right)) => left.partial_cmp(&right),
+                _ => None,
+            }
+        }
+        // Other types can only be checked for equality
+        _ => match comparator {
+            Token::Equals => return left == right,
+            Token::NotEquals => return left != right,
+            _ => return false,
+        },
+    };
+
+    match (ordering, comparator) {
+        (Some(ordering), Token::Equals) => ordering.is_eq(),
+        (Some(ordering), Token::NotEquals) => ordering.is_ne(),
+        (Some(ordering), Token::LessThan) => ordering.is_lt(),
+        (Some(ordering), Token::LessThanOrEquals) => ordering.is_le(),
+        (Some(ordering), Token::GreaterThan) => ordering.is_gt(),
+        (Some(ordering), Token::GreaterThanOrEquals) => ordering.is_ge(),
+        _ => false,
+    }
+}
+
+impl ValueExpression {
+    fn evaluate(&self, item: &Item) -> Result<AttributeValue, DynamoDbUtilError> {
+        match self {
+            ValueExpression::Operand(operand) => operand.evaluate(item),
+            ValueExpression::Add(left, right) => {
+                add_numbers(&left.evaluate(item)?, &right.evaluate(item)?, false)
+            }
+            ValueExpression::Subtract(left, right) => {
+                add_numbers(&left.evaluate(item)?, &right.evaluate(item)?, true)
+            }
+        }
+    }
+}
+
+fn as_list(value: AttributeValue) -> Result<Vec<AttributeValue>, DynamoDbUtilError> {
+    match value {
+        AttributeValue::L(list) => Ok(list),
+        value => Err(DynamoDbUtilError::AttributeValueConversionFailure(value)),
+    }
+}
+
+fn add_numbers(
+    left: &AttributeValue,
+    right: &AttributeValue,
+    subtract: bool,
+) -> Result<AttributeValue, DynamoDbUtilError> {
+    let left = left
+        .as_n()
+        .map_err(|err| DynamoDbUtilError::AttributeValueConversionFailure(err.to_owned()))?;
+    let right = right
+        .as_n()
+        .map_err(|err| DynamoDbUtilError::AttributeValueConversionFailure(err.to_owned()))?;
+
+    // Integers are kept exact and anything else falls back to floating point
+    let result = match (left.parse::<i64>(), right.parse::<i64>()) {
+        (Ok(left), Ok(right)) if subtract => (left - right).to_string(),
+        (Ok(left), Ok(right)) => (left + right).to_string(),
+        _ => {
+            let left = parse_float(left)?;
+            let right = parse_float(right)?;
+            if subtract {
+                (left - right).to_string()
+            } else {
+                (left + right).to_string()
+            }
+        }
+    };
+
+    Ok(AttributeValue::N(result))
+}
+
+fn parse_float(number: &str) -> Result<f64, DynamoDbUtilError> {
+    number.parse::<f64>().map_err(|err| {
+        DynamoDbUtilError::InMemoryFailure(format!("Failed to parse number {}: {}", number, err))
+    })
+}
+
+fn get_path<'a>(item: &'a Item, path: &[String]) -> Option<&'a AttributeValue> {
+    let mut current = item.get(&path[0])?;
+    for segment in &path[1..] {
+        current = current.as_m().ok()?.get(segment)?;
+    }
+
+    Some(current)
+}
+
+fn set_path(
+    item: &mut Item,
+    path: &[String],
+    value: AttributeValue,
+) -> Result<(), DynamoDbUtilError> {
+    let (last, parents) = path.split_last().unwrap();
+
+    let mut current = item;
+    for segment in parents {
+        current = match current.get_mut(segment) {
+            Some(AttributeValue::M(map)) => map,
+            _ => {
+                return Err(DynamoDbUtilError::InMemoryFailure(format!(
+                    "The document path provided in the update expression is invalid for update: {}",
+                    path.join(".")
+                )))
+            }
+        };
+    }
+    current.insert(last.to_owned(), value);
+
+    Ok(())
+}
+
+fn remove_path(item: &mut Item, path: &[String]) {
+    let (last, parents) = path.split_last().unwrap();
+
+    let mut current = item;
+    for segment in parents {
+        current = match current.get_mut(segment) {
+            Some(AttributeValue::M(map)) => map,
+            _ => return,
+        };
+    }
+    current.remove(last);
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use super::*;
+    use crate::aws_sdk::aws_dynamodb::SortKeyCondition;
+
+    const TABLE: &str = "ImageTable";
+    const PK: &str = "pk";
+    const SK: &str = "sk";
+
+    fn build_client() -> InMemoryDynamoDb {
+        InMemoryDynamoDb::new().with_table(TABLE, PK, Some(SK))
+    }
+
+    fn key(pk: &str, sk: &str) -> Vec<KeyAndAttribute<'static>> {
+        vec![
+            KeyAndAttribute {
+                key: PK,
+                attribute: AttributeValue::S(pk.to_owned()),
+            },
+            KeyAndAttribute {
+                key: SK,
+                attribute: AttributeValue::S(sk.to_owned()),
+            },
+        ]
+    }
+
+    fn number(value: i64) -> AttributeValue {
+        AttributeValue::N(value.to_string())
+    }
+
+    fn value<'a>(placeholder: &'a str, attribute: AttributeValue) -> KeyAndAttribute<'a> {
+        KeyAndAttribute {
+            key: placeholder,
+            attribute,
+        }
+    }
+
+    fn name<'a>(placeholder: &'a str, attribute_name: &'a str) -> KeyAndAttributeName<'a> {
+        KeyAndAttributeName {
+            key: placeholder,
+            attribute_name,
+        }
+    }
+
+    async fn get(client: &InMemoryDynamoDb, pk: &str, sk: &str) -> Option<Item> {
+        client.get_item_from_keys(TABLE, key(pk, sk)).await.unwrap()
+    }
+
+    async fn increment(client: &InMemoryDynamoDb, return_value: ReturnValue) -> Option<Item> {
+        client
+            .update_item_with_keys(
+                TABLE,
+                key("group_Counts", "2024-01-01"),
+                "SET #count = if_not_exists(#count, :zero) + :one".to_owned(),
+                return_value,
+                Some(vec![name("#count", "count")]),
+                vec![value(":zero", number(0)), value(":one", number(1))],
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn missing_item_is_none() {
+        let client = build_client();
+
+        assert_eq!(get(&client, "group_Counts", "2024-01-01").await, None);
+    }
+
+    #[tokio::test]
+    async fn if_not_exists_creates_then_increments() {
+        let client = build_client();
+
+        increment(&client, ReturnValue::None).await;
+        increment(&client, ReturnValue::None).await;
+
+        let item = get(&client, "group_Counts", "2024-01-01").await.unwrap();
+        assert_eq!(item.get("count"), Some(&number(2)));
+        assert_eq!(
+            item.get(PK),
+            Some(&AttributeValue::S("group_Counts".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn set_adds_and_subtracts_from_existing_values() {
+        let client = build_client();
+        increment(&client, ReturnValue::None).await;
+
+        client
+            .update_item_with_keys(
+                TABLE,
+                key("group_Counts", "2024-01-01"),
+                "SET #count = #count + :n, #other = :n - #count".to_owned(),
+                ReturnValue::None,
+                Some(vec![name("#count", "count"), name("#other", "other")]),
+                vec![value(":n", number(5))],
+            )
+            .await
+            .unwrap();
+
+        let item = get(&client, "group_Counts", "2024-01-01").await.unwrap();
+        // Both sides are evaluated against the item as it was before the update
+        assert_eq!(item.get("count"), Some(&number(6)));
+        assert_eq!(item.get("other"), Some(&number(4)));
+    }
+
+    #[tokio::test]
+    async fn nested_name_paths_update_maps() {
+        let client = build_client();
+        let update = |expression: &str, values: Vec<KeyAndAttribute<'static>>| {
+            client.update_item_with_keys(
+                TABLE,
+                key("group_Counts", "2024-01-01"),
+                expression.to_owned(),
+                ReturnValue::None,
+                Some(vec![name("#counts", "Counts"), name("#love", "Love")]),
+                values,
+            )
+        };
+
+        update(
+            "SET #counts = if_not_exists(#counts, :empty)",
+            vec![value(":empty", AttributeValue::M(HashMap::new()))],
+        )
+        .await
+        .unwrap();
+        update(
+            "SET #counts.#love = if_not_exists(#counts.#love, :zero) + :one",
+            vec![value(":zero", number(0)), value(":one", number(1))],
+        )
+        .await
+        .unwrap();
+
+        let item = get(&client, "group_Counts", "2024-01-01").await.unwrap();
+        assert_eq!(
+            item.get("Counts"),
+            Some(&AttributeValue::M(HashMap::from([(
+                "Love".to_owned(),
+                number(1)
+            )])))
+        );
+    }
+
+    #[tokio::test]
+    async fn nested_path_without_a_parent_map_fails() {
+        let client = build_client();
+
+        let result = client
+            .update_item_with_keys(
+                TABLE,
+                key("group_Counts", "2024-01-01"),
+                "SET #counts.#love = :one".to_owned(),
+                ReturnValue::None,
+                Some(vec![name("#counts", "Counts"), name("#love", "Love")]),
+                vec![value(":one", number(1))],
+            )
+            .await;
+
+        assert!(matches!(result, Err(DynamoDbUtilError::InMemoryFailure(_))));
+    }
+
+    #[tokio::test]
+    async fn returns_all_old_and_all_new() {
+        let client = build_client();
+
+        let first_old = increment(&client, ReturnValue::AllOld).await;
+        let second_old = increment(&client, ReturnValue::AllOld).await.unwrap();
+        let third_new = increment(&client, ReturnValue::AllNew).await.unwrap();
+
+        assert_eq!(first_old, None);
+        assert_eq!(second_old.get("count"), Some(&number(1)));
+        assert_eq!(third_new.get("count"), Some(&number(3)));
+        assert_eq!(
+            third_new.get(SK),
+            Some(&AttributeValue::S("2024-01-01".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_condition_leaves_the_item_alone() {
+        let client = build_client();
+        let put = |object_key: &str| {
+            let mut keys_and_attributes = key("group", "2024-01-01");
+            keys_and_attributes.push(value(
+                "object_key",
+                AttributeValue::S(object_key.to_owned()),
+            ));
+            client.put_item_from_keys(
+                TABLE,
+                keys_and_attributes,
+                Some("attribute_not_exists(#pk)".to_owned()),
+                Some(vec![name("#pk", PK)]),
+                Vec::new(),
+            )
+        };
+
+        put("group/first.png").await.unwrap();
+        let result = put("group/second.png").await;
+
+        assert!(matches!(
+            result,
+            Err(DynamoDbUtilError::ConditionalCheckFailure(_))
+        ));
+        let item = get(&client, "group", "2024-01-01").await.unwrap();
+        assert_eq!(
+            item.get("object_key"),
+            Some(&AttributeValue::S("group/first.png".to_owned()))
+        );
+    }
+
+    fn transact_increment<'a>(sk: &str, condition: Option<&str>) -> TransactUpdate<'a> {
+        TransactUpdate {
+            keys_and_attributes: key("group_Counts", sk),
+            update_expression: "SET #count = if_not_exists(#count, :zero) + :one".to_owned(),
+            condition_expression: condition.map(|condition| condition.to_owned()),
+            expression_attribute_names: Some(vec![name("#count", "count")]),
+            expression_attribute_values: vec![value(":zero", number(0)), value(":one", number(1))],
+        }
+    }
+
+    #[tokio::test]
+    async fn transaction_conditions_see_the_items_before_the_transaction() {
+        let client = build_client();
+        increment(&client, ReturnValue::None).await;
+
+        // The second condition would pass if it saw the first update
+        let result = client
+            .transact_update_items_with_keys(
+                TABLE,
+                vec![
+                    transact_increment("2024-01-02", None),
+                    transact_increment("2024-01-03", Some("attribute_exists(#count)")),
+                ],
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(DynamoDbUtilError::ConditionalCheckFailure(_))
+        ));
+        assert_eq!(get(&client, "group_Counts", "2024-01-02").await, None);
+        assert_eq!(get(&client, "group_Counts", "2024-01-03").await, None);
+    }
+
+    #[tokio::test]
+    async fn transaction_applies_every_update() {
+        let client = build_client();
+        increment(&client, ReturnValue::None).await;
+
+        client
+            .transact_update_items_with_keys(
+                TABLE,
+                vec![
+                    transact_increment("2024-01-01", Some("#count = :one")),
+                    transact_increment("2024-01-02", Some("attribute_not_exists(#count)")),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let first = get(&client, "group_Counts", "2024-01-01").await.unwrap();
+        let second = get(&client, "group_Counts", "2024-01-02").await.unwrap();
+        assert_eq!(first.get("count"), Some(&number(2)));
+        assert_eq!(second.get("count"), Some(&number(1)));
+    }
+
+    #[tokio::test]
+    async fn transaction_touching_an_item_twice_is_rejected() {
+        let client = build_client();
+
+        let result = client
+            .transact_update_items_with_keys(
+                TABLE,
+                vec![
+                    transact_increment("2024-01-01", None),
+                    transact_increment("2024-01-01", None),
+                ],
+            )
+            .await;
+
+        assert!(matches!(result, Err(DynamoDbUtilError::InMemoryFailure(_))));
+        assert_eq!(get(&client, "group_Counts", "2024-01-01").await, None);
+    }
+
+    #[tokio::test]
+    async fn query_pages_through_the_partition_in_sort_key_order() {
+        let client = build_client();
+        for sk in ["2024-01-03", "2024-01-01", "2024-01-02", "2023-12-31"] {
+            client
+                .put_item_from_keys(TABLE, key("group", sk), None, None, Vec::new())
+                .await
+                .unwrap();
+        }
+        client
+            .put_item_from_keys(TABLE, key("other", "2024-01-01"), None, None, Vec::new())
+            .await
+            .unwrap();
+
+        let pages = client
+            .query_by_partition(
+                TABLE,
+                PartitionQuery::new(value(PK, AttributeValue::S("group".to_owned())))
+                    .sort_key_condition(SK, SortKeyCondition::BeginsWith("2024".to_owned()))
+                    .limit(2),
+            )
+            .try_collect::<Vec<QueryPage>>()
+            .await
+            .unwrap();
+
+        let sort_keys = pages
+            .iter()
+            .map(|page| {
+                page.items
+                    .iter()
+                    .map(|item| item.get(SK).unwrap().as_s().unwrap().to_owned())
+                    .collect::<Vec<String>>()
+            })
+            .collect::<Vec<Vec<String>>>();
+        assert_eq!(
+            sort_keys,
+            vec![
+                vec!["2024-01-01".to_owned(), "2024-01-02".to_owned()],
+                vec!["2024-01-03".to_owned()],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_pages_backwards_before_the_sort_key() {
+        let client = build_client();
+        for sk in ["2024-01-03", "2024-01-01", "2024-01-02", "2023-12-31"] {
+            client
+                .put_item_from_keys(TABLE, key("group", sk), None, None, Vec::new())
+                .await
+                .unwrap();
+        }
+
+        let pages = client
+            .query_by_partition(
+                TABLE,
+                PartitionQuery::new(value(PK, AttributeValue::S("group".to_owned())))
+                    .sort_key_condition(
+                        SK,
+                        SortKeyCondition::LessThan(AttributeValue::S("2024-01-03".to_owned())),
+                    )
+                    .scan_index_forward(false)
+                    .limit(2),
+            )
+            .try_collect::<Vec<QueryPage>>()
+            .await
+            .unwrap();
+
+        let sort_keys = pages
+            .iter()
+            .map(|page| {
+                page.items
+                    .iter()
+                    .map(|item| item.get(SK).unwrap().as_s().unwrap().to_owned())
+                    .collect::<Vec<String>>()
+            })
+            .collect::<Vec<Vec<String>>>();
+        assert_eq!(
+            sort_keys,
+            vec![
+                vec!["2024-01-02".to_owned(), "2024-01-01".to_owned()],
+                vec!["2023-12-31".to_owned()],
+            ]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use aws_sdk_s3::types::Object;
+use futures::stream;
+
+use crate::aws_sdk::aws_s3::{ListPage, ListPages, S3Util, S3UtilError};
+
+// The most objects ListObjectsV2 returns in a single page
+const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/*
+ * In-memory stand in for S3 that can be handed to any of the DAOs in place of a real client.
+ *
+ * Objects are stored per bucket ordered by key, the same order S3 lists them in. Files are downloaded
+ * with `s3://{bucket}/{key}` urls instead of presigned urls and anything written as a get object
+ * response is kept per route so it can be inspected. Listings are paged with the key of the last
+ * object on the page as the continuation token.
+ */
+
+#[derive(Debug)]
+pub struct InMemoryS3 {
+    buckets: Mutex<HashMap<String, BTreeMap<String, Vec<u8>>>>,
+    object_responses: Mutex<HashMap<String, Vec<u8>>>,
+    page_size: usize,
+}
+
+impl Default for InMemoryS3 {
+    fn default() -> Self {
+        InMemoryS3 {
+            buckets: Mutex::default(),
+            object_responses: Mutex::default(),
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+impl InMemoryS3 {
+    pub fn new() -> InMemoryS3 {
+        InMemoryS3::default()
+    }
+
+    ///
+    /// Sets the most objects returned in a single page of a listing so paging can be exercised
+    /// without thousands of objects.
+    ///
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    ///
+    /// Stores an object and returns self so objects can be chained onto `new()`.
+    ///
+    pub fn with_object(self, bucket_name: &str, key: &str, bytes: Vec<u8>) -> Self {
+        self.put_object(bucket_name, key, bytes);
+        self
+    }
+
+    ///
+    /// Stores an object in the bucket, creating the bucket if necessary. Overwrites any existing object.
+    ///
+    pub fn put_object(&self, bucket_name: &str, key: &str, bytes: Vec<u8>) {
+        self.buckets
+            .lock()
+            .unwrap()
+            .entry(bucket_name.to_owned())
+            .or_default()
+            .insert(key.to_owned(), bytes);
+    }
+
+    ///
+    /// Returns the bytes written to the provided route by `send_to_get_object_response`.
+    ///
+    pub fn object_response(&self, route: &str) -> Option<Vec<u8>> {
+        self.object_responses.lock().unwrap().get(route).cloned()
+    }
+
+    ///
+    /// Returns the page of objects after the continuation token or None if the bucket doesn't exist
+    ///
+    fn list_page(
+        &self,
+        bucket_name: &str,
+        prefix: Option<&str>,
+        continuation_token: Option<String>,
+    ) -> Option<ListPage> {
+        let buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get(bucket_name)?;
+
+        let start = match continuation_token {
+            Some(last_key) => Bound::Excluded(last_key),
+            None => Bound::Unbounded,
+        };
+
+        // Take one extra object to tell whether there is another page
+        let mut objects = bucket
+            .range((start, Bound::Unbounded))
+            .filter(|(key, _)| key.starts_with(prefix.unwrap_or("")))
+            .take(self.page_size + 1)
+            .map(|(key, bytes)| Object::builder().key(key).size(bytes.len() as i64).build())
+            .collect::<Vec<Object>>();
+
+        let continuation_token = if objects.len() > self.page_size {
+            objects.truncate(self.page_size);
+            objects
+                .last()
+                .and_then(|object| object.key())
+                .map(|key| key.to_owned())
+        } else {
+            None
+        };
+
+        Some(ListPage {
+            objects,
+            continuation_token,
+        })
+    }
+}
+
+#[async_trait]
+impl S3Util for InMemoryS3 {
+    fn list_pages<'a>(&'a self, bucket_name: &'a str, prefix: Option<&'a str>) -> ListPages<'a> {
+        Box::pin(stream::try_unfold(
+            Some(None),
+            move |continuation_token: Option<Option<String>>| async move {
+                let continuation_token = match continuation_token {
+                    Some(continuation_token) => continuation_token,
+                    None => return Ok(None),
+                };
+
+                let page = self
+                    .list_page(bucket_name, prefix, continuation_token)
+                    .ok_or_else(|| {
+                        S3UtilError::InMemoryFailure(format!(
+                            "Bucket {} does not exist",
+                            bucket_name
+                        ))
+                    })?;
+                let next_continuation_token = page.continuation_token.clone().map(Some);
+
+                Ok(Some((page, next_continuation_token)))
+            },
+        ))
+    }
+
+    async fn head_object_from_key(
+        &self,
+        bucket_name: &str,
+        key: &str,
+    ) -> Result<Option<Object>, S3UtilError> {
+        Ok(self
+            .buckets
+            .lock()
+            .unwrap()
+            .get(bucket_name)
+            .and_then(|bucket| bucket.get(key))
+            .map(|bytes| Object::builder().key(key).size(bytes.len() as i64).build()))
+    }
+
+    async fn get_file_from_s3_url(&self, url: &str) -> Result<Vec<u8>, S3UtilError> {
+        let (bucket_name, key) = url
+            .strip_prefix("s3://")
+            .and_then(|path| path.split_once('/'))
+            .ok_or_else(|| {
+                S3UtilError::InMemoryFailure(format!(
+                    "Url {} is not of the form s3://bucket/key",
+                    url
+                ))
+            })?;
+
+        self.buckets
+            .lock()
+            .unwrap()
+            .get(bucket_name)
+            .and_then(|bucket| bucket.get(key))
+            .cloned()
+            .ok_or_else(|| S3UtilError::InMemoryFailure(format!("Object {} does not exist", url)))
+    }
+
+    async fn send_to_get_object_response(
+        &self,
+        route: String,
+        _token: String,
+        bytes: Vec<u8>,
+    ) -> Result<(), S3UtilError> {
+        self.object_responses.lock().unwrap().insert(route, bytes);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUCKET: &str = "images";
+
+    fn build_client() -> InMemoryS3 {
+        ["b/1.png", "a/2.png", "ab/1.png", "a/1.png", "a/3.png"]
+            .into_iter()
+            .fold(InMemoryS3::new().with_page_size(2), |client, key| {
+                client.with_object(BUCKET, key, Vec::new())
+            })
+    }
+
+    fn keys(objects: &[Object]) -> Vec<&str> {
+        objects.iter().filter_map(|object| object.key()).collect()
+    }
+
+    #[tokio::test]
+    async fn lists_only_the_prefix_in_key_order_across_pages() {
+        let client = build_client();
+
+        let objects = client.list_items(BUCKET, Some("a/")).await.unwrap();
+
+        assert_eq!(keys(&objects), vec!["a/1.png", "a/2.png", "a/3.png"]);
+    }
+
+    #[tokio::test]
+    async fn lists_every_object_without_a_prefix() {
+        let client = build_client();
+
+        let objects = client.list_items(BUCKET, None).await.unwrap();
+
+        assert_eq!(objects.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn listing_a_missing_bucket_fails() {
+        let client = build_client();
+
+        let result = client.list_items("missing", None).await;
+
+        assert!(matches!(result, Err(S3UtilError::InMemoryFailure(_))));
+    }
+
+    #[tokio::test]
+    async fn head_object_is_none_for_a_missing_object() {
+        let client = build_client();
+
+        let existing = client
+            .head_object_from_key(BUCKET, "a/1.png")
+            .await
+            .unwrap();
+        let missing = client
+            .head_object_from_key(BUCKET, "a/4.png")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            existing.and_then(|object| object.key),
+            Some("a/1.png".to_owned())
+        );
+        assert!(missing.is_none());
+    }
+}
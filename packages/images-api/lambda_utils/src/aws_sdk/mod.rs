@@ -1,3 +1,6 @@
 pub mod api_gateway;
 pub mod aws_dynamodb;
 pub mod aws_s3;
+pub mod in_memory_dynamodb;
+pub mod in_memory_s3;
+pub mod router;
@@ -0,0 +1,543 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::future::Future;
+
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::event::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpResponse};
+use aws_lambda_events::http::Method;
+use futures::future::{BoxFuture, FutureExt};
+use serde::de::value::{Error as ValueError, MapDeserializer};
+use serde::de::{DeserializeOwned, Error as _, IgnoredAny, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize};
+use tracing::{error, info};
+
+use crate::aws_sdk::api_gateway::{
+    extract_body_from_request, ApiError, ApiGatewayProxyResponseWithoutHeaders,
+    GROUP_PATH_PARAMETER,
+};
+
+/**
+ * A request passed to a route's handler, with its JSON body and query parameters already
+ * deserialized. Routes that don't use the body or the query parameters can leave them as
+ * `IgnoredAny`.
+ */
+pub struct Request<B = IgnoredAny, Q = IgnoredAny> {
+    pub body: B,
+    pub query: Q,
+}
+
+type RouteHandler<'a> = Box<
+    dyn Fn(ApiGatewayV2httpRequest) -> BoxFuture<'a, Result<ApiGatewayV2httpResponse, ApiError>>
+        + Send
+        + Sync
+        + 'a,
+>;
+
+struct Route<'a> {
+    method: Method,
+    path: &'static str,
+    handler: RouteHandler<'a>,
+}
+
+/**
+ * Dispatches API Gateway requests to typed handlers based on the method and route key.
+ *
+ * Paths are registered without the optional `/{group}` prefix, so `/archive` matches both the
+ * `PUT /archive` and `PUT /{group}/archive` route keys. Handlers return a body that is sent back as
+ * JSON with a 200, or an error that is converted into an `ApiError` response.
+ */
+#[derive(Default)]
+pub struct Router<'a> {
+    routes: Vec<Route<'a>>,
+}
+
+impl<'a> Router<'a> {
+    pub fn new() -> Self {
+        Router { routes: Vec::new() }
+    }
+
+    pub fn get<B, Q, R, E, F, Fut>(self, path: &'static str, handler: F) -> Self
+    where
+        B: DeserializeOwned + Send + 'a,
+        Q: DeserializeOwned + Send + 'a,
+        R: Serialize + Send + 'a,
+        E: Into<ApiError> + Debug + Send + 'a,
+        F: Fn(Request<B, Q>) -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = Result<R, E>> + Send + 'a,
+    {
+        self.route(Method::GET, path, handler)
+    }
+
+    pub fn put<B, Q, R, E, F, Fut>(self, path: &'static str, handler: F) -> Self
+    where
+        B: DeserializeOwned + Send + 'a,
+        Q: DeserializeOwned + Send + 'a,
+        R: Serialize + Send + 'a,
+        E: Into<ApiError> + Debug + Send + 'a,
+        F: Fn(Request<B, Q>) -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = Result<R, E>> + Send + 'a,
+    {
+        self.route(Method::PUT, path, handler)
+    }
+
+    /**
+     * Registers a handler for a method and path.
+     *
+     * # Arguments
+     *
+     * * `method` - The method the route handles
+     * * `path` - The path of the route without the `/{group}` prefix, e.g. `/archive`
+     * * `handler` - Called with the deserialized request. A body or query parameters that can't be
+     *   deserialized into the handler's types are rejected with a 400 before it is called
+     */
+    pub fn route<B, Q, R, E, F, Fut>(
+        mut self,
+        method: Method,
+        path: &'static str,
+        handler: F,
+    ) -> Self
+    where
+        B: DeserializeOwned + Send + 'a,
+        Q: DeserializeOwned + Send + 'a,
+        R: Serialize + Send + 'a,
+        E: Into<ApiError> + Debug + Send + 'a,
+        F: Fn(Request<B, Q>) -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = Result<R, E>> + Send + 'a,
+    {
+        let handler = move |req: ApiGatewayV2httpRequest| {
+            let request = match build_request::<B, Q>(&req) {
+                Ok(request) => request,
+                Err(err) => return futures::future::ready(Err(err)).boxed(),
+            };
+            let response = handler(request);
+
+            async move {
+                let response_body = response.await.map_err(|err| {
+                    error!(error = ?err, "Failed to properly handle the incoming request due to");
+                    err.into()
+                })?;
+
+                let response = serde_json::to_string(&response_body).map_err(ApiError::internal)?;
+
+                Ok(ApiGatewayProxyResponseWithoutHeaders {
+                    status_code: 200,
+                    body: Body::Text(response),
+                    is_base_64_encoded: false,
+                }
+                .build_v2_response())
+            }
+            .boxed()
+        };
+
+        self.routes.push(Route {
+            method,
+            path,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /**
+     * Handles the request with the matching route.
+     *
+     * # Returns
+     *
+     * The handler's response, or an error response if no route matches the path (404), no route for
+     * the path handles the method (405), or the request couldn't be handled.
+     */
+    pub async fn handle(&self, req: ApiGatewayV2httpRequest) -> ApiGatewayV2httpResponse {
+        let request_id = req.request_context.request_id.clone();
+        let method = req.request_context.http.method.clone();
+        let path = route_path(&req);
+
+        info!(method = ?method, path = path, "Routing the request");
+
+        let routes = self
+            .routes
+            .iter()
+            .filter(|route| Some(route.path) == path.as_deref())
+            .collect::<Vec<&Route>>();
+
+        let result = match routes.iter().find(|route| route.method == method) {
+            Some(route) => (route.handler)(req).await,
+            None if routes.is_empty() => Err(ApiError::NotFound(format!(
+                "There is no route for {}",
+                path.unwrap_or_default()
+            ))),
+            None => Err(ApiError::MethodNotAllowed(method)),
+        };
+
+        result.unwrap_or_else(|err| err.build_v2_response(request_id.as_deref()))
+    }
+}
+
+/**
+ * Gets the path of the route the request was made to, without the `/{group}` prefix. The path is
+ * taken from the route key, falling back to the raw path for requests without one.
+ */
+fn route_path(req: &ApiGatewayV2httpRequest) -> Option<String> {
+    let group_prefix = format!("/{{{}}}", GROUP_PATH_PARAMETER);
+
+    if let Some(route_key) = &req.route_key {
+        let path = route_key
+            .split_once(' ')
+            .map_or(route_key.as_str(), |(_, path)| path);
+        return Some(path.strip_prefix(&group_prefix).unwrap_or(path).to_owned());
+    }
+
+    let raw_path = req.raw_path.as_deref()?;
+    let path = req
+        .path_parameters
+        .get(GROUP_PATH_PARAMETER)
+        .and_then(|group| raw_path.strip_prefix(&format!("/{}", group)))
+        .unwrap_or(raw_path);
+    Some(path.to_owned())
+}
+
+fn build_request<B: DeserializeOwned, Q: DeserializeOwned>(
+    req: &ApiGatewayV2httpRequest,
+) -> Result<Request<B, Q>, ApiError> {
+    // A missing body is treated as null so routes that don't need one can still be called without it
+    let body = match &req.body {
+        Some(_) => {
+            serde_json::from_str(&extract_body_from_request(req).map_err(ApiError::BadRequest)?)
+                .map_err(|err| {
+                    ApiError::BadRequest(format!("The request body is invalid: {}", err))
+                })?
+        }
+        None => serde_json::from_str("null")
+            .map_err(|_| ApiError::BadRequest("The request body is missing".to_owned()))?,
+    };
+
+    // Only the first value of a repeated query parameter is used
+    let mut seen_keys = HashSet::new();
+    let query_parameters = req
+        .query_string_parameters
+        .iter()
+        .filter(|(key, _)| seen_keys.insert(*key))
+        .map(|(key, value)| (key, QueryValueDeserializer(value)));
+    let query =
+        Q::deserialize(MapDeserializer::<_, ValueError>::new(query_parameters)).map_err(|err| {
+            ApiError::BadRequest(format!("The query parameters are invalid: {}", err))
+        })?;
+
+    Ok(Request { body, query })
+}
+
+/**
+ * Deserializes a single query parameter. Query parameters are always strings so numbers and bools
+ * are parsed from them, and a parameter that was provided is always `Some`.
+ */
+struct QueryValueDeserializer<'q>(&'q str);
+
+impl<'de, 'q> IntoDeserializer<'de, ValueError> for QueryValueDeserializer<'q> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit(self.0.parse().map_err(ValueError::custom)?)
+            }
+        )*
+    };
+}
+
+impl<'de, 'q> Deserializer<'de> for QueryValueDeserializer<'q> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0
+            .into_deserializer()
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct newtype_struct seq tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_lambda_events::query_map::QueryMap;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Order {
+        Oldest,
+        Newest,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct TypedQuery {
+        limit: u32,
+        offset: Option<i64>,
+        ratio: Option<f64>,
+        pinned: Option<bool>,
+        order: Option<Order>,
+        cursor: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct RequestBody {
+        object_key: String,
+    }
+
+    fn build_request(
+        method: Method,
+        route_key: &str,
+        query: &[(&str, &[&str])],
+        body: Option<&str>,
+    ) -> ApiGatewayV2httpRequest {
+        let mut req = ApiGatewayV2httpRequest::default();
+        req.request_context.http.method = method;
+        req.route_key = Some(route_key.to_owned());
+        req.query_string_parameters = QueryMap::from(
+            query
+                .iter()
+                .map(|(key, values)| {
+                    (
+                        key.to_string(),
+                        values.iter().map(|value| value.to_string()).collect(),
+                    )
+                })
+                .collect::<HashMap<String, Vec<String>>>(),
+        );
+        req.body = body.map(|body| body.to_owned());
+        req
+    }
+
+    // Echoes the deserialized query and body back so tests can check how they were parsed
+    fn build_router() -> Router<'static> {
+        Router::new()
+            .get(
+                "/history",
+                |request: Request<IgnoredAny, TypedQuery>| async move {
+                    Ok::<TypedQuery, ApiError>(request.query)
+                },
+            )
+            .put("/archive", |request: Request<RequestBody>| async move {
+                Ok::<RequestBody, ApiError>(request.body)
+            })
+    }
+
+    fn response_body<T: DeserializeOwned>(response: &ApiGatewayV2httpResponse) -> T {
+        match &response.body {
+            Some(Body::Text(body)) => serde_json::from_str(body).unwrap(),
+            body => panic!("Expected a text body but got {:?}", body),
+        }
+    }
+
+    #[tokio::test]
+    async fn query_parameters_are_parsed_into_their_types() {
+        let req = build_request(
+            Method::GET,
+            "GET /history",
+            &[
+                ("limit", &["20"]),
+                ("offset", &["-3"]),
+                ("ratio", &["0.5"]),
+                ("pinned", &["true"]),
+                ("order", &["newest"]),
+            ],
+            None,
+        );
+
+        let response = build_router().handle(req).await;
+
+        assert_eq!(response.status_code, 200);
+        assert_eq!(
+            response_body::<TypedQuery>(&response),
+            TypedQuery {
+                limit: 20,
+                offset: Some(-3),
+                ratio: Some(0.5),
+                pinned: Some(true),
+                order: Some(Order::Newest),
+                cursor: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_first_value_of_a_repeated_query_parameter_is_used() {
+        let req = build_request(
+            Method::GET,
+            "GET /history",
+            &[("limit", &["5", "50"]), ("cursor", &["2024-01-02", "x"])],
+            None,
+        );
+
+        let response = build_router().handle(req).await;
+        let query = response_body::<TypedQuery>(&response);
+
+        assert_eq!(query.limit, 5);
+        assert_eq!(query.cursor.as_deref(), Some("2024-01-02"));
+    }
+
+    #[tokio::test]
+    async fn invalid_query_parameters_are_a_bad_request() {
+        let router = build_router();
+
+        let not_a_number = router
+            .handle(build_request(
+                Method::GET,
+                "GET /history",
+                &[("limit", &["many"])],
+                None,
+            ))
+            .await;
+        let not_a_bool = router
+            .handle(build_request(
+                Method::GET,
+                "GET /history",
+                &[("limit", &["1"]), ("pinned", &["yes"])],
+                None,
+            ))
+            .await;
+        let unknown_variant = router
+            .handle(build_request(
+                Method::GET,
+                "GET /history",
+                &[("limit", &["1"]), ("order", &["random"])],
+                None,
+            ))
+            .await;
+        let missing = router
+            .handle(build_request(Method::GET, "GET /history", &[], None))
+            .await;
+
+        assert_eq!(not_a_number.status_code, 400);
+        assert_eq!(not_a_bool.status_code, 400);
+        assert_eq!(unknown_variant.status_code, 400);
+        assert_eq!(missing.status_code, 400);
+    }
+
+    #[tokio::test]
+    async fn json_body_is_parsed() {
+        let req = build_request(
+            Method::PUT,
+            "PUT /archive",
+            &[],
+            Some(r#"{"object_key": "discord/1.png"}"#),
+        );
+
+        let response = build_router().handle(req).await;
+
+        assert_eq!(response.status_code, 200);
+        assert_eq!(
+            response_body::<RequestBody>(&response),
+            RequestBody {
+                object_key: "discord/1.png".to_owned(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_or_invalid_body_is_a_bad_request() {
+        let router = build_router();
+
+        let missing = router
+            .handle(build_request(Method::PUT, "PUT /archive", &[], None))
+            .await;
+        let invalid_json = router
+            .handle(build_request(
+                Method::PUT,
+                "PUT /archive",
+                &[],
+                Some("{\"object_key\": "),
+            ))
+            .await;
+        let wrong_shape = router
+            .handle(build_request(
+                Method::PUT,
+                "PUT /archive",
+                &[],
+                Some(r#"{"object_key": 1}"#),
+            ))
+            .await;
+
+        assert_eq!(missing.status_code, 400);
+        assert_eq!(invalid_json.status_code, 400);
+        assert_eq!(wrong_shape.status_code, 400);
+    }
+
+    #[tokio::test]
+    async fn unknown_path_is_not_found_and_wrong_method_is_not_allowed() {
+        let router = build_router();
+
+        let unknown_path = router
+            .handle(build_request(Method::GET, "GET /unknown", &[], None))
+            .await;
+        let wrong_method = router
+            .handle(build_request(Method::GET, "GET /archive", &[], None))
+            .await;
+
+        assert_eq!(unknown_path.status_code, 404);
+        assert_eq!(wrong_method.status_code, 405);
+    }
+
+    #[test]
+    fn group_prefix_is_stripped_from_the_route_path() {
+        let route_key = build_request(Method::PUT, "PUT /{group}/archive", &[], None);
+        let plain_route_key = build_request(Method::PUT, "PUT /archive", &[], None);
+        let raw_path = ApiGatewayV2httpRequest {
+            raw_path: Some("/friends/archive".to_owned()),
+            path_parameters: HashMap::from([(
+                GROUP_PATH_PARAMETER.to_owned(),
+                "friends".to_owned(),
+            )]),
+            ..Default::default()
+        };
+        let raw_path_without_group = ApiGatewayV2httpRequest {
+            raw_path: Some("/archive".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(route_path(&route_key).as_deref(), Some("/archive"));
+        assert_eq!(route_path(&plain_route_key).as_deref(), Some("/archive"));
+        assert_eq!(route_path(&raw_path).as_deref(), Some("/archive"));
+        assert_eq!(
+            route_path(&raw_path_without_group).as_deref(),
+            Some("/archive")
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use std::{collections::HashMap, fmt, str::FromStr};
 
 use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
 use serde::Deserialize;
 use strum::{EnumProperty, IntoEnumIterator, ParseError};
 use strum_macros::{EnumIter, EnumProperty, EnumString};
@@ -11,6 +13,11 @@ pub struct SstBucket {
     pub name: String,
 }
 
+#[derive(Deserialize)]
+pub struct SstSecret {
+    pub value: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct SstTable {
     pub name: String,
@@ -79,3 +86,40 @@ impl Reactions {
         Ok(Reactions::from_str(reaction_str)?)
     }
 }
+
+/** Model used to work out a group's current date */
+pub const DEFAULT_TIMEZONE: Tz = Tz::UTC;
+
+///
+/// A group's day runs from midnight to midnight in the group's IANA timezone. Every date a group's
+/// image, reactions and favorites are stored under is one of its days, so the current date should always
+/// come from here instead of the server's clock.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupDay {
+    pub timezone: Tz,
+}
+
+impl Default for GroupDay {
+    fn default() -> Self {
+        GroupDay {
+            timezone: DEFAULT_TIMEZONE,
+        }
+    }
+}
+
+impl GroupDay {
+    pub fn new(timezone: Tz) -> Self {
+        GroupDay { timezone }
+    }
+
+    /// The group's current date
+    pub fn today(&self) -> NaiveDate {
+        self.date_at(Utc::now())
+    }
+
+    /// The group's date at the provided instant
+    pub fn date_at(&self, instant: DateTime<Utc>) -> NaiveDate {
+        instant.with_timezone(&self.timezone).date_naive()
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoDbClient};
+use chrono_tz::Tz;
+use strum_macros::EnumString;
+use tracing::{error, info, instrument, warn};
+
+use crate::aws_sdk::aws_dynamodb::{DynamoDbUtil, DynamoDbUtilError, KeyAndAttribute};
+use crate::models::{GroupDay, Reactions, DEFAULT_TIMEZONE};
+
+// Structs
+pub struct GroupDao<'a, D = DynamoDbClient> {
+    pub table_name: &'a str,
+    pub primary_key: &'a str,
+    pub sort_key: &'a str,
+    pub dynamodb_client: &'a D,
+}
+
+///
+/// How a group's daily image is picked from the images that haven't been shown recently
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum SelectionStrategyKind {
+    /// Every image is equally likely
+    #[default]
+    Uniform,
+    /// Images are weighted by how they were received in the past
+    Weighted,
+}
+
+///
+/// What the weighted selection strategy rewards. Every weight is added on top of a base weight of 1
+///
+#[derive(Debug, Clone)]
+pub struct SelectionWeights {
+    /// Added per reaction of that kind, averaged over the days the image was shown
+    pub reactions: HashMap<String, f64>,
+    /// Added per user who picked the image as their favorite from a recap
+    pub favorite: f64,
+    /// Used instead of the other weights for images that have never been shown
+    pub never_shown: f64,
+    /// How many days back reactions and favorites are read from
+    pub history_days: i64,
+}
+
+impl Default for SelectionWeights {
+    fn default() -> Self {
+        SelectionWeights {
+            reactions: HashMap::from([
+                (Reactions::Love.to_string(), 1.0),
+                (Reactions::Funny.to_string(), 1.0),
+            ]),
+            favorite: 2.0,
+            never_shown: 3.0,
+            history_days: 60,
+        }
+    }
+}
+
+///
+/// How long an image has to wait before it can be picked again
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoRepeatWindow {
+    /// An image can't be picked again until this many days after it was shown
+    Days(i64),
+    /// An image can't be picked again until every other image has been shown
+    UntilExhausted,
+}
+
+impl Default for NoRepeatWindow {
+    fn default() -> Self {
+        NoRepeatWindow::Days(DEFAULT_NO_REPEAT_DAYS)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupSettings {
+    pub selection_strategy: SelectionStrategyKind,
+    pub selection_weights: SelectionWeights,
+    pub no_repeat_window: NoRepeatWindow,
+    /// A recap of the images shown since the last recap is shown every this many days
+    pub recap_period_days: i64,
+    /// How many days ahead the daily setup keeps images queued
+    pub queue_days: i64,
+    /// The IANA timezone the group's days start and end in
+    pub timezone: Tz,
+}
+
+impl Default for GroupSettings {
+    fn default() -> Self {
+        GroupSettings {
+            selection_strategy: SelectionStrategyKind::default(),
+            selection_weights: SelectionWeights::default(),
+            no_repeat_window: NoRepeatWindow::default(),
+            recap_period_days: DEFAULT_RECAP_PERIOD_DAYS,
+            queue_days: DEFAULT_QUEUE_DAYS,
+            timezone: DEFAULT_TIMEZONE,
+        }
+    }
+}
+
+impl GroupSettings {
+    pub fn group_day(&self) -> GroupDay {
+        GroupDay::new(self.timezone)
+    }
+}
+
+// Error Enum
+#[derive(Debug)]
+pub enum GroupDaoError {
+    DynamoDbError(DynamoDbUtilError),
+    AttributeValueConversionError(AttributeValue),
+    UnknownGroup(String),
+    InvalidGroupName(String),
+    LocalError(String),
+}
+
+impl From<DynamoDbUtilError> for GroupDaoError {
+    fn from(err: DynamoDbUtilError) -> GroupDaoError {
+        GroupDaoError::DynamoDbError(err)
+    }
+}
+
+impl From<AttributeValue> for GroupDaoError {
+    fn from(err: AttributeValue) -> GroupDaoError {
+        GroupDaoError::AttributeValueConversionError(err)
+    }
+}
+
+impl From<String> for GroupDaoError {
+    fn from(err: String) -> GroupDaoError {
+        GroupDaoError::LocalError(err)
+    }
+}
+
+// Implementation
+pub const DEFAULT_NO_REPEAT_DAYS: i64 = 5;
+pub const DEFAULT_RECAP_PERIOD_DAYS: i64 = 6;
+pub const DEFAULT_QUEUE_DAYS: i64 = 3;
+
+const GROUPS: &str = "Groups";
+const REGISTRY: &str = "Registry";
+const GROUP_NAMES: &str = "group_names";
+const SETTINGS_PREFIX: &str = "Settings#";
+const SELECTION_STRATEGY: &str = "selection_strategy";
+const SELECTION_WEIGHTS: &str = "selection_weights";
+const SELECTION_HISTORY_DAYS: &str = "selection_history_days";
+const FAVORITE_WEIGHT: &str = "favorite";
+const NEVER_SHOWN_WEIGHT: &str = "never_shown";
+const NO_REPEAT_WINDOW: &str = "no_repeat_window";
+const UNTIL_EXHAUSTED: &str = "until_exhausted";
+const RECAP_PERIOD_DAYS: &str = "recap_period_days";
+const QUEUE_DAYS: &str = "queue_days";
+const TIMEZONE: &str = "timezone";
+
+///
+/// Checks that a group name can be used in keys. Keys like `{group}_{date}` and `{group}_Archive` are
+/// split on `_` and image keys on `/`, so names containing either could collide with another group's.
+///
+pub fn is_valid_group_name(group: &str) -> bool {
+    !group.is_empty() && !group.contains('_') && !group.contains('/')
+}
+
+impl<D: DynamoDbUtil> GroupDao<'_, D> {
+    ///
+    /// Lists every group that has been registered. The registry is a single record
+    /// (pk "Groups", sk "Registry") holding a string set of group names. Names that aren't valid
+    /// group names are skipped.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<String>)` - The names of all registered groups sorted alphabetically
+    /// * `Error(GroupDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn list_groups(&self) -> Result<Vec<String>, GroupDaoError> {
+        let item = self
+            .dynamodb_client
+            .get_item_from_keys(self.table_name, self.build_registry_key_and_attribute())
+            .await?
+            .ok_or_else(|| DynamoDbUtilError::NotFound("The group registry".to_owned()))?;
+
+        let mut groups = item
+            .get(GROUP_NAMES)
+            .ok_or_else(|| "The group registry does not contain any group names".to_owned())?
+            .as_ss()
+            .map_err(|att_val| att_val.to_owned())?
+            .iter()
+            .filter(|group| {
+                let is_valid = is_valid_group_name(group);
+                if !is_valid {
+                    error!(
+                        group = group,
+                        "Skipping a registered group with an invalid name"
+                    );
+                }
+                is_valid
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+        groups.sort();
+
+        info!(groups = ?groups, "The registered groups are: ");
+
+        Ok(groups)
+    }
+
+    ///
+    /// Checks that the provided group is in the registry.
+    ///
+    /// # Arguments
+    /// * `group` - The name of the group to validate
+    ///
+    /// # Returns
+    /// * `Ok(())` - The group is registered
+    /// * `Error(GroupDaoError::InvalidGroupName)` - The name can't be used as a group name
+    /// * `Error(GroupDaoError::UnknownGroup)` - The group is not registered
+    /// * `Error(GroupDaoError)` - Any other failure that occurs while reading the registry
+    ///
+    #[instrument(skip_all)]
+    pub async fn validate_group(&self, group: &str) -> Result<(), GroupDaoError> {
+        if !is_valid_group_name(group) {
+            return Err(GroupDaoError::InvalidGroupName(group.to_owned()));
+        }
+
+        let groups = self.list_groups().await?;
+
+        if groups.iter().any(|registered| registered == group) {
+            Ok(())
+        } else {
+            Err(GroupDaoError::UnknownGroup(group.to_owned()))
+        }
+    }
+
+    ///
+    /// Gets the settings for the provided group. Settings are stored per group in the groups partition
+    /// (pk "Groups", sk "Settings#{group}"). Anything that isn't set uses the default.
+    ///
+    /// `selection_weights` is a map of numbers keyed by reaction name, "favorite" or "never_shown".
+    /// Only the provided weights replace the defaults, and they can't be negative. `selection_history_days`
+    /// is a number of days of at least 1.
+    ///
+    /// `no_repeat_window` is either a number of days or "until_exhausted" and `recap_period_days` is a
+    /// number of days of at least 2, since a recap covers the days in between.
+    ///
+    /// `timezone` is an IANA timezone name like "America/New_York".
+    ///
+    /// # Arguments
+    /// * `group` - The name of the group whose settings are being read
+    ///
+    /// # Returns
+    /// * `Ok(GroupSettings)` - The group's settings. The defaults if the group has no settings record
+    /// * `Error(GroupDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_settings(&self, group: &str) -> Result<GroupSettings, GroupDaoError> {
+        // Most groups won't have settings
+        let item = self
+            .dynamodb_client
+            .get_item_from_keys(
+                self.table_name,
+                self.build_settings_key_and_attribute(group),
+            )
+            .await?;
+
+        let mut settings = GroupSettings::default();
+        let item = match item {
+            Some(item) => item,
+            None => {
+                info!(
+                    group = group,
+                    "The group has no settings. Using the defaults"
+                );
+                return Ok(settings);
+            }
+        };
+
+        if let Some(selection_strategy) = item.get(SELECTION_STRATEGY) {
+            let selection_strategy = selection_strategy
+                .as_s()
+                .map_err(|att_val| att_val.to_owned())?;
+
+            settings.selection_strategy = SelectionStrategyKind::from_str(selection_strategy)
+                .map_err(|_| {
+                    format!(
+                        "Unknown selection strategy {} for group {}",
+                        selection_strategy, group
+                    )
+                })?;
+        }
+
+        if let Some(selection_weights) = item.get(SELECTION_WEIGHTS) {
+            let selection_weights = selection_weights
+                .as_m()
+                .map_err(|att_val| att_val.to_owned())?;
+
+            for (name, weight) in selection_weights {
+                let weight = match parse_number(weight)? {
+                    weight if weight.is_finite() && weight >= 0.0 => weight,
+                    weight => {
+                        return Err(
+                            format!("The selection weight {} can't be {}", name, weight).into()
+                        )
+                    }
+                };
+
+                match name.as_str() {
+                    FAVORITE_WEIGHT => settings.selection_weights.favorite = weight,
+                    NEVER_SHOWN_WEIGHT => settings.selection_weights.never_shown = weight,
+                    reaction if Reactions::get_reaction(reaction).is_ok() => {
+                        settings
+                            .selection_weights
+                            .reactions
+                            .insert(reaction.to_owned(), weight);
+                    }
+                    unknown => warn!(weight = unknown, "Ignoring an unknown selection weight"),
+                }
+            }
+        }
+
+        if let Some(history_days) = item.get(SELECTION_HISTORY_DAYS) {
+            settings.selection_weights.history_days = match parse_whole_number(history_days)? {
+                days if days >= 1 => days,
+                days => return Err(format!("The selection history can't be {} days", days).into()),
+            };
+        }
+
+        if let Some(no_repeat_window) = item.get(NO_REPEAT_WINDOW) {
+            settings.no_repeat_window = match no_repeat_window {
+                AttributeValue::S(window) if window == UNTIL_EXHAUSTED => {
+                    NoRepeatWindow::UntilExhausted
+                }
+                AttributeValue::N(_) => match parse_whole_number(no_repeat_window)? {
+                    days if days >= 0 => NoRepeatWindow::Days(days),
+                    days => {
+                        return Err(format!("The no repeat window can't be {} days", days).into())
+                    }
+                },
+                _ => {
+                    return Err(format!(
+                        "The no repeat window should be a number of days or {}",
+                        UNTIL_EXHAUSTED
+                    )
+                    .into())
+                }
+            };
+        }
+
+        if let Some(recap_period_days) = item.get(RECAP_PERIOD_DAYS) {
+            settings.recap_period_days = match parse_whole_number(recap_period_days)? {
+                days if days >= 2 => days,
+                days => return Err(format!("The recap period can't be {} days", days).into()),
+            };
+        }
+
+        if let Some(queue_days) = item.get(QUEUE_DAYS) {
+            settings.queue_days = match parse_whole_number(queue_days)? {
+                days if days >= 1 => days,
+                days => return Err(format!("The queue can't be {} days", days).into()),
+            };
+        }
+
+        if let Some(timezone) = item.get(TIMEZONE) {
+            let timezone = timezone.as_s().map_err(|att_val| att_val.to_owned())?;
+
+            settings.timezone = Tz::from_str(timezone).map_err(|err| {
+                format!("Unknown timezone {} for group {}: {}", timezone, group, err)
+            })?;
+        }
+
+        info!(group = group, settings = ?settings, "The group's settings are: ");
+
+        Ok(settings)
+    }
+
+    ///
+    /// Gets the group's day, which decides what the group's current date is.
+    ///
+    /// # Arguments
+    /// * `group` - The name of the group whose day is being read
+    ///
+    /// # Returns
+    /// The day in the group's timezone. The default timezone is used if the settings can't be read, so
+    /// a bad settings record can't stop the group from working.
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_group_day(&self, group: &str) -> GroupDay {
+        self.get_settings(group)
+            .await
+            .map(|settings| settings.group_day())
+            .unwrap_or_else(|err| {
+                error!(group = group, error = ?err, "Failed to read the group's settings. Using the default timezone");
+                GroupDay::default()
+            })
+    }
+
+    /** Helper Functions that require state */
+    fn build_registry_key_and_attribute(&self) -> Vec<KeyAndAttribute<'_>> {
+        vec![
+            KeyAndAttribute {
+                key: self.primary_key,
+                attribute: AttributeValue::S(GROUPS.to_owned()),
+            },
+            KeyAndAttribute {
+                key: self.sort_key,
+                attribute: AttributeValue::S(REGISTRY.to_owned()),
+            },
+        ]
+    }
+
+    fn build_settings_key_and_attribute(&self, group: &str) -> Vec<KeyAndAttribute<'_>> {
+        vec![
+            KeyAndAttribute {
+                key: self.primary_key,
+                attribute: AttributeValue::S(GROUPS.to_owned()),
+            },
+            KeyAndAttribute {
+                key: self.sort_key,
+                attribute: AttributeValue::S(format!("{}{}", SETTINGS_PREFIX, group)),
+            },
+        ]
+    }
+}
+
+// Helper functions that don't require state
+fn parse_number(value: &AttributeValue) -> Result<f64, GroupDaoError> {
+    Ok(value
+        .as_n()
+        .map_err(|att_val| att_val.to_owned())?
+        .parse::<f64>()
+        .map_err(|err| format!("The selection weight isn't a number: {}", err))?)
+}
+
+fn parse_whole_number(value: &AttributeValue) -> Result<i64, GroupDaoError> {
+    Ok(value
+        .as_n()
+        .map_err(|att_val| att_val.to_owned())?
+        .parse::<i64>()
+        .map_err(|err| format!("The setting isn't a whole number: {}", err))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws_sdk::in_memory_dynamodb::InMemoryDynamoDb;
+
+    const TABLE: &str = "ImageTable";
+    const GROUP: &str = "discord";
+
+    fn build_dao(dynamodb_client: &InMemoryDynamoDb) -> GroupDao<'_, InMemoryDynamoDb> {
+        GroupDao {
+            table_name: TABLE,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client,
+        }
+    }
+
+    async fn get_settings_with(
+        setting: &'static str,
+        value: AttributeValue,
+    ) -> Result<GroupSettings, GroupDaoError> {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        let mut keys_and_attributes = dao.build_settings_key_and_attribute(GROUP);
+        keys_and_attributes.push(KeyAndAttribute {
+            key: setting,
+            attribute: value,
+        });
+        dynamodb_client
+            .put_item_from_keys(TABLE, keys_and_attributes, None, None, Vec::new())
+            .await
+            .unwrap();
+
+        dao.get_settings(GROUP).await
+    }
+
+    fn weights(name: &str, weight: &str) -> AttributeValue {
+        AttributeValue::M(HashMap::from([(
+            name.to_owned(),
+            AttributeValue::N(weight.to_owned()),
+        )]))
+    }
+
+    #[tokio::test]
+    async fn selection_weights_replace_the_defaults() {
+        let settings = get_settings_with(SELECTION_WEIGHTS, weights("Love", "4.5"))
+            .await
+            .unwrap();
+
+        assert_eq!(settings.selection_weights.reactions.get("Love"), Some(&4.5));
+    }
+
+    #[tokio::test]
+    async fn negative_or_non_finite_selection_weights_are_rejected() {
+        for weight in ["-1", "NaN", "inf"] {
+            let result =
+                get_settings_with(SELECTION_WEIGHTS, weights(FAVORITE_WEIGHT, weight)).await;
+
+            assert!(result.is_err(), "{} should be rejected", weight);
+        }
+    }
+
+    #[tokio::test]
+    async fn non_positive_selection_history_days_are_rejected() {
+        for days in ["0", "-30"] {
+            let result =
+                get_settings_with(SELECTION_HISTORY_DAYS, AttributeValue::N(days.to_owned())).await;
+
+            assert!(result.is_err(), "{} should be rejected", days);
+        }
+    }
+
+    #[test]
+    fn group_names_cannot_contain_key_separators() {
+        assert!(is_valid_group_name("discord"));
+        assert!(!is_valid_group_name(""));
+        assert!(!is_valid_group_name("my_group"));
+        assert!(!is_valid_group_name("my/group"));
+    }
+}
@@ -1,18 +1,28 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::num::ParseIntError;
 
-use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoDbClient};
+use aws_sdk_dynamodb::{
+    types::{AttributeValue, ReturnValue},
+    Client as DynamoDbClient,
+};
 use aws_sdk_s3::types::Object;
 use chrono::{Duration, NaiveDate, ParseError};
-use tracing::{info, instrument};
+use futures::TryStreamExt;
+use tracing::{info, instrument, warn};
 
-use crate::aws_sdk::aws_dynamodb::{DynamoDbUtil, DynamoDbUtilError, KeyAndAttribute, PK};
+use crate::aws_sdk::aws_dynamodb::{
+    DynamoDbUtil, DynamoDbUtilError, KeyAndAttribute, PartitionQuery, SortKeyCondition,
+    TransactUpdate, PK,
+};
+use crate::persistence::group_dao::DEFAULT_RECAP_PERIOD_DAYS;
 
 // Structs
-pub struct ImageDynamoDao<'a> {
+pub struct ImageDynamoDao<'a, D = DynamoDbClient> {
     pub table_name: &'a str,
     pub primary_key: &'a str,
     pub sort_key: &'a str,
-    pub dynamodb_client: &'a DynamoDbClient,
+    pub dynamodb_client: &'a D,
 }
 
 #[derive(Debug, Eq, Hash, PartialEq)]
@@ -21,6 +31,38 @@ pub struct Image {
     pub get_recents: bool,
     pub days_until_get_recents: i64,
     pub date: NaiveDate,
+    /// Picked by an admin instead of the daily setup
+    pub pinned: bool,
+}
+
+///
+/// A page of the images that have already been shown, ordered from the most recent date backwards
+///
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImagePage {
+    pub images: Vec<Image>,
+    /// The date to read the next page before. None once there are no older images, though a page ending
+    /// on the oldest image can still have one that leads to an empty page
+    pub next_cursor: Option<NaiveDate>,
+}
+
+// The attributes written to an Image record. Attributes that are None are left as they are
+struct ImageUpdate<'a> {
+    object_key: &'a str,
+    get_recents_and_days_until: Option<(bool, i64)>,
+    pinned: Option<bool>,
+}
+
+///
+/// Every date an image has been set for. Images that have never been set don't have a history
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageHistory {
+    pub object_key: String,
+    pub first_shown: NaiveDate,
+    pub last_shown: NaiveDate,
+    pub times_shown: i64,
+    pub dates: BTreeSet<NaiveDate>,
 }
 
 // Error Enum
@@ -30,6 +72,12 @@ pub enum ImageDynamoDaoError {
     AttributeValueConversionError(AttributeValue),
     ChronoParseError(ParseError),
     ParseIntError(ParseIntError),
+    PartialImages {
+        images: Vec<Image>,
+        unprocessed_count: usize,
+    },
+    /// The date already has an image and it wasn't forced. Holds the key of the image that is set
+    ImageAlreadySet(String),
     LocalError(String),
 }
 
@@ -68,22 +116,43 @@ const OBJECT_KEY: &str = "object_key";
 const GET_RECENTS: &str = "get_recents";
 const DAYS_UNTIL_GET_RECENTS: &str = "days_until_get_recents";
 const IMAGE: &str = "Image";
+const IMAGE_HISTORY: &str = "ImageHistory";
+const IMAGE_DATES: &str = "ImageDates";
+const DATES: &str = "dates";
+const ARCHIVE: &str = "Archive";
+const ARCHIVED: &str = "archived";
+const PINNED: &str = "pinned";
+const MAX_WRITE_HISTORY_ATTEMPTS: usize = 3;
 
-const DAYS_BETWEEN_GET_RECENTS: i64 = 5;
+// Only used for records missing the value, which every record written by set_image has
+const DEFAULT_DAYS_UNTIL_GET_RECENTS: i64 = DEFAULT_RECAP_PERIOD_DAYS - 1;
+// How many days are read at a time when reading back through every image
+const HISTORY_PAGE_DAYS: i64 = 100;
 
-impl ImageDynamoDao<'_> {
+impl<D: DynamoDbUtil> ImageDynamoDao<'_, D> {
+    ///
+    /// Gets the image set for the provided date.
+    ///
+    /// # Returns
+    /// * `Ok(Option<Image>)` - The image set for the date. None if the date hasn't been set
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
     #[instrument(skip_all)]
     pub async fn get_image(
         &self,
         group: &str,
         date: NaiveDate,
-    ) -> Result<Image, ImageDynamoDaoError> {
+    ) -> Result<Option<Image>, ImageDynamoDaoError> {
         let get_keys_and_attributes = self.build_get_image_key_and_attribute(group, date);
 
-        let item = self
+        let item = match self
             .dynamodb_client
             .get_item_from_keys(self.table_name, get_keys_and_attributes)
-            .await?;
+            .await?
+        {
+            Some(item) => item,
+            None => return Ok(None),
+        };
 
         let object_key = item
             .get(OBJECT_KEY)
@@ -98,13 +167,7 @@ impl ImageDynamoDao<'_> {
                 .is_ok_and(|att_val| att_val.to_owned())
         });
 
-        let days_until_get_recents =
-            item.get(DAYS_UNTIL_GET_RECENTS)
-                .map_or(5, |days_until_get_recents| {
-                    days_until_get_recents
-                        .as_n()
-                        .map_or(5, |att_val| att_val.parse::<i64>().unwrap_or(5))
-                });
+        let days_until_get_recents = parse_days_until_get_recents(&item);
 
         let date = item
             .get(PK)
@@ -112,23 +175,26 @@ impl ImageDynamoDao<'_> {
                 parse_date_from_primary_key(pk, NaiveDate::from_ymd_opt(2099, 12, 31).unwrap())
             });
 
-        Ok(Image {
+        Ok(Some(Image {
             object_key,
             get_recents,
             days_until_get_recents,
             date,
-        })
+            pinned: is_pinned(&item),
+        }))
     }
 
     ///
-    /// Given a date get the last five images from previous days not including the provided date.
-    /// Returns a set of S3 keys represented as strings
+    /// Given a date get the images that make up its recap, which are the images from the days since the
+    /// previous recap not including the provided date.
     ///
     /// # Arguments
     /// * `date` - Date represing the date to count backwards from
+    /// * `recap_period_days` - How many days there are between recaps
     ///
     /// # Returns
-    /// * `Ok(HashSet<Image>)` - Returns a HashSet of Image structs
+    /// * `Ok(Vec<Image>)` - Returns the images ordered from the most recent day backwards
+    /// * `Error(ImageDynamoDaoError::PartialImages)` - Some days couldn't be fetched. Holds the images that were
     /// * `Error(ImageDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
     ///
     #[instrument(skip_all)]
@@ -136,47 +202,132 @@ impl ImageDynamoDao<'_> {
         &self,
         group: &str,
         date: NaiveDate,
+        recap_period_days: i64,
     ) -> Result<Vec<Image>, ImageDynamoDaoError> {
-        let batch_get_keys_and_attributes = self.build_get_recents_key_and_attribute(group, date);
-
-        // Create set of items that get returned. Short circuit for any error thrown
         let generated_set = self
-            .dynamodb_client
-            .batch_get_item_from_keys(self.table_name, batch_get_keys_and_attributes)
-            .await?
-            .iter()
-            .map(|key_and_vals| {
-                Image {
-                    object_key: match key_and_vals.get(OBJECT_KEY) {
-                        Some(value) => value.as_s().unwrap_or(&"".to_owned()).to_owned(),
-                        None => "".to_owned(), // Including "" in the list is fine as there will be no actual object keys of ""
-                    },
-                    get_recents: match key_and_vals.get(GET_RECENTS) {
-                        Some(value) => value.as_bool().unwrap_or(&false).to_owned(),
-                        None => false,
-                    },
-                    date: match key_and_vals.get(PK) {
-                        Some(value) => parse_date_from_primary_key(
-                            value,
-                            NaiveDate::from_ymd_opt(2099, 12, 31).unwrap(),
-                        ),
-                        None => NaiveDate::from_ymd_opt(2099, 12, 31).unwrap(),
-                    },
-                    days_until_get_recents: match key_and_vals.get(DAYS_UNTIL_GET_RECENTS) {
-                        Some(value) => value
-                            .as_n()
-                            .map_or(5, |att_val| att_val.parse::<i64>().unwrap_or(5)),
-                        None => 5,
-                    },
-                }
-            })
-            .collect::<Vec<Image>>();
+            .get_images_before(group, date, recap_period_days - 1)
+            .await?;
 
         info!(set = ?generated_set, "The set of recent keys: ");
 
         Ok(generated_set)
     }
 
+    ///
+    /// Given a date get every image set before it, reading back a page of days at a time until a page
+    /// has no images.
+    ///
+    /// # Arguments
+    /// * `date` - Date represing the date to count backwards from
+    ///
+    /// # Returns
+    /// * `Ok(Vec<Image>)` - Returns the images ordered from the most recent day backwards
+    /// * `Error(ImageDynamoDaoError::PartialImages)` - Some days couldn't be fetched. Holds the images that were
+    /// * `Error(ImageDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_all_images_before(
+        &self,
+        group: &str,
+        date: NaiveDate,
+    ) -> Result<Vec<Image>, ImageDynamoDaoError> {
+        let mut images = Vec::new();
+        let mut page_date = date;
+        loop {
+            let mut page = match self
+                .get_images_before(group, page_date, HISTORY_PAGE_DAYS)
+                .await
+            {
+                Ok(page) => page,
+                Err(ImageDynamoDaoError::PartialImages {
+                    images: page,
+                    unprocessed_count,
+                }) => {
+                    images.extend(page);
+                    return Err(ImageDynamoDaoError::PartialImages {
+                        images,
+                        unprocessed_count,
+                    });
+                }
+                Err(err) => return Err(err),
+            };
+
+            if page.is_empty() {
+                break;
+            }
+
+            images.append(&mut page);
+            page_date -= Duration::days(HISTORY_PAGE_DAYS);
+        }
+
+        info!("Found {} images before {}", images.len(), date);
+
+        Ok(images)
+    }
+
+    ///
+    /// Given a date get the images set on each of the previous `days` days not including the provided date.
+    /// Days without an image are skipped.
+    ///
+    /// # Arguments
+    /// * `date` - Date represing the date to count backwards from
+    /// * `days` - How many days to count backwards
+    ///
+    /// # Returns
+    /// * `Ok(Vec<Image>)` - Returns the images ordered from the most recent day backwards
+    /// * `Error(ImageDynamoDaoError::PartialImages)` - Some days couldn't be fetched. Holds the images that were
+    /// * `Error(ImageDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_images_before(
+        &self,
+        group: &str,
+        date: NaiveDate,
+        days: i64,
+    ) -> Result<Vec<Image>, ImageDynamoDaoError> {
+        let batch_get_keys_and_attributes =
+            self.build_get_images_before_key_and_attribute(group, date, days);
+
+        // Keys that couldn't be fetched are surfaced alongside the images that were fetched so
+        // callers can decide whether that's good enough
+        match self
+            .dynamodb_client
+            .batch_get_item_from_keys(self.table_name, batch_get_keys_and_attributes)
+            .await
+        {
+            Ok(items) => Ok(items.iter().map(build_image_from_item).collect()),
+            Err(DynamoDbUtilError::BatchGetItemPartialFailure {
+                items,
+                unprocessed_keys,
+            }) => Err(ImageDynamoDaoError::PartialImages {
+                images: items.iter().map(build_image_from_item).collect(),
+                unprocessed_count: unprocessed_keys.len(),
+            }),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    ///
+    /// Sets the image for the provided date and records the date in the image's history. A date that
+    /// already has an image is left alone unless `force` is set, so retries never change an image people
+    /// may have already seen. When forced, the date is taken out of the replaced image's history.
+    ///
+    /// The image record and the histories are written in a single transaction that is conditioned on what
+    /// was read, so the histories always match the image records. If either changes in between (e.g. the
+    /// same image being set for two dates at once) they are read again and retried.
+    ///
+    /// # Arguments
+    /// * `object` - The object being set as the image for the date
+    /// * `date` - The date the image is set for
+    /// * `days_since_get_recents` - How many days it has been since the last recap. 0 makes this date a recap
+    /// * `recap_period_days` - How many days there are between recaps
+    /// * `force` - Replace the image if the date already has one
+    ///
+    /// # Returns
+    /// * `Ok(String)` - The object key that was set
+    /// * `Error(ImageDynamoDaoError::ImageAlreadySet)` - The date already has an image and `force` wasn't set
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
     #[instrument(skip_all)]
     pub async fn set_image(
         &self,
@@ -184,6 +335,8 @@ impl ImageDynamoDao<'_> {
         object: Object,
         date: NaiveDate,
         days_since_get_recents: i64,
+        recap_period_days: i64,
+        force: bool,
     ) -> Result<String, ImageDynamoDaoError> {
         let object_key = object
             .key()
@@ -191,7 +344,7 @@ impl ImageDynamoDao<'_> {
 
         let get_recents = days_since_get_recents == 0;
 
-        let days_until_get_recents = (DAYS_BETWEEN_GET_RECENTS + 1) - days_since_get_recents;
+        let days_until_get_recents = recap_period_days - days_since_get_recents;
 
         info!(date = ?date, object_key = object_key, "Writing object as the record for date: ");
         info!(
@@ -199,32 +352,606 @@ impl ImageDynamoDao<'_> {
             "Days until fetch recents: "
         );
 
-        let keys_and_attributes = self.build_set_image_key_and_attribute(
+        self.write_image(
             group,
             date,
-            object_key,
-            get_recents,
-            days_until_get_recents,
+            ImageUpdate {
+                object_key,
+                get_recents_and_days_until: Some((get_recents, days_until_get_recents)),
+                pinned: None,
+            },
+            force,
+        )
+        .await
+    }
+
+    ///
+    /// Pins the image to the provided date, replacing any image already set for it. The daily setup keeps
+    /// pinned images and only fills in when the recap is due once it reaches the date.
+    ///
+    /// # Arguments
+    /// * `object` - The object being pinned
+    /// * `date` - The date the image is pinned to
+    ///
+    /// # Returns
+    /// * `Ok(String)` - The object key that was pinned
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn pin_image(
+        &self,
+        group: &str,
+        object: Object,
+        date: NaiveDate,
+    ) -> Result<String, ImageDynamoDaoError> {
+        let object_key = object
+            .key()
+            .ok_or_else(|| "Provided object's key does not exist".to_owned())?;
+
+        info!(date = ?date, object_key = object_key, "Pinning object as the record for date: ");
+
+        self.write_image(
+            group,
+            date,
+            ImageUpdate {
+                object_key,
+                get_recents_and_days_until: None,
+                pinned: Some(true),
+            },
+            true,
+        )
+        .await
+    }
+
+    ///
+    /// Gets the history of every image that has been shown in the group. Histories are stored per image in
+    /// their own partition (pk "{group}_ImageHistory", sk "{object_key}").
+    ///
+    /// # Returns
+    /// * `Ok(Vec<ImageHistory>)` - The histories ordered from the most recently shown backwards
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_image_histories(
+        &self,
+        group: &str,
+    ) -> Result<Vec<ImageHistory>, ImageDynamoDaoError> {
+        let items = self
+            .dynamodb_client
+            .query_all_by_partition(
+                self.table_name,
+                PartitionQuery::new(KeyAndAttribute {
+                    key: self.primary_key,
+                    attribute: AttributeValue::S(format_history_primary_key(group)),
+                }),
+            )
+            .await?;
+
+        let mut histories = items
+            .iter()
+            .map(|item| build_image_history_from_item(item, self.sort_key))
+            .collect::<Result<Vec<Option<ImageHistory>>, ImageDynamoDaoError>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<ImageHistory>>();
+        histories.sort_by_key(|history| Reverse(history.last_shown));
+
+        info!("Found the history of {} images", histories.len());
+
+        Ok(histories)
+    }
+
+    ///
+    /// Records the provided images in their histories and the date index. Used to build both from the
+    /// images that were set before they were kept. Dates already in a history are left as they are.
+    ///
+    /// # Arguments
+    /// * `images` - The images to record, e.g. from `get_all_images_before`
+    ///
+    /// # Returns
+    /// * `Ok(())` - Every image was recorded
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn backfill_image_histories(
+        &self,
+        group: &str,
+        images: &[Image],
+    ) -> Result<(), ImageDynamoDaoError> {
+        let mut dates_by_object_key: HashMap<&str, BTreeSet<NaiveDate>> = HashMap::new();
+        for image in images.iter().filter(|image| !image.object_key.is_empty()) {
+            dates_by_object_key
+                .entry(&image.object_key)
+                .or_default()
+                .insert(image.date);
+        }
+
+        info!(
+            "Backfilling the history of {} images from {} days",
+            dates_by_object_key.len(),
+            images.len()
+        );
+
+        self.write_image_dates(
+            group,
+            images
+                .iter()
+                .filter(|image| !image.object_key.is_empty())
+                .map(|image| (image.date, image.object_key.as_str())),
+        )
+        .await?;
+
+        for (object_key, new_dates) in dates_by_object_key {
+            let mut attempts = 0;
+            loop {
+                attempts += 1;
+
+                let stored_history = self.get_image_history(group, object_key).await?;
+                let mut dates = stored_history
+                    .as_ref()
+                    .map(|history| history.dates.to_owned())
+                    .unwrap_or_default();
+                if new_dates.is_subset(&dates) {
+                    break;
+                }
+                dates.extend(&new_dates);
+
+                let transact_result = self
+                    .dynamodb_client
+                    .transact_update_items_with_keys(
+                        self.table_name,
+                        vec![self.build_write_history_update(
+                            group,
+                            object_key,
+                            stored_history.as_ref(),
+                            &dates,
+                        )],
+                    )
+                    .await;
+
+                match transact_result {
+                    Ok(()) => break,
+                    Err(DynamoDbUtilError::ConditionalCheckFailure(reason))
+                        if attempts < MAX_WRITE_HISTORY_ATTEMPTS =>
+                    {
+                        warn!(
+                            reason = reason,
+                            "The history changed while it was being backfilled. Trying again"
+                        );
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Adds the dates in the provided histories that are missing from the date index, e.g. the dates of
+    /// groups whose histories were kept before the date index was.
+    ///
+    /// # Arguments
+    /// * `histories` - The group's image histories, e.g. from `get_image_histories`
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - How many dates were added to the date index
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn backfill_image_dates(
+        &self,
+        group: &str,
+        histories: &[ImageHistory],
+    ) -> Result<usize, ImageDynamoDaoError> {
+        let items = self
+            .dynamodb_client
+            .query_all_by_partition(self.table_name, self.build_image_dates_query(group))
+            .await?;
+        let indexed_dates = self
+            .parse_image_dates(&items)?
+            .into_iter()
+            .collect::<BTreeSet<NaiveDate>>();
+
+        let missing_dates = histories
+            .iter()
+            .flat_map(|history| {
+                history
+                    .dates
+                    .iter()
+                    .map(|date| (*date, history.object_key.as_str()))
+            })
+            .filter(|(date, _)| !indexed_dates.contains(date))
+            .collect::<Vec<(NaiveDate, &str)>>();
+
+        if !missing_dates.is_empty() {
+            info!(
+                "Backfilling {} dates missing from the date index",
+                missing_dates.len()
+            );
+        }
+
+        self.write_image_dates(group, missing_dates.iter().copied())
+            .await?;
+
+        Ok(missing_dates.len())
+    }
+
+    ///
+    /// Archives or unarchives an image. Archived images stay in the bucket but are never picked as the
+    /// daily image. Archive markers are stored per image in their own partition (pk "{group}_Archive",
+    /// sk "{object_key}").
+    ///
+    /// # Arguments
+    /// * `object_key` - The key of the image being archived or unarchived
+    /// * `archived` - Whether the image should be archived
+    ///
+    /// # Returns
+    /// * `Ok(bool)` - Whether the image was archived before
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn set_archived(
+        &self,
+        group: &str,
+        object_key: &str,
+        archived: bool,
+    ) -> Result<bool, ImageDynamoDaoError> {
+        info!(
+            group = group,
+            object_key = object_key,
+            archived = archived,
+            "Setting whether the image is archived"
         );
 
-        // TODO: Consider adding a conditional expressions for dates that haven't happened yet?
-        // I would say for any dates but its fine to overwrite a date for tomorrow so that's where
-        // I'm coming from
-        let _put_result = self
+        let old_item = self
             .dynamodb_client
-            .put_item_from_keys(self.table_name, keys_and_attributes)
+            .update_item_with_keys(
+                self.table_name,
+                self.build_archive_key_and_attribute(group, object_key),
+                format!("SET {} = :archived", ARCHIVED),
+                ReturnValue::AllOld,
+                None,
+                vec![KeyAndAttribute {
+                    key: ":archived",
+                    attribute: AttributeValue::Bool(archived),
+                }],
+            )
             .await?;
 
-        Ok(object_key.to_owned())
+        // There are no old attributes when the image had never been archived
+        Ok(old_item.is_some_and(|old_item| is_archived(&old_item)))
+    }
+
+    ///
+    /// Gets the keys of every archived image in the group.
+    ///
+    /// # Returns
+    /// * `Ok(HashSet<String>)` - The keys of the archived images
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_archived_object_keys(
+        &self,
+        group: &str,
+    ) -> Result<HashSet<String>, ImageDynamoDaoError> {
+        let items = self
+            .dynamodb_client
+            .query_all_by_partition(
+                self.table_name,
+                PartitionQuery::new(KeyAndAttribute {
+                    key: self.primary_key,
+                    attribute: AttributeValue::S(format_archive_primary_key(group)),
+                }),
+            )
+            .await?;
+
+        let archived_object_keys = items
+            .iter()
+            .filter(|item| is_archived(item))
+            .filter_map(|item| item.get(self.sort_key))
+            .map(|object_key| {
+                object_key
+                    .as_s()
+                    .map(|object_key| object_key.to_owned())
+                    .map_err(|att_val| att_val.to_owned().into())
+            })
+            .collect::<Result<HashSet<String>, ImageDynamoDaoError>>()?;
+
+        info!("Found {} archived images", archived_object_keys.len());
+
+        Ok(archived_object_keys)
+    }
+
+    ///
+    /// Gets the images set for the dates after the provided date, i.e. the queue of upcoming images. The
+    /// dates are read from the group's date index so pinned dates far past the rest of the queue are included.
+    ///
+    /// # Arguments
+    /// * `date` - The date to get the images after, usually today
+    ///
+    /// # Returns
+    /// * `Ok(Vec<Image>)` - The upcoming images ordered from the soonest date forwards
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_upcoming_images(
+        &self,
+        group: &str,
+        date: NaiveDate,
+    ) -> Result<Vec<Image>, ImageDynamoDaoError> {
+        let items = self
+            .dynamodb_client
+            .query_all_by_partition(
+                self.table_name,
+                self.build_image_dates_query(group).sort_key_condition(
+                    self.sort_key,
+                    SortKeyCondition::GreaterThan(format_date(date)),
+                ),
+            )
+            .await?;
+        let upcoming_dates = self.parse_image_dates(&items)?;
+
+        let mut images = self.get_images_for_dates(group, &upcoming_dates).await?;
+        images.sort_by_key(|image| image.date);
+
+        info!("Found {} upcoming images after {}", images.len(), date);
+
+        Ok(images)
+    }
+
+    ///
+    /// Gets a page of the images set before the provided date. The dates are read backwards from the
+    /// group's date index, so long gaps between images don't need to be read day by day.
+    ///
+    /// # Arguments
+    /// * `before` - The date to get the images before, e.g. tomorrow for a page starting with today
+    /// * `limit` - The most images to return
+    ///
+    /// # Returns
+    /// * `Ok(ImagePage)` - The images ordered from the most recent date backwards
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_image_page(
+        &self,
+        group: &str,
+        before: NaiveDate,
+        limit: usize,
+    ) -> Result<ImagePage, ImageDynamoDaoError> {
+        let page = self
+            .dynamodb_client
+            .query_by_partition(
+                self.table_name,
+                self.build_image_dates_query(group)
+                    .sort_key_condition(
+                        self.sort_key,
+                        SortKeyCondition::LessThan(format_date(before)),
+                    )
+                    .scan_index_forward(false)
+                    .limit(i32::try_from(limit).unwrap_or(i32::MAX)),
+            )
+            .try_next()
+            .await?;
+
+        let (items, last_evaluated_key) = match page {
+            Some(page) => (page.items, page.last_evaluated_key),
+            None => (Vec::new(), None),
+        };
+        let page_dates = self.parse_image_dates(&items)?;
+
+        // The next page starts before the last date read, as long as DynamoDB stopped short of the end
+        let next_cursor = match last_evaluated_key {
+            Some(last_evaluated_key) => Some(self.parse_image_date(&last_evaluated_key)?),
+            None => None,
+        };
+
+        let mut images = self.get_images_for_dates(group, &page_dates).await?;
+        images.sort_by_key(|image| Reverse(image.date));
+
+        info!(
+            "Found {} images before {}. The next page is before {:?}",
+            images.len(),
+            before,
+            next_cursor
+        );
+
+        Ok(ImagePage {
+            images,
+            next_cursor,
+        })
+    }
+
+    ///
+    /// Shuffles the images between the dates of the queue. Unlike setting the images, every date keeps its
+    /// recap and whether it was pinned.
+    ///
+    /// Every image record, date index entry and history involved is written in a single transaction. Each
+    /// date is conditioned on still having the image from `queue`, so if the queue changed since it was read
+    /// nothing is written and `ConditionalCheckFailure` is returned.
+    ///
+    /// # Arguments
+    /// * `queue` - The queued images as they were read, e.g. from `get_upcoming_images`
+    /// * `object_keys` - The key of the image for each date of the queue, in the same order as `queue`
+    ///
+    /// # Returns
+    /// * `Ok(())` - The queue was reordered
+    /// * `Error(ImageDynamoDaoError)` - Any failure that occurs when calling DynamoDb or parsing the output
+    ///
+    #[instrument(skip_all)]
+    pub async fn reorder_images(
+        &self,
+        group: &str,
+        queue: &[Image],
+        object_keys: &[String],
+    ) -> Result<(), ImageDynamoDaoError> {
+        let moves = queue
+            .iter()
+            .zip(object_keys)
+            .filter(|(image, object_key)| image.object_key != **object_key)
+            .collect::<Vec<(&Image, &String)>>();
+
+        if moves.is_empty() {
+            return Ok(());
+        }
+
+        info!("Moving the images of {} dates", moves.len());
+
+        let mut updates = Vec::new();
+        let mut moved_dates: HashMap<&str, (BTreeSet<NaiveDate>, BTreeSet<NaiveDate>)> =
+            HashMap::new();
+        for (image, object_key) in &moves {
+            updates.push(self.build_set_image_update(
+                group,
+                image.date,
+                &ImageUpdate {
+                    object_key,
+                    get_recents_and_days_until: None,
+                    pinned: None,
+                },
+                Some(&image.object_key),
+            ));
+            updates.push(self.build_write_image_date_update(group, image.date, object_key));
+
+            moved_dates
+                .entry(&image.object_key)
+                .or_default()
+                .0
+                .insert(image.date);
+            moved_dates
+                .entry(object_key)
+                .or_default()
+                .1
+                .insert(image.date);
+        }
+
+        // Each image's history loses the dates it was moved off of and gains the ones it was moved to
+        for (object_key, (removed_dates, added_dates)) in moved_dates {
+            let stored_history = self.get_image_history(group, object_key).await?;
+            let mut dates = stored_history
+                .as_ref()
+                .map(|history| history.dates.to_owned())
+                .unwrap_or_default();
+            dates.retain(|date| !removed_dates.contains(date));
+            dates.extend(added_dates);
+
+            updates.push(self.build_write_history_update(
+                group,
+                object_key,
+                stored_history.as_ref(),
+                &dates,
+            ));
+        }
+
+        self.dynamodb_client
+            .transact_update_items_with_keys(self.table_name, updates)
+            .await?;
+
+        Ok(())
     }
 
     /** Helper Functions that require state */
+    ///
+    /// Writes the image record, its entry in the date index and the histories of the images involved in a
+    /// single transaction conditioned on what was read, retrying if either changes in between.
+    ///
+    async fn write_image(
+        &self,
+        group: &str,
+        date: NaiveDate,
+        image_update: ImageUpdate<'_>,
+        force: bool,
+    ) -> Result<String, ImageDynamoDaoError> {
+        let object_key = image_update.object_key;
+
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+
+            let stored_object_key = self
+                .get_image(group, date)
+                .await?
+                .map(|image| image.object_key);
+            if let (Some(stored_object_key), false) = (&stored_object_key, force) {
+                info!(
+                    stored_object_key = stored_object_key,
+                    "The date already has an image. Leaving it as is"
+                );
+                return Err(ImageDynamoDaoError::ImageAlreadySet(
+                    stored_object_key.to_owned(),
+                ));
+            }
+
+            let mut updates = vec![
+                self.build_set_image_update(
+                    group,
+                    date,
+                    &image_update,
+                    stored_object_key.as_deref(),
+                ),
+                self.build_write_image_date_update(group, date, object_key),
+            ];
+
+            // Setting the same image again leaves the histories as they are
+            if stored_object_key.as_deref() != Some(object_key) {
+                let stored_history = self.get_image_history(group, object_key).await?;
+                let mut dates = stored_history
+                    .as_ref()
+                    .map(|history| history.dates.to_owned())
+                    .unwrap_or_default();
+                dates.insert(date);
+                updates.push(self.build_write_history_update(
+                    group,
+                    object_key,
+                    stored_history.as_ref(),
+                    &dates,
+                ));
+
+                // Images set before histories were kept don't have the date in their history
+                if let Some(replaced_object_key) = stored_object_key.as_deref() {
+                    if let Some(replaced_history) = self
+                        .get_image_history(group, replaced_object_key)
+                        .await?
+                        .filter(|history| history.dates.contains(&date))
+                    {
+                        let mut dates = replaced_history.dates.to_owned();
+                        dates.remove(&date);
+                        updates.push(self.build_write_history_update(
+                            group,
+                            replaced_object_key,
+                            Some(&replaced_history),
+                            &dates,
+                        ));
+                    }
+                }
+            }
+
+            let transact_result = self
+                .dynamodb_client
+                .transact_update_items_with_keys(self.table_name, updates)
+                .await;
+
+            match transact_result {
+                Ok(()) => return Ok(object_key.to_owned()),
+                Err(DynamoDbUtilError::ConditionalCheckFailure(reason))
+                    if attempts < MAX_WRITE_HISTORY_ATTEMPTS =>
+                {
+                    warn!(
+                        reason = reason,
+                        "The image or its history changed while it was being set. Trying again"
+                    );
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
     #[instrument(skip_all)]
     fn build_get_image_key_and_attribute(
         &self,
         group: &str,
         date: NaiveDate,
-    ) -> Vec<KeyAndAttribute> {
+    ) -> Vec<KeyAndAttribute<'_>> {
         info!(date = ?date, group = group, "Date and group are");
 
         vec![
@@ -240,15 +967,16 @@ impl ImageDynamoDao<'_> {
     }
 
     #[instrument(skip_all)]
-    fn build_get_recents_key_and_attribute(
+    fn build_get_images_before_key_and_attribute(
         &self,
         group: &str,
         date: NaiveDate,
-    ) -> Vec<Vec<KeyAndAttribute>> {
+        days: i64,
+    ) -> Vec<Vec<KeyAndAttribute<'_>>> {
         info!(date = ?date, group = group, "Date and group are: ");
 
         let mut key_and_attribute: Vec<Vec<KeyAndAttribute>> = Vec::<Vec<KeyAndAttribute>>::new();
-        for num in 1..=DAYS_BETWEEN_GET_RECENTS {
+        for num in 1..=days {
             let date = date - Duration::days(num);
 
             info!(prev_date = ?date, "Next date is.");
@@ -267,50 +995,754 @@ impl ImageDynamoDao<'_> {
         key_and_attribute
     }
 
-    #[instrument(skip_all)]
-    fn build_set_image_key_and_attribute(
+    async fn get_image_history(
+        &self,
+        group: &str,
+        object_key: &str,
+    ) -> Result<Option<ImageHistory>, ImageDynamoDaoError> {
+        let item = self
+            .dynamodb_client
+            .get_item_from_keys(
+                self.table_name,
+                self.build_image_history_key_and_attribute(group, object_key),
+            )
+            .await?;
+
+        match item {
+            Some(item) => build_image_history_from_item(&item, self.sort_key),
+            None => Ok(None),
+        }
+    }
+
+    fn build_set_image_update(
         &self,
         group: &str,
         date: NaiveDate,
+        image_update: &ImageUpdate,
+        stored_object_key: Option<&str>,
+    ) -> TransactUpdate<'_> {
+        info!(date = ?date, group = group, object = image_update.object_key, "The day, group and object_key are: ");
+
+        let mut update_expression = "SET object_key = :object_key".to_owned();
+        let mut expression_attribute_values = vec![KeyAndAttribute {
+            key: ":object_key",
+            attribute: AttributeValue::S(image_update.object_key.to_owned()),
+        }];
+
+        if let Some((get_recents, days_until_get_recents)) = image_update.get_recents_and_days_until
+        {
+            update_expression.push_str(
+                ", get_recents = :get_recents, days_until_get_recents = :days_until_get_recents",
+            );
+            expression_attribute_values.extend([
+                KeyAndAttribute {
+                    key: ":get_recents",
+                    attribute: AttributeValue::Bool(get_recents),
+                },
+                KeyAndAttribute {
+                    key: ":days_until_get_recents",
+                    attribute: AttributeValue::N(days_until_get_recents.to_string()),
+                },
+            ]);
+        }
+
+        if let Some(pinned) = image_update.pinned {
+            update_expression.push_str(", pinned = :pinned");
+            expression_attribute_values.push(KeyAndAttribute {
+                key: ":pinned",
+                attribute: AttributeValue::Bool(pinned),
+            });
+        }
+
+        // Only succeeds if the date still has the image that was read
+        let condition_expression = match stored_object_key {
+            Some(stored_object_key) => {
+                expression_attribute_values.push(KeyAndAttribute {
+                    key: ":stored_object_key",
+                    attribute: AttributeValue::S(stored_object_key.to_owned()),
+                });
+                "object_key = :stored_object_key"
+            }
+            None => "attribute_not_exists(object_key)",
+        };
+
+        TransactUpdate {
+            keys_and_attributes: self.build_get_image_key_and_attribute(group, date),
+            update_expression,
+            condition_expression: Some(condition_expression.to_owned()),
+            expression_attribute_names: None,
+            expression_attribute_values,
+        }
+    }
+
+    ///
+    /// Replaces the dates in an image's history, conditioned on the dates that were read. DynamoDB
+    /// doesn't allow empty sets so a history without any dates has them removed instead.
+    ///
+    fn build_write_history_update(
+        &self,
+        group: &str,
         object_key: &str,
-        get_recents: bool,
-        days_until_get_recents: i64,
-    ) -> Vec<KeyAndAttribute> {
-        info!(date = ?date, group = group, object = object_key, "The day, group and object_key are: ");
+        stored_history: Option<&ImageHistory>,
+        dates: &BTreeSet<NaiveDate>,
+    ) -> TransactUpdate<'_> {
+        let mut expression_attribute_values = vec![KeyAndAttribute {
+            key: ":times_shown",
+            attribute: AttributeValue::N(dates.len().to_string()),
+        }];
+
+        let update_expression = match (dates.first(), dates.last()) {
+            (Some(first_shown), Some(last_shown)) => {
+                expression_attribute_values.extend([
+                    KeyAndAttribute {
+                        key: ":dates",
+                        attribute: format_dates(dates),
+                    },
+                    KeyAndAttribute {
+                        key: ":first_shown",
+                        attribute: AttributeValue::S(first_shown.format("%Y-%m-%d").to_string()),
+                    },
+                    KeyAndAttribute {
+                        key: ":last_shown",
+                        attribute: AttributeValue::S(last_shown.format("%Y-%m-%d").to_string()),
+                    },
+                ]);
+                "SET dates = :dates, first_shown = :first_shown, last_shown = :last_shown, times_shown = :times_shown"
+            }
+            _ => "SET times_shown = :times_shown REMOVE dates, first_shown, last_shown",
+        };
+
+        // Only succeeds if the history still has the dates that were read
+        let condition_expression = match stored_history {
+            Some(stored_history) => {
+                expression_attribute_values.push(KeyAndAttribute {
+                    key: ":stored_dates",
+                    attribute: format_dates(&stored_history.dates),
+                });
+                "dates = :stored_dates"
+            }
+            None => "attribute_not_exists(dates)",
+        };
+
+        TransactUpdate {
+            keys_and_attributes: self.build_image_history_key_and_attribute(group, object_key),
+            update_expression: update_expression.to_owned(),
+            condition_expression: Some(condition_expression.to_owned()),
+            expression_attribute_names: None,
+            expression_attribute_values,
+        }
+    }
 
+    fn build_image_history_key_and_attribute(
+        &self,
+        group: &str,
+        object_key: &str,
+    ) -> Vec<KeyAndAttribute<'_>> {
         vec![
             KeyAndAttribute {
                 key: self.primary_key,
-                attribute: AttributeValue::S(format_primary_key(group, date)),
+                attribute: AttributeValue::S(format_history_primary_key(group)),
             },
             KeyAndAttribute {
                 key: self.sort_key,
-                attribute: AttributeValue::S(IMAGE.to_owned()),
-            },
-            KeyAndAttribute {
-                key: OBJECT_KEY,
                 attribute: AttributeValue::S(object_key.to_owned()),
             },
+        ]
+    }
+
+    ///
+    /// Points the date's entry in the date index (pk "{group}_ImageDates", sk "{date}") at the image so
+    /// the dates with images can be read as a range instead of day by day.
+    ///
+    fn build_write_image_date_update(
+        &self,
+        group: &str,
+        date: NaiveDate,
+        object_key: &str,
+    ) -> TransactUpdate<'_> {
+        TransactUpdate {
+            keys_and_attributes: vec![
+                KeyAndAttribute {
+                    key: self.primary_key,
+                    attribute: AttributeValue::S(format_image_dates_primary_key(group)),
+                },
+                KeyAndAttribute {
+                    key: self.sort_key,
+                    attribute: format_date(date),
+                },
+            ],
+            update_expression: "SET object_key = :object_key".to_owned(),
+            condition_expression: None,
+            expression_attribute_names: None,
+            expression_attribute_values: vec![KeyAndAttribute {
+                key: ":object_key",
+                attribute: AttributeValue::S(object_key.to_owned()),
+            }],
+        }
+    }
+
+    ///
+    /// Writes the entries for the provided dates to the date index. Entries only point at the date, so
+    /// writing them again changes nothing.
+    ///
+    async fn write_image_dates(
+        &self,
+        group: &str,
+        dates: impl Iterator<Item = (NaiveDate, &str)>,
+    ) -> Result<(), ImageDynamoDaoError> {
+        for (date, object_key) in dates {
+            let date_update = self.build_write_image_date_update(group, date, object_key);
+            self.dynamodb_client
+                .update_item_with_keys(
+                    self.table_name,
+                    date_update.keys_and_attributes,
+                    date_update.update_expression,
+                    ReturnValue::None,
+                    None,
+                    date_update.expression_attribute_values,
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn build_image_dates_query(&self, group: &str) -> PartitionQuery<'_> {
+        PartitionQuery::new(KeyAndAttribute {
+            key: self.primary_key,
+            attribute: AttributeValue::S(format_image_dates_primary_key(group)),
+        })
+        .projection(vec![self.sort_key])
+    }
+
+    fn parse_image_dates(
+        &self,
+        items: &[HashMap<String, AttributeValue>],
+    ) -> Result<Vec<NaiveDate>, ImageDynamoDaoError> {
+        items
+            .iter()
+            .map(|item| self.parse_image_date(item))
+            .collect()
+    }
+
+    fn parse_image_date(
+        &self,
+        item: &HashMap<String, AttributeValue>,
+    ) -> Result<NaiveDate, ImageDynamoDaoError> {
+        let date = item
+            .get(self.sort_key)
+            .ok_or_else(|| "The date index entry has no date".to_owned())?
+            .as_s()
+            .map_err(|att_val| att_val.to_owned())?;
+
+        Ok(NaiveDate::parse_from_str(date, "%Y-%m-%d")?)
+    }
+
+    ///
+    /// Batch gets the images set for the provided dates. Dates without an image are left out.
+    ///
+    async fn get_images_for_dates(
+        &self,
+        group: &str,
+        dates: &[NaiveDate],
+    ) -> Result<Vec<Image>, ImageDynamoDaoError> {
+        if dates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(self
+            .dynamodb_client
+            .batch_get_item_from_keys(
+                self.table_name,
+                dates
+                    .iter()
+                    .map(|date| self.build_get_image_key_and_attribute(group, *date))
+                    .collect(),
+            )
+            .await?
+            .iter()
+            .filter(|item| item.contains_key(OBJECT_KEY))
+            .map(build_image_from_item)
+            .collect())
+    }
+
+    fn build_archive_key_and_attribute(
+        &self,
+        group: &str,
+        object_key: &str,
+    ) -> Vec<KeyAndAttribute<'_>> {
+        vec![
             KeyAndAttribute {
-                key: GET_RECENTS,
-                attribute: AttributeValue::Bool(get_recents),
+                key: self.primary_key,
+                attribute: AttributeValue::S(format_archive_primary_key(group)),
             },
             KeyAndAttribute {
-                key: DAYS_UNTIL_GET_RECENTS,
-                attribute: AttributeValue::N(days_until_get_recents.to_string()),
+                key: self.sort_key,
+                attribute: AttributeValue::S(object_key.to_owned()),
             },
         ]
     }
 }
 
 // Helper functinos that don't require state
+fn build_image_from_item(key_and_vals: &HashMap<String, AttributeValue>) -> Image {
+    Image {
+        object_key: match key_and_vals.get(OBJECT_KEY) {
+            Some(value) => value.as_s().unwrap_or(&"".to_owned()).to_owned(),
+            None => "".to_owned(), // Including "" in the list is fine as there will be no actual object keys of ""
+        },
+        get_recents: match key_and_vals.get(GET_RECENTS) {
+            Some(value) => value.as_bool().unwrap_or(&false).to_owned(),
+            None => false,
+        },
+        date: match key_and_vals.get(PK) {
+            Some(value) => {
+                parse_date_from_primary_key(value, NaiveDate::from_ymd_opt(2099, 12, 31).unwrap())
+            }
+            None => NaiveDate::from_ymd_opt(2099, 12, 31).unwrap(),
+        },
+        days_until_get_recents: parse_days_until_get_recents(key_and_vals),
+        pinned: is_pinned(key_and_vals),
+    }
+}
+
+///
+/// Builds a history from a history item. Histories of images that were set and then replaced on
+/// every date they were set for have no dates and are treated as never shown.
+///
+fn build_image_history_from_item(
+    item: &HashMap<String, AttributeValue>,
+    sort_key: &str,
+) -> Result<Option<ImageHistory>, ImageDynamoDaoError> {
+    let dates = match item.get(DATES) {
+        Some(dates) => dates
+            .as_ss()
+            .map_err(|att_val| att_val.to_owned())?
+            .iter()
+            .map(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d"))
+            .collect::<Result<BTreeSet<NaiveDate>, ParseError>>()?,
+        None => return Ok(None),
+    };
+
+    let object_key = item
+        .get(sort_key)
+        .ok_or_else(|| "The image history has no object key".to_owned())?
+        .as_s()
+        .map_err(|att_val| att_val.to_owned())?
+        .to_owned();
+
+    let (first_shown, last_shown) = match (dates.first(), dates.last()) {
+        (Some(first_shown), Some(last_shown)) => (*first_shown, *last_shown),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(ImageHistory {
+        object_key,
+        first_shown,
+        last_shown,
+        times_shown: dates.len() as i64,
+        dates,
+    }))
+}
+
 fn format_primary_key(group: &str, date: NaiveDate) -> String {
     format!("{}_{}", group, date.format("%Y-%m-%d"))
 }
 
+fn format_history_primary_key(group: &str) -> String {
+    format!("{}_{}", group, IMAGE_HISTORY)
+}
+
+fn format_image_dates_primary_key(group: &str) -> String {
+    format!("{}_{}", group, IMAGE_DATES)
+}
+
+fn format_date(date: NaiveDate) -> AttributeValue {
+    AttributeValue::S(date.format("%Y-%m-%d").to_string())
+}
+
+fn format_archive_primary_key(group: &str) -> String {
+    format!("{}_{}", group, ARCHIVE)
+}
+
+fn is_pinned(item: &HashMap<String, AttributeValue>) -> bool {
+    item.get(PINNED)
+        .is_some_and(|pinned| pinned.as_bool().is_ok_and(|pinned| *pinned))
+}
+
+fn is_archived(item: &HashMap<String, AttributeValue>) -> bool {
+    item.get(ARCHIVED)
+        .is_some_and(|archived| archived.as_bool().is_ok_and(|archived| *archived))
+}
+
+fn format_dates(dates: &BTreeSet<NaiveDate>) -> AttributeValue {
+    AttributeValue::Ss(
+        dates
+            .iter()
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .collect(),
+    )
+}
+
+fn parse_days_until_get_recents(item: &HashMap<String, AttributeValue>) -> i64 {
+    item.get(DAYS_UNTIL_GET_RECENTS)
+        .and_then(|days_until_get_recents| days_until_get_recents.as_n().ok())
+        .and_then(|days_until_get_recents| days_until_get_recents.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_DAYS_UNTIL_GET_RECENTS)
+}
+
 fn parse_date_from_primary_key(value: &AttributeValue, default_date: NaiveDate) -> NaiveDate {
     let string_value = value.as_s().unwrap_or(&"".to_owned()).to_owned();
     let date_str = string_value.split("_").last().unwrap();
 
     NaiveDate::parse_from_str(date_str, "%Y-%m-%d").unwrap_or(default_date)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws_sdk::in_memory_dynamodb::InMemoryDynamoDb;
+
+    const TABLE: &str = "ImageTable";
+    const GROUP: &str = "discord";
+
+    fn build_dao(dynamodb_client: &InMemoryDynamoDb) -> ImageDynamoDao<'_, InMemoryDynamoDb> {
+        ImageDynamoDao {
+            table_name: TABLE,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client,
+        }
+    }
+
+    fn object(key: &str) -> Object {
+        Object::builder().key(key).build()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    #[tokio::test]
+    async fn set_image_round_trips_with_its_history() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+
+        dao.set_image(GROUP, object("discord/1.png"), date(1), 0, 6, false)
+            .await
+            .unwrap();
+        let image = dao.get_image(GROUP, date(1)).await.unwrap();
+        let histories = dao.get_image_histories(GROUP).await.unwrap();
+
+        assert_eq!(
+            image,
+            Some(Image {
+                object_key: "discord/1.png".to_owned(),
+                get_recents: true,
+                days_until_get_recents: 6,
+                date: date(1),
+                pinned: false,
+            })
+        );
+        assert_eq!(dao.get_image(GROUP, date(2)).await.unwrap(), None);
+        assert_eq!(
+            histories,
+            vec![ImageHistory {
+                object_key: "discord/1.png".to_owned(),
+                first_shown: date(1),
+                last_shown: date(1),
+                times_shown: 1,
+                dates: BTreeSet::from([date(1)]),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn set_image_keeps_an_existing_image_unless_forced() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        dao.set_image(GROUP, object("discord/1.png"), date(1), 0, 6, false)
+            .await
+            .unwrap();
+
+        let result = dao
+            .set_image(GROUP, object("discord/2.png"), date(1), 0, 6, false)
+            .await;
+        dao.set_image(GROUP, object("discord/2.png"), date(1), 0, 6, true)
+            .await
+            .unwrap();
+        let histories = dao.get_image_histories(GROUP).await.unwrap();
+
+        assert!(matches!(
+            result,
+            Err(ImageDynamoDaoError::ImageAlreadySet(object_key)) if object_key == "discord/1.png"
+        ));
+        // Forcing the new image moves the date to its history
+        assert_eq!(
+            histories
+                .iter()
+                .map(|history| (history.object_key.as_str(), history.times_shown))
+                .collect::<Vec<(&str, i64)>>(),
+            vec![("discord/2.png", 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn image_pages_read_backwards_from_the_date_index() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        for day in [1, 2, 5, 9] {
+            dao.set_image(
+                GROUP,
+                object(&format!("discord/{}.png", day)),
+                date(day),
+                1,
+                6,
+                false,
+            )
+            .await
+            .unwrap();
+        }
+
+        let first_page = dao.get_image_page(GROUP, date(9), 2).await.unwrap();
+        let second_page = dao
+            .get_image_page(GROUP, first_page.next_cursor.unwrap(), 2)
+            .await
+            .unwrap();
+        let upcoming = dao.get_upcoming_images(GROUP, date(2)).await.unwrap();
+
+        let dates = |images: &[Image]| {
+            images
+                .iter()
+                .map(|image| image.date)
+                .collect::<Vec<NaiveDate>>()
+        };
+        assert_eq!(dates(&first_page.images), vec![date(5), date(2)]);
+        assert_eq!(first_page.next_cursor, Some(date(2)));
+        assert_eq!(dates(&second_page.images), vec![date(1)]);
+        assert_eq!(second_page.next_cursor, None);
+        assert_eq!(dates(&upcoming), vec![date(5), date(9)]);
+    }
+
+    // Writes an image the way it was stored before the histories and date index were kept
+    async fn put_legacy_image(dynamodb_client: &InMemoryDynamoDb, image: &Image) {
+        dynamodb_client
+            .put_item_from_keys(
+                TABLE,
+                vec![
+                    KeyAndAttribute {
+                        key: "pk",
+                        attribute: AttributeValue::S(format_primary_key(GROUP, image.date)),
+                    },
+                    KeyAndAttribute {
+                        key: "sk",
+                        attribute: AttributeValue::S(IMAGE.to_owned()),
+                    },
+                    KeyAndAttribute {
+                        key: OBJECT_KEY,
+                        attribute: AttributeValue::S(image.object_key.to_owned()),
+                    },
+                    KeyAndAttribute {
+                        key: DAYS_UNTIL_GET_RECENTS,
+                        attribute: AttributeValue::N("5".to_owned()),
+                    },
+                ],
+                None,
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn backfilled_images_are_added_to_the_date_index() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        let images = [1, 3]
+            .into_iter()
+            .map(|day| Image {
+                object_key: format!("discord/{}.png", day),
+                get_recents: false,
+                days_until_get_recents: 5,
+                date: date(day),
+                pinned: false,
+            })
+            .collect::<Vec<Image>>();
+        for image in &images {
+            put_legacy_image(&dynamodb_client, image).await;
+        }
+
+        let before_backfill = dao.get_image_page(GROUP, date(4), 10).await.unwrap();
+        dao.backfill_image_histories(GROUP, &images).await.unwrap();
+        let after_backfill = dao.get_image_page(GROUP, date(4), 10).await.unwrap();
+
+        assert!(before_backfill.images.is_empty());
+        assert_eq!(
+            after_backfill,
+            ImagePage {
+                images: images.into_iter().rev().collect(),
+                next_cursor: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn history_dates_missing_from_the_date_index_are_backfilled() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        dao.set_image(GROUP, object("discord/1.png"), date(1), 1, 6, false)
+            .await
+            .unwrap();
+        // The history of an image set before the date index was kept
+        let image = Image {
+            object_key: "discord/2.png".to_owned(),
+            get_recents: false,
+            days_until_get_recents: 5,
+            date: date(2),
+            pinned: false,
+        };
+        put_legacy_image(&dynamodb_client, &image).await;
+        dynamodb_client
+            .transact_update_items_with_keys(
+                TABLE,
+                vec![dao.build_write_history_update(
+                    GROUP,
+                    &image.object_key,
+                    None,
+                    &BTreeSet::from([image.date]),
+                )],
+            )
+            .await
+            .unwrap();
+        let histories = dao.get_image_histories(GROUP).await.unwrap();
+
+        let before_backfill = dao.get_image_page(GROUP, date(3), 10).await.unwrap();
+        let backfilled = dao.backfill_image_dates(GROUP, &histories).await.unwrap();
+        let after_backfill = dao.get_image_page(GROUP, date(3), 10).await.unwrap();
+        let backfilled_again = dao.backfill_image_dates(GROUP, &histories).await.unwrap();
+
+        let dates = |images: &[Image]| {
+            images
+                .iter()
+                .map(|image| image.date)
+                .collect::<Vec<NaiveDate>>()
+        };
+        assert_eq!(dates(&before_backfill.images), vec![date(1)]);
+        assert_eq!(backfilled, 1);
+        assert_eq!(dates(&after_backfill.images), vec![date(2), date(1)]);
+        assert_eq!(backfilled_again, 0);
+    }
+
+    async fn set_queue(dao: &ImageDynamoDao<'_, InMemoryDynamoDb>, days: &[u32]) -> Vec<Image> {
+        for day in days {
+            dao.set_image(
+                GROUP,
+                object(&format!("discord/{}.png", day)),
+                date(*day),
+                1,
+                6,
+                false,
+            )
+            .await
+            .unwrap();
+        }
+
+        dao.get_upcoming_images(GROUP, date(1)).await.unwrap()
+    }
+
+    fn history_dates(histories: &[ImageHistory]) -> Vec<(String, Vec<NaiveDate>)> {
+        let mut history_dates = histories
+            .iter()
+            .map(|history| {
+                (
+                    history.object_key.to_owned(),
+                    history.dates.iter().copied().collect(),
+                )
+            })
+            .collect::<Vec<(String, Vec<NaiveDate>)>>();
+        history_dates.sort();
+        history_dates
+    }
+
+    #[tokio::test]
+    async fn reorder_moves_the_images_and_their_histories() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        let queue = set_queue(&dao, &[2, 3, 4]).await;
+
+        dao.reorder_images(
+            GROUP,
+            &queue,
+            &[
+                "discord/4.png".to_owned(),
+                "discord/3.png".to_owned(),
+                "discord/2.png".to_owned(),
+            ],
+        )
+        .await
+        .unwrap();
+        let reordered = dao.get_upcoming_images(GROUP, date(1)).await.unwrap();
+        let histories = dao.get_image_histories(GROUP).await.unwrap();
+
+        assert_eq!(
+            reordered
+                .iter()
+                .map(|image| (image.object_key.as_str(), image.date))
+                .collect::<Vec<(&str, NaiveDate)>>(),
+            vec![
+                ("discord/4.png", date(2)),
+                ("discord/3.png", date(3)),
+                ("discord/2.png", date(4)),
+            ]
+        );
+        assert_eq!(
+            history_dates(&histories),
+            vec![
+                ("discord/2.png".to_owned(), vec![date(4)]),
+                ("discord/3.png".to_owned(), vec![date(3)]),
+                ("discord/4.png".to_owned(), vec![date(2)]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reorder_of_a_stale_queue_writes_nothing() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        let stale_queue = set_queue(&dao, &[2, 3, 4]).await;
+        // The last date changes after the queue was read, so only the final move's condition fails
+        dao.set_image(GROUP, object("discord/9.png"), date(4), 1, 6, true)
+            .await
+            .unwrap();
+        let queue = dao.get_upcoming_images(GROUP, date(1)).await.unwrap();
+        let histories = dao.get_image_histories(GROUP).await.unwrap();
+
+        let result = dao
+            .reorder_images(
+                GROUP,
+                &stale_queue,
+                &[
+                    "discord/4.png".to_owned(),
+                    "discord/2.png".to_owned(),
+                    "discord/3.png".to_owned(),
+                ],
+            )
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(ImageDynamoDaoError::DynamoDbError(
+                DynamoDbUtilError::ConditionalCheckFailure(_)
+            ))
+        ));
+        assert_eq!(
+            dao.get_upcoming_images(GROUP, date(1)).await.unwrap(),
+            queue
+        );
+        assert_eq!(
+            history_dates(&dao.get_image_histories(GROUP).await.unwrap()),
+            history_dates(&histories)
+        );
+    }
+}
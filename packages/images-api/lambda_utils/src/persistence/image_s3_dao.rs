@@ -1,7 +1,9 @@
 use aws_sdk_s3::{types::Object, Client as S3Client};
-use tracing::instrument;
+use futures::TryStreamExt;
+use rand::Rng;
+use tracing::{info, instrument};
 
-use crate::aws_sdk::aws_s3::{S3Util, S3UtilError};
+use crate::aws_sdk::aws_s3::{ListPages, S3Util, S3UtilError};
 
 /*
  * TODO: This feels really stupid. I feel like both of the image DAOs should be able to be merged.
@@ -17,9 +19,17 @@ pub enum ImageS3DaoError {
     LocalError(String),
 }
 
-pub struct ImageS3Dao<'a> {
+pub struct ImageS3Dao<'a, S = S3Client> {
     pub bucket_name: &'a str,
-    pub s3_client: &'a S3Client,
+    pub s3_client: &'a S,
+}
+
+///
+/// The prefix of every object belonging to the group. The trailing `/` keeps group `a` from
+/// matching the objects of group `ab`.
+///
+pub fn group_prefix(group: &str) -> String {
+    format!("{}/", group)
 }
 
 impl From<S3UtilError> for ImageS3DaoError {
@@ -34,7 +44,7 @@ impl From<String> for ImageS3DaoError {
     }
 }
 
-impl ImageS3Dao<'_> {
+impl<S: S3Util> ImageS3Dao<'_, S> {
     ///
     /// List the objects in the associated bucket with the provided prefix.
     /// Return a list of Object's that contain metadata on the objects listed.
@@ -50,4 +60,75 @@ impl ImageS3Dao<'_> {
             .list_items(self.bucket_name, Some(prefix))
             .await?)
     }
+
+    ///
+    /// Get the metadata of the object with the provided key in the associated bucket.
+    ///
+    /// # Result
+    /// * `Ok(Some(Object))` - The Object that contains the S3 object's metadata
+    /// * `Ok(None)` - There is no object with the key
+    /// * `Err(ImageDaoError)` - Error in case of an S3 call failin or some other issue.
+    ///
+    #[instrument(skip_all)]
+    pub async fn get_by_key(&self, key: &str) -> Result<Option<Object>, ImageS3DaoError> {
+        Ok(self
+            .s3_client
+            .head_object_from_key(self.bucket_name, key)
+            .await?)
+    }
+
+    ///
+    /// Stream the pages of objects in the associated bucket with the provided prefix.
+    ///
+    pub fn stream_by_prefix<'b>(&'b self, prefix: &'b str) -> ListPages<'b> {
+        self.s3_client.list_pages(self.bucket_name, Some(prefix))
+    }
+
+    ///
+    /// Picks one object at random from the objects with the provided prefix, with each object's chance
+    /// proportional to its weight. Uses weighted reservoir sampling (A-Res) so only one page of objects is
+    /// held at a time no matter how large the bucket is.
+    ///
+    /// # Arguments
+    /// * `prefix` - The prefix of the objects to pick from
+    /// * `weight` - The relative chance of picking each object. Objects weighted 0 or less are never picked
+    ///
+    /// # Result
+    /// * `Ok(Some(Object))` - The randomly picked object
+    /// * `Ok(None)` - No objects had a weight greater than 0
+    /// * `Err(ImageDaoError)` - Error in case of an S3 call failing or some other issue.
+    ///
+    #[instrument(skip_all)]
+    pub async fn sample_by_prefix<F: FnMut(&Object) -> f64>(
+        &self,
+        prefix: &str,
+        mut weight: F,
+    ) -> Result<Option<Object>, ImageS3DaoError> {
+        let mut pages = self.stream_by_prefix(prefix);
+
+        let mut selected: Option<(f64, Object)> = None;
+        let mut weighted_objects: u64 = 0;
+        while let Some(page) = pages.try_next().await? {
+            for object in page.objects {
+                let object_weight = weight(&object);
+                if object_weight <= 0.0 {
+                    continue;
+                }
+                weighted_objects += 1;
+
+                // Keeping the object with the largest u^(1/weight) picks each object with probability
+                // proportional to its weight. Compared as ln(u)/weight to keep precision for large weights
+                let random: f64 = 1.0 - rand::thread_rng().gen::<f64>();
+                let sample_key = random.ln() / object_weight;
+
+                if !matches!(&selected, Some((selected_key, _)) if sample_key <= *selected_key) {
+                    selected = Some((sample_key, object));
+                }
+            }
+        }
+
+        info!("Sampled from {} weighted objects", weighted_objects);
+
+        Ok(selected.map(|(_, object)| object))
+    }
 }
@@ -1,3 +1,4 @@
+pub mod group_dao;
 pub mod image_dynamo_dao;
 pub mod image_s3_dao;
 pub mod user_reaction_dao;
@@ -4,20 +4,21 @@ use aws_sdk_dynamodb::{
     types::{AttributeValue, ReturnValue},
     Client,
 };
-use log::{error, info, warn};
+use log::{info, warn};
 
 use crate::{
     aws_sdk::aws_dynamodb::{
-        DynamoDbUtil, DynamoDbUtilError, KeyAndAttribute, KeyAndAttributeName,
+        DynamoDbUtil, DynamoDbUtilError, KeyAndAttribute, KeyAndAttributeName, PartitionQuery,
+        SortKeyCondition, TransactUpdate,
     },
     models::{ReactionError, Reactions},
 };
 
-pub struct UserReactionDao<'a> {
+pub struct UserReactionDao<'a, D = Client> {
     pub table_name: &'a str,
     pub primary_key: &'a str,
     pub sort_key: &'a str,
-    pub dynamodb_client: &'a Client,
+    pub dynamodb_client: &'a D,
 }
 
 #[derive(Debug)]
@@ -25,6 +26,8 @@ pub enum UserReactionDaoError {
     DynamoDbError(DynamoDbUtilError),
     AttributeValueParsingError(AttributeValue),
     ReactionConversionError(ReactionError),
+    /// The reaction can still be read but can no longer be set. Holds the reaction
+    DeprecatedReaction(String),
     ManualError(String),
 }
 
@@ -53,7 +56,10 @@ impl From<String> for UserReactionDaoError {
 }
 
 const REACTION_COUNTS: &str = "ReactionCounts";
+const FAVORITE_COUNTS: &str = "FavoriteCounts";
 const USER_PREFIX: &str = "user";
+const COUNTS: &str = "Counts";
+const MAX_SET_REACTION_ATTEMPTS: usize = 3;
 
 // Struct of what can be retrieved from the table
 pub struct UserItems {
@@ -61,7 +67,24 @@ pub struct UserItems {
     pub favorite_image: String,
 }
 
-impl UserReactionDao<'_> {
+// The outcome of recomputing a day's counts from the user records
+#[derive(Debug)]
+pub struct CountsReconciliation {
+    pub stored_counts: HashMap<String, String>,
+    pub recomputed_counts: HashMap<String, String>,
+    pub differences: Vec<CountsDifference>,
+    pub applied: bool,
+}
+
+// A reaction whose stored count doesn't match the recomputed count. None means the reaction is missing
+#[derive(Debug)]
+pub struct CountsDifference {
+    pub reaction: String,
+    pub stored: Option<String>,
+    pub recomputed: Option<String>,
+}
+
+impl<D: DynamoDbUtil> UserReactionDao<'_, D> {
     ///
     /// Returns all data associated with the provided date and uuid.
     ///
@@ -71,16 +94,22 @@ impl UserReactionDao<'_> {
     /// * `curr_uuid` - The UUID associated with the current user making a request
     ///
     /// # Result
-    /// * `UserItems` - No errors can be thrown. Anything that can't be found will return a default
-    pub async fn get(&self, group: &str, today_as_string: &str, curr_uuid: &str) -> UserItems {
+    /// * `Ok(UserItems)` - The user's items. A user without a record, or anything missing from it, gets the defaults
+    /// * `Error(UserReactionDaoError)` - Any error that occurs while trying to get the user's record
+    ///
+    pub async fn get(
+        &self,
+        group: &str,
+        today_as_string: &str,
+        curr_uuid: &str,
+    ) -> Result<UserItems, UserReactionDaoError> {
         let keys_and_attributes =
             self.build_user_reaction_key_and_attribute(group, today_as_string, curr_uuid);
 
         let get_item_from_key_result = self
             .dynamodb_client
             .get_item_from_keys(self.table_name, keys_and_attributes)
-            .await
-            .ok();
+            .await?;
 
         let reaction = match &get_item_from_key_result {
             Some(dynamo_map) => dynamo_map.get("reaction").map_or(
@@ -109,16 +138,19 @@ impl UserReactionDao<'_> {
             None => "".to_owned(),
         };
 
-        UserItems {
+        Ok(UserItems {
             reaction,
             favorite_image,
-        }
+        })
     }
 
     ///
     /// Given a date, uuid, and reaction it will set the provided users reaction on the provided
-    /// date. This will overwrite the previous reaction if it exists and return the old reaction as
-    /// a string.
+    /// date. This will overwrite the previous reaction if it exists and return the old reaction.
+    ///
+    /// The user record and the "ReactionCounts" record are written in a single transaction that is
+    /// conditioned on the reaction that was read, so the counts always match the user records. If the
+    /// reaction changes in between (e.g. concurrent requests from the same user) it is read again and retried.
     ///
     /// Also will only write the reaction if it is a current active reaction. Any deprecated reactions
     /// will be ignored
@@ -129,7 +161,8 @@ impl UserReactionDao<'_> {
     /// * `new_reaction` - The reaction as a string that is being set.
     ///
     /// # Returns
-    /// * `Ok(String)` - Returns the old reaction as a string. If none exists, returns Reactions::NoReaction
+    /// * `Ok(Reactions)` - Returns the old reaction. If none exists, returns Reactions::NoReaction
+    /// * `Error(UserReactionDaoError::DeprecatedReaction)` - The provided reaction is deprecated
     /// * `Error(UserReactionDaoError) - Propagates an unexpted error from calling DynamoDB.
     ///
     pub async fn set_reaction(
@@ -140,57 +173,84 @@ impl UserReactionDao<'_> {
         new_reaction: &Reactions,
     ) -> Result<Reactions, UserReactionDaoError> {
         // Return an error if the provided reaction is deprecated
-        new_reaction
-            .is_active()
-            .then_some(())
-            .ok_or("The provided reaction is deprecated".to_owned())?;
+        if !new_reaction.is_active() {
+            return Err(UserReactionDaoError::DeprecatedReaction(
+                new_reaction.to_string(),
+            ));
+        }
 
-        let keys_and_attributes =
-            self.build_user_reaction_key_and_attribute(group, today_as_string, curr_uuid);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
 
-        let expression_attribute_values = vec![KeyAndAttribute {
-            key: ":new_reaction",
-            attribute: AttributeValue::S(new_reaction.to_string()),
-        }];
+            let stored_reaction = self
+                .get_stored_user_attribute(group, today_as_string, curr_uuid, "reaction")
+                .await?;
+            let old_reaction = Reactions::get_reaction(
+                stored_reaction
+                    .as_deref()
+                    .unwrap_or(&Reactions::NoReaction.to_string()),
+            )?;
 
-        // Updates the reaction
-        // Gets the old reaction. This allows for decrementing the old reaction count
-        let update_reaction_result = self
-            .dynamodb_client
-            .update_item_with_keys(
-                self.table_name,
-                keys_and_attributes,
-                "SET reaction = :new_reaction".to_owned(),
-                ReturnValue::AllOld,
-                None,
-                expression_attribute_values,
-            )
-            .await;
+            let new_reaction_str = new_reaction.to_string();
 
-        let old_reaction = match update_reaction_result {
-            Ok(result) => handle_old_update_success(
-                result,
-                "reaction".to_owned(),
-                Reactions::NoReaction.to_string(),
-            ),
-            Err(err) => handle_old_update_error(err, Reactions::NoReaction.to_string()),
-        }?;
+            // Nothing changes so there is nothing to write
+            if stored_reaction.as_deref() == Some(new_reaction_str.as_str()) {
+                return Ok(old_reaction);
+            }
+
+            let transact_result = self
+                .dynamodb_client
+                .transact_update_items_with_keys(
+                    self.table_name,
+                    vec![
+                        self.build_set_reaction_update(
+                            group,
+                            today_as_string,
+                            curr_uuid,
+                            stored_reaction.as_deref(),
+                            &new_reaction_str,
+                        ),
+                        self.build_update_counts_update(
+                            group,
+                            today_as_string,
+                            stored_reaction.as_deref(),
+                            &new_reaction_str,
+                        ),
+                    ],
+                )
+                .await;
 
-        Ok(Reactions::get_reaction(&old_reaction)?)
+            match transact_result {
+                Ok(()) => return Ok(old_reaction),
+                Err(DynamoDbUtilError::ConditionalCheckFailure(reason))
+                    if attempts < MAX_SET_REACTION_ATTEMPTS =>
+                {
+                    warn!(
+                        "The reaction changed while it was being set. Trying again: {}",
+                        reason
+                    );
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
     }
 
     ///
-    /// Given a date, uuid, and favorite image key it will set the provided users favorite image on the provided
-    /// date. This will overwrite the previous favorite image if it exists and return the old favorite image key as
-    /// a string.
+    /// Given a date, uuid, and favorite image it will set the provided users favorite image on the provided
+    /// date and return the old favorite image. The empty string clears the favorite.
+    ///
+    /// The "FavoriteCounts" are updated in the same transaction as the favorite, which is conditioned on the
+    /// favorite that was read so the counts always match the user records. If the favorite changes in between
+    /// it is read again and retried.
     ///
     /// # Arguments
     /// * `today_as_string` - The date as a string "YYYY-MM-DD"
     /// * `curr_uuid` - The Users UUID
-    /// * `new_image` - The image key as a string that is being set.
+    /// * `new_image` - The url or key of the image that is being set
     ///
     /// # Returns
-    /// * `Ok(String)` - Returns the old image key as a string. If none exists, returns the empty string
+    /// * `Ok(String)` - Returns the old favorite image. If none exists, returns the empty string
     /// * `Error(UserReactionDaoError) - Propagates an unexpted error from calling DynamoDB.
     ///
     pub async fn set_favorite(
@@ -200,36 +260,100 @@ impl UserReactionDao<'_> {
         curr_uuid: &str,
         new_image: &str,
     ) -> Result<String, UserReactionDaoError> {
-        let keys_and_attributes =
-            self.build_user_reaction_key_and_attribute(group, today_as_string, curr_uuid);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
 
-        let expression_attribute_values = vec![KeyAndAttribute {
-            key: ":new_favorite_image",
-            attribute: AttributeValue::S(new_image.to_owned()),
-        }];
+            let stored_image = self
+                .get_stored_user_attribute(group, today_as_string, curr_uuid, "favorite_image")
+                .await?;
+            let old_image = stored_image.clone().unwrap_or_default();
 
-        // Updates the reaction
-        // Gets the old reaction. This allows for decrementing the old reaction count
-        let update_favorite_result = self
+            // Nothing changes so there is nothing to write
+            if old_image == new_image {
+                return Ok(old_image);
+            }
+
+            // The counts are only set up the first time a favorite is picked for the date
+            self.setup_favorite_counts(group, today_as_string).await?;
+
+            let mut updates = vec![self.build_set_favorite_update(
+                group,
+                today_as_string,
+                curr_uuid,
+                stored_image.as_deref(),
+                new_image,
+            )];
+            updates.extend(self.build_update_favorite_counts_update(
+                group,
+                today_as_string,
+                &old_image,
+                new_image,
+            ));
+
+            let transact_result = self
+                .dynamodb_client
+                .transact_update_items_with_keys(self.table_name, updates)
+                .await;
+
+            match transact_result {
+                Ok(()) => {
+                    info!("Request to update the favorite completed");
+                    return Ok(old_image);
+                }
+                Err(DynamoDbUtilError::ConditionalCheckFailure(reason))
+                    if attempts < MAX_SET_REACTION_ATTEMPTS =>
+                {
+                    warn!(
+                        "The favorite changed while it was being set. Trying again: {}",
+                        reason
+                    );
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    ///
+    /// Gets how many users picked each image as their favorite on the provided date.
+    ///
+    /// # Arguments
+    /// * `today_as_string` - Date represented as a string in the format 'YYYY-MM-DD'
+    ///
+    /// # Result
+    /// * `Ok(HashMap<String, String>)` - The number of favorites keyed by the image's key. Images nobody has
+    ///   picked anymore are left out, and it is empty if there is no "FavoriteCounts" record for the date
+    /// * `Error(UserReactionDaoError)` - Any error that occurs while trying to get the counts
+    ///
+    pub async fn get_favorite_counts(
+        &self,
+        group: &str,
+        today_as_string: &str,
+    ) -> Result<HashMap<String, String>, UserReactionDaoError> {
+        let favorite_counts = match self
             .dynamodb_client
-            .update_item_with_keys(
+            .get_item_from_keys(
                 self.table_name,
-                keys_and_attributes,
-                "SET favorite_image = :new_favorite_image".to_owned(),
-                ReturnValue::AllOld,
-                None,
-                expression_attribute_values,
+                self.build_counts_key_and_attribute(group, today_as_string, FAVORITE_COUNTS),
             )
-            .await;
+            .await?
+        {
+            Some(item) => item,
+            // The counts are set up the first time a favorite is picked for the date
+            None => return Ok(HashMap::new()),
+        };
 
-        let old_image = match update_favorite_result {
-            Ok(result) => {
-                handle_old_update_success(result, "favorite_image".to_owned(), "".to_owned())
-            }
-            Err(err) => handle_old_update_error(err, "".to_owned()),
-        }?;
+        let counts = match favorite_counts.get(COUNTS) {
+            Some(counts) => generate_numeric_counts(counts.as_m().map_err(|err| err.to_owned())?),
+            None => HashMap::new(),
+        };
 
-        Ok(old_image)
+        info!("Request to retrieve favorite counts completed");
+
+        Ok(counts
+            .into_iter()
+            .filter(|(_, count)| count != "0")
+            .collect())
     }
 
     ///
@@ -280,6 +404,7 @@ impl UserReactionDao<'_> {
     ///
     /// # Result
     /// * `Ok(HashMap<String, String>)` - Returns a HashMap where key is the reaction string and value is the number of times its been "reacted"
+    /// * `Error(UserReactionDaoError::DynamoDbError(DynamoDbUtilError::NotFound))` - The date's counts were never set up
     /// * `Error(UserReactionDaoError)` - Any error that occurs while trying to get the current counts
     ///
     pub async fn get_counts(
@@ -293,7 +418,13 @@ impl UserReactionDao<'_> {
         let get_counts_result = self
             .dynamodb_client
             .get_item_from_keys(self.table_name, keys_and_attributes)
-            .await?;
+            .await?
+            .ok_or_else(|| {
+                DynamoDbUtilError::NotFound(format!(
+                    "The reaction counts for {}",
+                    format_primary_key(group, today_as_string)
+                ))
+            })?;
 
         let counts = get_counts_result
             .get("Counts")
@@ -307,86 +438,591 @@ impl UserReactionDao<'_> {
     }
 
     ///
-    /// Given a date as well as old and new reactions, update count totals as necessary.
-    /// If the reactions are the same this means doing nothing. If they're different this means
-    /// incrementing and decrementing the new and old reaction counts respectively.
+    /// Gets the counts of all reactions for several dates at once.
     ///
     /// # Arguments
-    /// * `today_as_string` - String representing the date being updated as "YYYY-MM-DD"
-    /// * `old_reaction` - The old reaction that was changed
-    /// * `new_reaction` - The new reaction that has been set
+    /// * `dates_as_strings` - Dates represented as strings in the format 'YYYY-MM-DD'
+    ///
+    /// # Result
+    /// * `Ok(HashMap<String, HashMap<String, String>>)` - The counts keyed by date. Dates without counts are left out
+    /// * `Error(UserReactionDaoError)` - Any error that occurs while trying to get the counts
+    ///
+    pub async fn batch_get_counts(
+        &self,
+        group: &str,
+        dates_as_strings: &[String],
+    ) -> Result<HashMap<String, HashMap<String, String>>, UserReactionDaoError> {
+        self.batch_get_counts_records(group, dates_as_strings, REACTION_COUNTS)
+            .await
+    }
+
+    ///
+    /// Gets the favorite counts for several dates at once.
+    ///
+    /// # Arguments
+    /// * `dates_as_strings` - Dates represented as strings in the format 'YYYY-MM-DD'
+    ///
+    /// # Result
+    /// * `Ok(HashMap<String, HashMap<String, String>>)` - The number of favorites keyed by date and then by the
+    ///   image's key. Dates without a "FavoriteCounts" record are left out
+    /// * `Error(UserReactionDaoError)` - Any error that occurs while trying to get the counts
+    ///
+    pub async fn batch_get_favorite_counts(
+        &self,
+        group: &str,
+        dates_as_strings: &[String],
+    ) -> Result<HashMap<String, HashMap<String, String>>, UserReactionDaoError> {
+        self.batch_get_counts_records(group, dates_as_strings, FAVORITE_COUNTS)
+            .await
+    }
+
+    ///
+    /// Gets every favorite image picked by a user on the provided date. Users without a favorite are left out.
+    ///
+    /// # Arguments
+    /// * `today_as_string` - Date represented as a string in the format 'YYYY-MM-DD'
+    ///
+    /// # Result
+    /// * `Ok(Vec<String>)` - The favorite picked by each user who picked one
+    /// * `Error(UserReactionDaoError)` - Any error that occurs while reading the user records
+    ///
+    pub async fn get_favorites(
+        &self,
+        group: &str,
+        today_as_string: &str,
+    ) -> Result<Vec<String>, UserReactionDaoError> {
+        let user_items = self
+            .dynamodb_client
+            .query_all_by_partition(
+                self.table_name,
+                PartitionQuery::new(KeyAndAttribute {
+                    key: self.primary_key,
+                    attribute: AttributeValue::S(format_primary_key(group, today_as_string)),
+                })
+                .sort_key_condition(
+                    self.sort_key,
+                    SortKeyCondition::BeginsWith(format!("{}#", USER_PREFIX)),
+                )
+                .projection(vec!["favorite_image"]),
+            )
+            .await?;
+
+        let favorites = user_items
+            .iter()
+            .filter_map(|user_item| user_item.get("favorite_image"))
+            .filter_map(|favorite_image| favorite_image.as_s().ok())
+            .filter(|favorite_image| !favorite_image.is_empty())
+            .map(|favorite_image| favorite_image.to_owned())
+            .collect::<Vec<String>>();
+
+        info!(
+            "Found {} favorites for {}",
+            favorites.len(),
+            format_primary_key(group, today_as_string)
+        );
+
+        Ok(favorites)
+    }
+
+    ///
+    /// Recomputes the "ReactionCounts" for a date from every user record on that date and reports how
+    /// the stored counts differ. Only active reactions are counted so deprecated reactions that are
+    /// still in the stored counts show up as differences and get dropped.
+    ///
+    /// A date without a counts record or any user records has nothing to reconcile, so no zeroed counts
+    /// are written for days nobody reacted to.
+    ///
+    /// When `apply` is set and there are differences the counts are overwritten. The write is conditioned
+    /// on the counts being unchanged since they were read, so a reaction coming in mid reconciliation
+    /// fails the write instead of being lost. Running it again will pick the new reaction up.
+    ///
+    /// # Arguments
+    /// * `today_as_string` - The date as a string "YYYY-MM-DD"
+    /// * `apply` - Whether to overwrite the stored counts with the recomputed counts
     ///
     /// # Returns
-    /// * `Ok(HashMap<String, String>)` - Returns a HashMap where key is the reaction string and value is the number of times its been "reacted"
-    /// * `Error(UserReactionDaoError)` - Any failure that occurs while trying to update/get the counts
+    /// * `Ok(CountsReconciliation)` - The stored and recomputed counts along with their differences
+    /// * `Error(UserReactionDaoError)` - Any failure reading the records or writing the counts
     ///
-    pub async fn update_counts(
+    pub async fn reconcile_counts(
         &self,
         group: &str,
         today_as_string: &str,
-        old_reaction: &Reactions,
-        new_reaction: &Reactions,
-    ) -> Result<HashMap<String, String>, UserReactionDaoError> {
-        let old_reaction_str = old_reaction.to_string();
-        let new_reaction_str = new_reaction.to_string();
+        apply: bool,
+    ) -> Result<CountsReconciliation, UserReactionDaoError> {
+        // A missing record is treated the same as missing counts so they get created
+        let stored_item = self
+            .dynamodb_client
+            .get_item_from_keys(
+                self.table_name,
+                self.build_reaction_counts_key_and_attribute(group, today_as_string),
+            )
+            .await?;
+        let stored_counts = match stored_item.as_ref().and_then(|item| item.get(COUNTS)) {
+            Some(counts) => Some(counts.as_m().map_err(|err| err.to_owned())?.to_owned()),
+            None => None,
+        };
+
+        let user_items = self
+            .dynamodb_client
+            .query_all_by_partition(
+                self.table_name,
+                PartitionQuery::new(KeyAndAttribute {
+                    key: self.primary_key,
+                    attribute: AttributeValue::S(format_primary_key(group, today_as_string)),
+                })
+                .sort_key_condition(
+                    self.sort_key,
+                    SortKeyCondition::BeginsWith(format!("{}#", USER_PREFIX)),
+                )
+                .projection(vec!["reaction"]),
+            )
+            .await?;
 
-        // If the reactions are the same, return early
-        if old_reaction_str == new_reaction_str {
-            let curr_counts = self.get_counts(group, today_as_string).await?;
-            return Ok(curr_counts);
+        info!(
+            "Recomputing the counts for {} from {} user records",
+            format_primary_key(group, today_as_string),
+            user_items.len()
+        );
+
+        let mut recomputed_counts: HashMap<String, i64> = Reactions::active_reactions()
+            .into_iter()
+            .map(|reaction| (reaction, 0))
+            .collect();
+        for user_item in &user_items {
+            let reaction = match user_item.get("reaction").map(|reaction| reaction.as_s()) {
+                Some(Ok(reaction)) => reaction,
+                _ => continue,
+            };
+
+            // Deprecated or unknown reactions aren't counted
+            if let Some(count) = recomputed_counts.get_mut(reaction) {
+                *count += 1;
+            }
         }
+        let recomputed_counts: HashMap<String, String> = recomputed_counts
+            .into_iter()
+            .map(|(reaction, count)| (reaction, count.to_string()))
+            .collect();
 
-        // Otherwise, update counts as necessary
-        let counts_keys_and_attributes =
-            self.build_reaction_counts_key_and_attribute(group, today_as_string);
+        let stored_numeric_counts = stored_counts
+            .as_ref()
+            .map(generate_numeric_counts)
+            .unwrap_or_default();
 
-        let counts_expression_attribute_names = Some(vec![
-            KeyAndAttributeName {
-                key: "#new_reaction",
-                attribute_name: &new_reaction_str,
-            },
-            KeyAndAttributeName {
-                key: "#old_reaction",
-                attribute_name: &old_reaction_str,
-            },
-        ]);
+        let mut reactions = stored_numeric_counts
+            .keys()
+            .chain(recomputed_counts.keys())
+            .collect::<Vec<&String>>();
+        reactions.sort();
+        reactions.dedup();
+
+        // Zeroed counts aren't a difference when nothing is stored and nobody reacted
+        if stored_item.is_none() && user_items.is_empty() {
+            reactions.clear();
+        }
+
+        let differences = reactions
+            .into_iter()
+            .filter(|reaction| {
+                stored_numeric_counts.get(*reaction) != recomputed_counts.get(*reaction)
+            })
+            .map(|reaction| CountsDifference {
+                reaction: reaction.to_owned(),
+                stored: stored_numeric_counts.get(reaction).cloned(),
+                recomputed: recomputed_counts.get(reaction).cloned(),
+            })
+            .collect::<Vec<CountsDifference>>();
+
+        let applied = apply && !differences.is_empty();
+        if applied {
+            self.overwrite_counts(group, today_as_string, stored_counts, &recomputed_counts)
+                .await?;
+            info!("Overwrote the counts with the recomputed counts");
+        }
+
+        Ok(CountsReconciliation {
+            stored_counts: stored_numeric_counts,
+            recomputed_counts,
+            differences,
+            applied,
+        })
+    }
+
+    /** Helper Functions that require state */
+    async fn overwrite_counts(
+        &self,
+        group: &str,
+        today_as_string: &str,
+        stored_counts: Option<HashMap<String, AttributeValue>>,
+        new_counts: &HashMap<String, String>,
+    ) -> Result<(), UserReactionDaoError> {
+        let new_counts = new_counts
+            .iter()
+            .map(|(reaction, count)| (reaction.to_owned(), AttributeValue::N(count.to_owned())))
+            .collect();
 
-        let counts_expression_attribute_values = vec![KeyAndAttribute {
-            key: ":count",
-            attribute: AttributeValue::N("1".to_owned()),
+        let mut expression_attribute_values = vec![KeyAndAttribute {
+            key: ":new_counts",
+            attribute: AttributeValue::M(new_counts),
         }];
 
-        let update_counts_result = self.dynamodb_client.update_item_with_keys(
-            self.table_name,
-            counts_keys_and_attributes,
-            "SET Counts.#new_reaction = Counts.#new_reaction + :count , Counts.#old_reaction = Counts.#old_reaction - :count".to_owned(),
-            ReturnValue::AllNew,
-            counts_expression_attribute_names,
-            counts_expression_attribute_values
-        ).await?;
+        let condition_expression = match stored_counts {
+            Some(stored_counts) => {
+                expression_attribute_values.push(KeyAndAttribute {
+                    key: ":stored_counts",
+                    attribute: AttributeValue::M(stored_counts),
+                });
+                "Counts = :stored_counts"
+            }
+            None => "attribute_not_exists(Counts)",
+        };
+
+        self.dynamodb_client
+            .transact_update_items_with_keys(
+                self.table_name,
+                vec![TransactUpdate {
+                    keys_and_attributes: self
+                        .build_reaction_counts_key_and_attribute(group, today_as_string),
+                    update_expression: "SET Counts = :new_counts".to_owned(),
+                    condition_expression: Some(condition_expression.to_owned()),
+                    expression_attribute_names: None,
+                    expression_attribute_values,
+                }],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Sets up the "FavoriteCounts" record for the date if it does not already exist. Favorites picked
+    /// before the counts were kept are counted from the user records, so moving a favorite away from
+    /// one of them never takes its count below 0.
+    ///
+    async fn setup_favorite_counts(
+        &self,
+        group: &str,
+        today_as_string: &str,
+    ) -> Result<(), UserReactionDaoError> {
+        let stored_item = self
+            .dynamodb_client
+            .get_item_from_keys(
+                self.table_name,
+                self.build_counts_key_and_attribute(group, today_as_string, FAVORITE_COUNTS),
+            )
+            .await?;
+        if stored_item.is_some() {
+            return Ok(());
+        }
+
+        let counts_map = self
+            .count_favorites(group, today_as_string)
+            .await?
+            .into_iter()
+            .map(|(object_key, count)| (object_key, AttributeValue::N(count.to_string())))
+            .collect();
+
+        // Another request may have set the counts up in the meantime, in which case they are kept
+        self.dynamodb_client
+            .update_item_with_keys(
+                self.table_name,
+                self.build_counts_key_and_attribute(group, today_as_string, FAVORITE_COUNTS),
+                "SET Counts = if_not_exists(Counts, :counts_map)".to_owned(),
+                ReturnValue::None,
+                None,
+                vec![KeyAndAttribute {
+                    key: ":counts_map",
+                    attribute: AttributeValue::M(counts_map),
+                }],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Batch gets the counts record with the provided sort key, e.g. "ReactionCounts", for each date.
+    ///
+    async fn batch_get_counts_records(
+        &self,
+        group: &str,
+        dates_as_strings: &[String],
+        counts_sort_key: &str,
+    ) -> Result<HashMap<String, HashMap<String, String>>, UserReactionDaoError> {
+        let keys_and_attributes = dates_as_strings
+            .iter()
+            .map(|date| self.build_counts_key_and_attribute(group, date, counts_sort_key))
+            .collect::<Vec<Vec<KeyAndAttribute>>>();
+
+        let items = self
+            .dynamodb_client
+            .batch_get_item_from_keys(self.table_name, keys_and_attributes)
+            .await?;
+
+        let mut counts_by_date = HashMap::new();
+        for item in items {
+            let primary_key = item
+                .get(self.primary_key)
+                .ok_or_else(|| "Counts record is missing its primary key".to_owned())?
+                .as_s()
+                .map_err(|err| err.to_owned())?;
+            let date = primary_key
+                .strip_prefix(&format_primary_key(group, ""))
+                .ok_or_else(|| format!("Unexpected counts primary key {}", primary_key))?
+                .to_owned();
+
+            let counts = match item.get(COUNTS) {
+                Some(counts) => counts.as_m().map_err(|err| err.to_owned())?,
+                None => continue,
+            };
+
+            counts_by_date.insert(date, generate_numeric_counts(counts));
+        }
 
         info!(
-            "Request to update counts completed: {:?}",
-            update_counts_result
+            "Request to retrieve counts for {} dates completed",
+            dates_as_strings.len()
         );
 
-        let updated_counts = update_counts_result
-            .get("Counts")
-            .ok_or_else(|| "Did not successfully update counts".to_owned())?
-            .as_m()
-            .map_err(|err| err.to_owned())?;
+        Ok(counts_by_date)
+    }
 
-        Ok(generate_numeric_counts(updated_counts))
+    ///
+    /// Counts the favorites in the user records for the date, keyed by the image's key.
+    ///
+    async fn count_favorites(
+        &self,
+        group: &str,
+        today_as_string: &str,
+    ) -> Result<HashMap<String, i64>, UserReactionDaoError> {
+        let mut counts: HashMap<String, i64> = HashMap::new();
+        for favorite in self.get_favorites(group, today_as_string).await? {
+            *counts
+                .entry(object_key_from_favorite(&favorite).to_owned())
+                .or_default() += 1;
+        }
+
+        Ok(counts)
     }
 
-    /** Helper Functions that require state */
+    ///
+    /// Reads an attribute stored on the users record, e.g. the reaction. A missing record or attribute
+    /// means the user hasn't set it yet and returns None.
+    ///
+    async fn get_stored_user_attribute(
+        &self,
+        group: &str,
+        today_as_string: &str,
+        curr_uuid: &str,
+        attribute: &str,
+    ) -> Result<Option<String>, UserReactionDaoError> {
+        let user_item = self
+            .dynamodb_client
+            .get_item_from_keys(
+                self.table_name,
+                self.build_user_reaction_key_and_attribute(group, today_as_string, curr_uuid),
+            )
+            .await?;
+
+        let value = match user_item.as_ref().and_then(|item| item.get(attribute)) {
+            Some(value) => Some(value.as_s().map_err(|err| err.to_owned())?.to_owned()),
+            None => None,
+        };
+
+        Ok(value)
+    }
+
+    fn build_set_favorite_update(
+        &self,
+        group: &str,
+        today_as_string: &str,
+        curr_uuid: &str,
+        stored_image: Option<&str>,
+        new_image: &str,
+    ) -> TransactUpdate<'_> {
+        let mut expression_attribute_values = vec![KeyAndAttribute {
+            key: ":new_favorite_image",
+            attribute: AttributeValue::S(new_image.to_owned()),
+        }];
+
+        // Only succeeds if the favorite is still the one that was read
+        let condition_expression = match stored_image {
+            Some(stored_image) => {
+                expression_attribute_values.push(KeyAndAttribute {
+                    key: ":old_favorite_image",
+                    attribute: AttributeValue::S(stored_image.to_owned()),
+                });
+                "favorite_image = :old_favorite_image"
+            }
+            None => "attribute_not_exists(favorite_image)",
+        };
+
+        TransactUpdate {
+            keys_and_attributes: self.build_user_reaction_key_and_attribute(
+                group,
+                today_as_string,
+                curr_uuid,
+            ),
+            update_expression: "SET favorite_image = :new_favorite_image".to_owned(),
+            condition_expression: Some(condition_expression.to_owned()),
+            expression_attribute_names: None,
+            expression_attribute_values,
+        }
+    }
+
+    ///
+    /// Builds the update moving the user's favorite count from the old image to the new one. Favorites
+    /// are counted by the image's key, so a url and a key for the same image leave the counts as they
+    /// are. The empty string means there is no favorite, so there is nothing to update when both are empty.
+    ///
+    fn build_update_favorite_counts_update<'a>(
+        &'a self,
+        group: &str,
+        today_as_string: &str,
+        old_favorite: &'a str,
+        new_favorite: &'a str,
+    ) -> Option<TransactUpdate<'a>> {
+        let old_object_key = object_key_from_favorite(old_favorite);
+        let new_object_key = object_key_from_favorite(new_favorite);
+        if old_object_key == new_object_key {
+            return None;
+        }
+
+        let mut expression_attribute_names = Vec::new();
+        let mut set_expressions = Vec::new();
+
+        // Images aren't in the counts until they are picked for the first time
+        if !new_object_key.is_empty() {
+            expression_attribute_names.push(KeyAndAttributeName {
+                key: "#new_favorite",
+                attribute_name: new_object_key,
+            });
+            set_expressions
+                .push("Counts.#new_favorite = if_not_exists(Counts.#new_favorite, :zero) + :count");
+        }
+
+        if !old_object_key.is_empty() {
+            expression_attribute_names.push(KeyAndAttributeName {
+                key: "#old_favorite",
+                attribute_name: old_object_key,
+            });
+            set_expressions
+                .push("Counts.#old_favorite = if_not_exists(Counts.#old_favorite, :zero) - :count");
+        }
+
+        if set_expressions.is_empty() {
+            return None;
+        }
+
+        Some(TransactUpdate {
+            keys_and_attributes: self.build_counts_key_and_attribute(
+                group,
+                today_as_string,
+                FAVORITE_COUNTS,
+            ),
+            update_expression: format!("SET {}", set_expressions.join(" , ")),
+            condition_expression: None,
+            expression_attribute_names: Some(expression_attribute_names),
+            expression_attribute_values: vec![
+                KeyAndAttribute {
+                    key: ":count",
+                    attribute: AttributeValue::N("1".to_owned()),
+                },
+                KeyAndAttribute {
+                    key: ":zero",
+                    attribute: AttributeValue::N("0".to_owned()),
+                },
+            ],
+        })
+    }
+
+    fn build_set_reaction_update(
+        &self,
+        group: &str,
+        today_as_string: &str,
+        curr_uuid: &str,
+        stored_reaction: Option<&str>,
+        new_reaction: &str,
+    ) -> TransactUpdate<'_> {
+        let mut expression_attribute_values = vec![KeyAndAttribute {
+            key: ":new_reaction",
+            attribute: AttributeValue::S(new_reaction.to_owned()),
+        }];
+
+        // Only succeeds if the reaction is still the one that was read
+        let condition_expression = match stored_reaction {
+            Some(stored_reaction) => {
+                expression_attribute_values.push(KeyAndAttribute {
+                    key: ":old_reaction",
+                    attribute: AttributeValue::S(stored_reaction.to_owned()),
+                });
+                "reaction = :old_reaction"
+            }
+            None => "attribute_not_exists(reaction)",
+        };
+
+        TransactUpdate {
+            keys_and_attributes: self.build_user_reaction_key_and_attribute(
+                group,
+                today_as_string,
+                curr_uuid,
+            ),
+            update_expression: "SET reaction = :new_reaction".to_owned(),
+            condition_expression: Some(condition_expression.to_owned()),
+            expression_attribute_names: None,
+            expression_attribute_values,
+        }
+    }
+
+    fn build_update_counts_update<'a>(
+        &'a self,
+        group: &str,
+        today_as_string: &str,
+        stored_reaction: Option<&'a str>,
+        new_reaction: &'a str,
+    ) -> TransactUpdate<'a> {
+        let mut expression_attribute_names = vec![KeyAndAttributeName {
+            key: "#new_reaction",
+            attribute_name: new_reaction,
+        }];
+
+        // Users who hadn't reacted yet weren't counted under any reaction. Deprecated reactions
+        // aren't in the counts anymore so there is nothing to decrement for them either
+        let decrement_reaction = stored_reaction.filter(|stored_reaction| {
+            Reactions::get_reaction(stored_reaction).is_ok_and(|reaction| reaction.is_active())
+        });
+
+        let update_expression = match decrement_reaction {
+            Some(decrement_reaction) => {
+                expression_attribute_names.push(KeyAndAttributeName {
+                    key: "#old_reaction",
+                    attribute_name: decrement_reaction,
+                });
+                "SET Counts.#new_reaction = Counts.#new_reaction + :count , Counts.#old_reaction = Counts.#old_reaction - :count"
+            }
+            None => "SET Counts.#new_reaction = Counts.#new_reaction + :count",
+        };
+
+        TransactUpdate {
+            keys_and_attributes: self
+                .build_reaction_counts_key_and_attribute(group, today_as_string),
+            update_expression: update_expression.to_owned(),
+            condition_expression: None,
+            expression_attribute_names: Some(expression_attribute_names),
+            expression_attribute_values: vec![KeyAndAttribute {
+                key: ":count",
+                attribute: AttributeValue::N("1".to_owned()),
+            }],
+        }
+    }
 
     fn build_user_reaction_key_and_attribute(
         &self,
         group: &str,
         today_as_string: &str,
         user: &str,
-    ) -> Vec<KeyAndAttribute> {
+    ) -> Vec<KeyAndAttribute<'_>> {
         vec![
             KeyAndAttribute {
                 key: self.primary_key,
@@ -407,7 +1043,16 @@ impl UserReactionDao<'_> {
         &self,
         group: &str,
         today_as_string: &str,
-    ) -> Vec<KeyAndAttribute> {
+    ) -> Vec<KeyAndAttribute<'_>> {
+        self.build_counts_key_and_attribute(group, today_as_string, REACTION_COUNTS)
+    }
+
+    fn build_counts_key_and_attribute(
+        &self,
+        group: &str,
+        today_as_string: &str,
+        counts: &str,
+    ) -> Vec<KeyAndAttribute<'_>> {
         vec![
             KeyAndAttribute {
                 key: self.primary_key,
@@ -415,12 +1060,26 @@ impl UserReactionDao<'_> {
             },
             KeyAndAttribute {
                 key: self.sort_key,
-                attribute: AttributeValue::S(REACTION_COUNTS.to_owned()),
+                attribute: AttributeValue::S(counts.to_owned()),
             },
         ]
     }
 }
 
+///
+/// Gets the key of the image a favorite was picked for. Favorites are stored as the url of the recap
+/// image, e.g. https://{domain}/{object_key}
+///
+pub fn object_key_from_favorite(favorite: &str) -> &str {
+    match favorite
+        .strip_prefix("https://")
+        .and_then(|path| path.split_once('/'))
+    {
+        Some((_domain, object_key)) => object_key,
+        None => favorite,
+    }
+}
+
 /** Generate helper functions that don't require state */
 fn format_primary_key(group: &str, date: &str) -> String {
     format!("{}_{}", group, date)
@@ -437,39 +1096,253 @@ fn generate_numeric_counts(
     numeric_counts
 }
 
-///
-/// Helper functions for updates
-///
-fn handle_old_update_success(
-    result: HashMap<String, AttributeValue>,
-    key: String,
-    default: String,
-) -> Result<String, UserReactionDaoError> {
-    info!("Request to update value completed successfully");
-
-    let reaction = match result.get(&key) {
-        Some(value) => value.as_s().map_err(|err| err.to_owned())?.to_owned(),
-        None => default,
-    };
-
-    Ok(reaction)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws_sdk::in_memory_dynamodb::InMemoryDynamoDb;
 
-fn handle_old_update_error<T>(
-    err: DynamoDbUtilError,
-    default: T,
-) -> Result<T, UserReactionDaoError> {
-    warn!("There was an error attempting to update");
+    const TABLE: &str = "ImageTable";
+    const GROUP: &str = "discord";
+    const DATE: &str = "2024-01-07";
 
-    if let DynamoDbUtilError::LocalError(_) = err {
-        info!(
-            "Error only caused because this was the first inserted value. Continuing: {:?}",
-            err
+    fn build_dao(dynamodb_client: &InMemoryDynamoDb) -> UserReactionDao<'_, InMemoryDynamoDb> {
+        UserReactionDao {
+            table_name: TABLE,
+            primary_key: "pk",
+            sort_key: "sk",
+            dynamodb_client,
+        }
+    }
+
+    // Writes a favorite the way it was stored before the favorite counts were kept
+    async fn put_legacy_favorite(dynamodb_client: &InMemoryDynamoDb, uuid: &str, favorite: &str) {
+        dynamodb_client
+            .put_item_from_keys(
+                TABLE,
+                vec![
+                    KeyAndAttribute {
+                        key: "pk",
+                        attribute: AttributeValue::S(format_primary_key(GROUP, DATE)),
+                    },
+                    KeyAndAttribute {
+                        key: "sk",
+                        attribute: AttributeValue::S(format!("{}#{}", USER_PREFIX, uuid)),
+                    },
+                    KeyAndAttribute {
+                        key: "favorite_image",
+                        attribute: AttributeValue::S(favorite.to_owned()),
+                    },
+                ],
+                None,
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_favorite_counts_favorites_picked_before_the_counts_existed() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        put_legacy_favorite(&dynamodb_client, "first", "https://images/discord/1.png").await;
+        put_legacy_favorite(&dynamodb_client, "second", "https://images/discord/1.png").await;
+
+        let counts_before = dao.get_favorite_counts(GROUP, DATE).await.unwrap();
+        let old_favorite = dao
+            .set_favorite(GROUP, DATE, "first", "https://images/discord/2.png")
+            .await
+            .unwrap();
+        let counts_after = dao.get_favorite_counts(GROUP, DATE).await.unwrap();
+
+        assert_eq!(old_favorite, "https://images/discord/1.png");
+        assert!(counts_before.is_empty());
+        assert_eq!(
+            counts_after,
+            HashMap::from([
+                ("discord/1.png".to_owned(), "1".to_owned()),
+                ("discord/2.png".to_owned(), "1".to_owned()),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn clearing_the_only_favorite_leaves_no_counts() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        put_legacy_favorite(&dynamodb_client, "first", "https://images/discord/1.png").await;
+
+        dao.set_favorite(GROUP, DATE, "first", "").await.unwrap();
+        let counts = dao.get_favorite_counts(GROUP, DATE).await.unwrap();
+
+        assert!(counts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn switching_between_a_favorites_url_and_key_keeps_the_count() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        dao.set_favorite(GROUP, DATE, "first", "https://images/discord/1.png")
+            .await
+            .unwrap();
+
+        let old_favorite = dao
+            .set_favorite(GROUP, DATE, "first", "discord/1.png")
+            .await
+            .unwrap();
+        let counts = dao.get_favorite_counts(GROUP, DATE).await.unwrap();
+
+        assert_eq!(old_favorite, "https://images/discord/1.png");
+        assert_eq!(
+            counts,
+            HashMap::from([("discord/1.png".to_owned(), "1".to_owned())])
         );
+    }
+
+    #[tokio::test]
+    async fn favorite_counts_are_empty_without_a_counts_record() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        put_legacy_favorite(&dynamodb_client, "first", "https://images/discord/1.png").await;
+
+        let counts = dao.get_favorite_counts(GROUP, DATE).await.unwrap();
+
+        assert!(counts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reconciling_a_day_without_any_records_writes_nothing() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+
+        let reconciliation = dao.reconcile_counts(GROUP, DATE, true).await.unwrap();
+        let stored_counts = dynamodb_client
+            .get_item_from_keys(
+                TABLE,
+                dao.build_reaction_counts_key_and_attribute(GROUP, DATE),
+            )
+            .await
+            .unwrap();
+
+        assert!(reconciliation.differences.is_empty());
+        assert!(!reconciliation.applied);
+        assert_eq!(stored_counts, None);
+    }
+
+    fn count(counts: &HashMap<String, String>, reaction: &str) -> Option<String> {
+        counts.get(reaction).cloned()
+    }
+
+    #[tokio::test]
+    async fn first_reaction_only_increments() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        dao.setup_counts(GROUP, DATE).await.unwrap();
+
+        let old_reaction = dao
+            .set_reaction(GROUP, DATE, "user-1", &Reactions::Love)
+            .await
+            .unwrap();
+        let counts = dao.get_counts(GROUP, DATE).await.unwrap();
+
+        assert!(matches!(old_reaction, Reactions::NoReaction));
+        assert_eq!(count(&counts, "Love"), Some("1".to_owned()));
+        assert!(counts
+            .iter()
+            .filter(|(reaction, _)| *reaction != "Love")
+            .all(|(_, count)| count == "0"));
+    }
 
-        return Ok(default);
+    #[tokio::test]
+    async fn switching_reactions_moves_the_count() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        dao.setup_counts(GROUP, DATE).await.unwrap();
+        dao.set_reaction(GROUP, DATE, "user-1", &Reactions::Love)
+            .await
+            .unwrap();
+
+        let old_reaction = dao
+            .set_reaction(GROUP, DATE, "user-1", &Reactions::Funny)
+            .await
+            .unwrap();
+        let counts = dao.get_counts(GROUP, DATE).await.unwrap();
+        let user_items = dao.get(GROUP, DATE, "user-1").await.unwrap();
+
+        assert!(matches!(old_reaction, Reactions::Love));
+        assert_eq!(count(&counts, "Love"), Some("0".to_owned()));
+        assert_eq!(count(&counts, "Funny"), Some("1".to_owned()));
+        assert_eq!(user_items.reaction, "Funny");
+    }
+
+    #[tokio::test]
+    async fn deprecated_old_reaction_isnt_decremented() {
+        let dynamodb_client = InMemoryDynamoDb::new().with_table(TABLE, "pk", Some("sk"));
+        let dao = build_dao(&dynamodb_client);
+        dao.setup_counts(GROUP, DATE).await.unwrap();
+        dynamodb_client
+            .update_item_with_keys(
+                TABLE,
+                dao.build_user_reaction_key_and_attribute(GROUP, DATE, "user-1"),
+                "SET reaction = :reaction".to_owned(),
+                ReturnValue::None,
+                None,
+                vec![KeyAndAttribute {
+                    key: ":reaction",
+                    attribute: AttributeValue::S(Reactions::Eesh.to_string()),
+                }],
+            )
+            .await
+            .unwrap();
+
+        let old_reaction = dao
+            .set_reaction(GROUP, DATE, "user-1", &Reactions::Love)
+            .await
+            .unwrap();
+        let counts = dao.get_counts(GROUP, DATE).await.unwrap();
+
+        assert!(matches!(old_reaction, Reactions::Eesh));
+        assert_eq!(count(&counts, "Love"), Some("1".to_owned()));
+        assert_eq!(count(&counts, "Eesh"), None);
     }
 
-    error!("Error was a failure to update the previous value");
-    Err(UserReactionDaoError::DynamoDbError(err))
+    #[tokio::test]
+    async fn concurrent_change_is_retried() {
+        let dynamodb_client = InMemoryDynamoDb::new()
+            .with_table(TABLE, "pk", Some("sk"))
+            .with_conditional_check_failures(MAX_SET_REACTION_ATTEMPTS - 1);
+        let dao = build_dao(&dynamodb_client);
+        dao.setup_counts(GROUP, DATE).await.unwrap();
+
+        dao.set_reaction(GROUP, DATE, "user-1", &Reactions::Love)
+            .await
+            .unwrap();
+        let counts = dao.get_counts(GROUP, DATE).await.unwrap();
+
+        assert_eq!(count(&counts, "Love"), Some("1".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn concurrent_change_on_every_attempt_is_surfaced() {
+        let dynamodb_client = InMemoryDynamoDb::new()
+            .with_table(TABLE, "pk", Some("sk"))
+            .with_conditional_check_failures(MAX_SET_REACTION_ATTEMPTS);
+        let dao = build_dao(&dynamodb_client);
+        dao.setup_counts(GROUP, DATE).await.unwrap();
+
+        let result = dao
+            .set_reaction(GROUP, DATE, "user-1", &Reactions::Love)
+            .await;
+        let counts = dao.get_counts(GROUP, DATE).await.unwrap();
+        let user_items = dao.get(GROUP, DATE, "user-1").await.unwrap();
+
+        assert!(matches!(
+            result,
+            Err(UserReactionDaoError::DynamoDbError(
+                DynamoDbUtilError::ConditionalCheckFailure(_)
+            ))
+        ));
+        assert_eq!(count(&counts, "Love"), Some("0".to_owned()));
+        assert_eq!(user_items.reaction, Reactions::NoReaction.to_string());
+    }
 }
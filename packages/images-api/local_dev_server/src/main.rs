@@ -0,0 +1,223 @@
+mod routes;
+
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use aws_config::BehaviorVersion;
+use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoDbClient};
+use aws_sdk_s3::Client as S3Client;
+use daily_setup_lambda::{daily_setup::setup_all_groups_by_day, select_and_set::SelectionMode};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Server,
+};
+use lambda_utils::aws_sdk::{
+    aws_dynamodb::{DynamoDbUtil, KeyAndAttribute},
+    aws_s3::S3Util,
+    in_memory_dynamodb::InMemoryDynamoDb,
+    in_memory_s3::InMemoryS3,
+};
+use tracing::{error, info};
+
+use crate::routes::{route, DevServerState};
+
+/*
+ * Serves the image API routes locally without Lambda or API Gateway.
+ *
+ * Plain HTTP requests are converted to `ApiGatewayV2httpRequest`s and handed to the same handler
+ * functions the lambdas run. DynamoDB and S3 are either real clients pointed at `DYNAMODB_ENDPOINT`
+ * and `S3_ENDPOINT` (e.g. DynamoDB Local or MinIO) or in-memory stores when those aren't set.
+ */
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_runtime::Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .init();
+
+    let config = DevServerConfig::build();
+    info!(config = ?config, "Starting the local dev server with the following config");
+
+    // In-memory stores start out empty so they are seeded with the default group and today's image.
+    // Real endpoints are left alone and are expected to already be set up
+    match (config.dynamodb_endpoint.clone(), config.s3_endpoint.clone()) {
+        (Some(dynamodb_endpoint), Some(s3_endpoint)) => {
+            let dynamodb_client = build_dynamodb_client(&dynamodb_endpoint).await;
+            let s3_client = build_s3_client(&s3_endpoint).await;
+            serve(config, dynamodb_client, s3_client, false).await
+        }
+        (Some(dynamodb_endpoint), None) => {
+            let dynamodb_client = build_dynamodb_client(&dynamodb_endpoint).await;
+            let s3_client = build_in_memory_s3(&config);
+            serve(config, dynamodb_client, s3_client, false).await
+        }
+        (None, Some(s3_endpoint)) => {
+            let dynamodb_client = build_in_memory_dynamodb(&config).await?;
+            let s3_client = build_s3_client(&s3_endpoint).await;
+            serve(config, dynamodb_client, s3_client, true).await
+        }
+        (None, None) => {
+            let dynamodb_client = build_in_memory_dynamodb(&config).await?;
+            let s3_client = build_in_memory_s3(&config);
+            serve(config, dynamodb_client, s3_client, true).await
+        }
+    }
+}
+
+async fn serve<D: DynamoDbUtil + 'static, S: S3Util + 'static>(
+    config: DevServerConfig,
+    dynamodb_client: D,
+    s3_client: S,
+    setup_today: bool,
+) -> Result<(), lambda_runtime::Error> {
+    let address = SocketAddr::from(([127, 0, 0, 1], config.port));
+    let state = Arc::new(DevServerState::build(&config, dynamodb_client, s3_client));
+
+    if setup_today {
+        if let Err(err) = setup_all_groups_by_day(
+            &state.daily_setup_environment_variables,
+            &state.dynamodb_client,
+            &state.s3_client,
+            |group_day| group_day.today(),
+        )
+        .await
+        {
+            // Not fatal since the image can still be set up later through POST /dev/daily-setup
+            error!(error = err, "Failed to set up today's image");
+        }
+    }
+
+    let make_service = make_service_fn(move |_connection| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| route(state.clone(), request))) }
+    });
+
+    info!("Listening on http://{}", address);
+    Server::bind(&address).serve(make_service).await?;
+
+    Ok(())
+}
+
+async fn build_in_memory_dynamodb(
+    config: &DevServerConfig,
+) -> Result<InMemoryDynamoDb, lambda_runtime::Error> {
+    let dynamodb_client = InMemoryDynamoDb::new().with_table(
+        &config.table_name,
+        &config.table_primary_key,
+        Some(&config.table_sort_key),
+    );
+
+    // Register the default group so requests without a group resolve to something
+    dynamodb_client
+        .put_item_from_keys(
+            &config.table_name,
+            vec![
+                KeyAndAttribute {
+                    key: &config.table_primary_key,
+                    attribute: AttributeValue::S("Groups".to_owned()),
+                },
+                KeyAndAttribute {
+                    key: &config.table_sort_key,
+                    attribute: AttributeValue::S("Registry".to_owned()),
+                },
+                KeyAndAttribute {
+                    key: "group_names",
+                    attribute: AttributeValue::Ss(vec![config.default_group.clone()]),
+                },
+            ],
+            None,
+            None,
+            Vec::new(),
+        )
+        .await
+        .map_err(|err| format!("Failed to register the default group: {:?}", err))?;
+
+    Ok(dynamodb_client)
+}
+
+fn build_in_memory_s3(config: &DevServerConfig) -> InMemoryS3 {
+    let s3_client = InMemoryS3::new();
+    for key in &config.seed_images {
+        s3_client.put_object(&config.bucket_name, key, Vec::new());
+    }
+
+    s3_client
+}
+
+async fn build_dynamodb_client(endpoint: &str) -> DynamoDbClient {
+    let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+    let dynamodb_config = aws_sdk_dynamodb::config::Builder::from(&config)
+        .endpoint_url(endpoint)
+        .build();
+
+    DynamoDbClient::from_conf(dynamodb_config)
+}
+
+async fn build_s3_client(endpoint: &str) -> S3Client {
+    let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+    // Local S3 implementations like MinIO don't support virtual hosted style bucket urls
+    let s3_config = aws_sdk_s3::config::Builder::from(&config)
+        .endpoint_url(endpoint)
+        .force_path_style(true)
+        .build();
+
+    S3Client::from_conf(s3_config)
+}
+
+#[derive(Debug)]
+pub struct DevServerConfig {
+    pub port: u16,
+    pub default_group: String,
+    pub image_domain: String,
+    pub bucket_name: String,
+    pub table_name: String,
+    pub table_primary_key: String,
+    pub table_sort_key: String,
+    pub dynamodb_endpoint: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub seed_images: Vec<String>,
+    pub selection_mode: SelectionMode,
+    pub admin_api_token: String,
+}
+
+impl DevServerConfig {
+    fn build() -> DevServerConfig {
+        let default_group = env_or("DEFAULT_GROUP", "discord");
+        // By default seed a few images for the default group so there is something to pick from
+        let seed_images = std::env::var("SEED_IMAGES")
+            .unwrap_or_else(|_| {
+                (1..=10)
+                    .map(|number| format!("{}/image_{}.png", default_group, number))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .split(',')
+            .map(|key| key.trim().to_owned())
+            .filter(|key| !key.is_empty())
+            .collect();
+
+        DevServerConfig {
+            port: env_or("PORT", "3000")
+                .parse()
+                .expect("PORT should be a valid port number"),
+            image_domain: env_or("IMAGE_DOMAIN", "localhost"),
+            bucket_name: env_or("BUCKET_NAME", "images"),
+            table_name: env_or("TABLE_NAME", "ImageTable"),
+            table_primary_key: env_or("TABLE_PRIMARY_KEY", "pk"),
+            table_sort_key: env_or("TABLE_SORT_KEY", "sk"),
+            selection_mode: env_or("SELECTION_MODE", "reservoir_sample")
+                .parse()
+                .expect("SELECTION_MODE should be list_all or reservoir_sample"),
+            // Only used locally so a well known default is fine
+            admin_api_token: env_or("ADMIN_API_TOKEN", "local-admin-token"),
+            dynamodb_endpoint: std::env::var("DYNAMODB_ENDPOINT").ok(),
+            s3_endpoint: std::env::var("S3_ENDPOINT").ok(),
+            default_group,
+            seed_images,
+        }
+    }
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_owned())
+}
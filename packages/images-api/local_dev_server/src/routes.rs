@@ -0,0 +1,360 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+
+use aws_lambda_events::encodings::Body as ApiGatewayBody;
+use aws_lambda_events::event::apigw::{
+    ApiGatewayV2httpRequest, ApiGatewayV2httpRequestContext,
+    ApiGatewayV2httpRequestContextHttpDescription, ApiGatewayV2httpResponse,
+};
+use chrono::{Local, NaiveDate};
+use daily_setup_lambda::daily_setup::setup_all_groups_by_day;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use lambda_utils::aws_sdk::{
+    api_gateway::GROUP_PATH_PARAMETER, aws_dynamodb::DynamoDbUtil, aws_s3::S3Util,
+};
+use query_map::QueryMap;
+use tracing::{error, info};
+
+use crate::DevServerConfig;
+
+pub struct DevServerState<D, S> {
+    pub get_image_environment_variables: get_image_lambda::handler::EnvironmentVariables,
+    pub reaction_environment_variables: get_or_set_reaction_lambda::handler::EnvironmentVariables,
+    pub favorite_environment_variables: set_favorite_recent_lambda::handler::EnvironmentVariables,
+    pub archive_environment_variables: archive_image_lambda::handler::EnvironmentVariables,
+    pub pin_environment_variables: pin_image_lambda::handler::EnvironmentVariables,
+    pub queue_environment_variables: image_queue_lambda::handler::EnvironmentVariables,
+    pub daily_setup_environment_variables: daily_setup_lambda::daily_setup::EnvironmentVariables,
+    pub dynamodb_client: D,
+    pub s3_client: S,
+}
+
+impl<D, S> DevServerState<D, S> {
+    pub fn build(config: &DevServerConfig, dynamodb_client: D, s3_client: S) -> Self {
+        let default_group = Some(config.default_group.clone());
+        let daily_setup_environment_variables =
+            daily_setup_lambda::daily_setup::EnvironmentVariables {
+                bucket_name: config.bucket_name.clone(),
+                table_name: config.table_name.clone(),
+                table_primary_key: config.table_primary_key.clone(),
+                table_sort_key: config.table_sort_key.clone(),
+                selection_mode: config.selection_mode,
+            };
+
+        DevServerState {
+            get_image_environment_variables: get_image_lambda::handler::EnvironmentVariables {
+                image_domain: config.image_domain.clone(),
+                default_group: default_group.clone(),
+                table_name: config.table_name.clone(),
+                table_primary_key: config.table_primary_key.clone(),
+                table_sort_key: config.table_sort_key.clone(),
+                daily_setup_environment_variables: daily_setup_environment_variables.clone(),
+            },
+            reaction_environment_variables:
+                get_or_set_reaction_lambda::handler::EnvironmentVariables {
+                    default_group: default_group.clone(),
+                    table_name: config.table_name.clone(),
+                    table_primary_key: config.table_primary_key.clone(),
+                    table_sort_key: config.table_sort_key.clone(),
+                },
+            favorite_environment_variables:
+                set_favorite_recent_lambda::handler::EnvironmentVariables {
+                    default_group: default_group.clone(),
+                    table_name: config.table_name.clone(),
+                    table_primary_key: config.table_primary_key.clone(),
+                    table_sort_key: config.table_sort_key.clone(),
+                },
+            archive_environment_variables: archive_image_lambda::handler::EnvironmentVariables {
+                default_group: default_group.clone(),
+                admin_token: config.admin_api_token.clone(),
+                table_name: config.table_name.clone(),
+                table_primary_key: config.table_primary_key.clone(),
+                table_sort_key: config.table_sort_key.clone(),
+            },
+            pin_environment_variables: pin_image_lambda::handler::EnvironmentVariables {
+                default_group: default_group.clone(),
+                admin_token: config.admin_api_token.clone(),
+                bucket_name: config.bucket_name.clone(),
+                table_name: config.table_name.clone(),
+                table_primary_key: config.table_primary_key.clone(),
+                table_sort_key: config.table_sort_key.clone(),
+            },
+            queue_environment_variables: image_queue_lambda::handler::EnvironmentVariables {
+                default_group,
+                admin_token: config.admin_api_token.clone(),
+                table_name: config.table_name.clone(),
+                table_primary_key: config.table_primary_key.clone(),
+                table_sort_key: config.table_sort_key.clone(),
+            },
+            daily_setup_environment_variables,
+            dynamodb_client,
+            s3_client,
+        }
+    }
+}
+
+///
+/// Routes a request to the handler of the matching lambda. Mirrors the routes in `sst.config.ts`,
+/// each of which is available both with and without a leading `/{group}`.
+///
+/// Also serves `POST /dev/daily-setup?date=YYYY-MM-DD` which runs the daily setup for the provided
+/// date (today if not provided) since there is no cron locally.
+///
+pub async fn route<D: DynamoDbUtil, S: S3Util>(
+    state: Arc<DevServerState<D, S>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    info!(method = ?request.method(), path = request.uri().path(), "Received a request");
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+
+    if method == Method::POST && path == "/dev/daily-setup" {
+        return Ok(daily_setup(&state, &request).await);
+    }
+
+    let (group, route) = split_group(&path);
+    let route_key = match &group {
+        Some(_) => format!("{} /{{{}}}{}", method, GROUP_PATH_PARAMETER, route),
+        None => format!("{} {}", method, route),
+    };
+
+    let result = match (&method, route) {
+        (&Method::GET, "/todays-image") | (&Method::GET, "/history") => {
+            let request = match to_api_gateway_request(request, route_key, group).await {
+                Ok(request) => request,
+                Err(response) => return Ok(response),
+            };
+            get_image_lambda::handler::handler(
+                &state.get_image_environment_variables,
+                &state.dynamodb_client,
+                &state.s3_client,
+                request,
+            )
+            .await
+        }
+        (&Method::GET, "/todays-metadata") | (&Method::PUT, "/todays-metadata") => {
+            let request = match to_api_gateway_request(request, route_key, group).await {
+                Ok(request) => request,
+                Err(response) => return Ok(response),
+            };
+            get_or_set_reaction_lambda::handler::handler(
+                &state.reaction_environment_variables,
+                &state.dynamodb_client,
+                request,
+            )
+            .await
+        }
+        (&Method::PUT, "/set-favorite") => {
+            let request = match to_api_gateway_request(request, route_key, group).await {
+                Ok(request) => request,
+                Err(response) => return Ok(response),
+            };
+            set_favorite_recent_lambda::handler::handler(
+                &state.favorite_environment_variables,
+                &state.dynamodb_client,
+                request,
+            )
+            .await
+        }
+        (&Method::PUT, "/archive") => {
+            let request = match to_api_gateway_request(request, route_key, group).await {
+                Ok(request) => request,
+                Err(response) => return Ok(response),
+            };
+            archive_image_lambda::handler::handler(
+                &state.archive_environment_variables,
+                &state.dynamodb_client,
+                request,
+            )
+            .await
+        }
+        (&Method::PUT, "/pin") => {
+            let request = match to_api_gateway_request(request, route_key, group).await {
+                Ok(request) => request,
+                Err(response) => return Ok(response),
+            };
+            pin_image_lambda::handler::handler(
+                &state.pin_environment_variables,
+                &state.dynamodb_client,
+                &state.s3_client,
+                request,
+            )
+            .await
+        }
+        (&Method::GET, "/queue") | (&Method::PUT, "/queue") => {
+            let request = match to_api_gateway_request(request, route_key, group).await {
+                Ok(request) => request,
+                Err(response) => return Ok(response),
+            };
+            image_queue_lambda::handler::handler(
+                &state.queue_environment_variables,
+                &state.dynamodb_client,
+                request,
+            )
+            .await
+        }
+        _ => return Ok(text_response(StatusCode::NOT_FOUND, "Not Found".to_owned())),
+    };
+
+    // A lambda returning an error shows up as a 500 from API Gateway
+    Ok(match result {
+        Ok(response) => from_api_gateway_response(response),
+        Err(err) => {
+            error!(error = ?err, "The handler failed with the following");
+            text_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal Server Error".to_owned(),
+            )
+        }
+    })
+}
+
+async fn daily_setup<D: DynamoDbUtil, S: S3Util>(
+    state: &DevServerState<D, S>,
+    request: &Request<Body>,
+) -> Response<Body> {
+    let date = match parse_query(request.uri().query()).first("date") {
+        Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => Some(date),
+            Err(_) => {
+                return text_response(
+                    StatusCode::BAD_REQUEST,
+                    "The date should be formatted as YYYY-MM-DD".to_owned(),
+                )
+            }
+        },
+        None => None,
+    };
+
+    // Without a date each group is set up from its own today
+    match setup_all_groups_by_day(
+        &state.daily_setup_environment_variables,
+        &state.dynamodb_client,
+        &state.s3_client,
+        |group_day| date.unwrap_or_else(|| group_day.today()),
+    )
+    .await
+    {
+        Ok(()) => text_response(
+            StatusCode::OK,
+            match date {
+                Some(date) => format!("Set up {} for every group", date),
+                None => "Set up today for every group".to_owned(),
+            },
+        ),
+        Err(err) => text_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+
+///
+/// Splits `/{group}/{route}` into the group and `/{route}`. Paths with a single segment have no group.
+///
+fn split_group(path: &str) -> (Option<String>, &str) {
+    let trimmed = path.trim_start_matches('/');
+    match trimmed.split_once('/') {
+        Some((group, _)) if !group.is_empty() => (Some(group.to_owned()), &trimmed[group.len()..]),
+        _ => (None, path),
+    }
+}
+
+///
+/// Converts a plain HTTP request into the request API Gateway would hand the lambda.
+///
+/// # Returns
+/// * `Ok(ApiGatewayV2httpRequest)` - The converted request
+/// * `Err(Response<Body>)` - A 400 response if the body couldn't be read as UTF-8
+///
+async fn to_api_gateway_request(
+    request: Request<Body>,
+    route_key: String,
+    group: Option<String>,
+) -> Result<ApiGatewayV2httpRequest, Response<Body>> {
+    let (parts, body) = request.into_parts();
+
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(bytes) if bytes.is_empty() => None,
+        Ok(bytes) => match String::from_utf8(bytes.to_vec()) {
+            Ok(body) => Some(body),
+            Err(_) => {
+                return Err(text_response(
+                    StatusCode::BAD_REQUEST,
+                    "The request body should be UTF-8".to_owned(),
+                ))
+            }
+        },
+        Err(err) => {
+            return Err(text_response(
+                StatusCode::BAD_REQUEST,
+                format!("Failed to read the request body: {}", err),
+            ))
+        }
+    };
+
+    let mut path_parameters = HashMap::new();
+    if let Some(group) = group {
+        path_parameters.insert(GROUP_PATH_PARAMETER.to_owned(), group);
+    }
+
+    Ok(ApiGatewayV2httpRequest {
+        http_method: parts.method.clone(),
+        route_key: Some(route_key.clone()),
+        raw_path: Some(parts.uri.path().to_owned()),
+        raw_query_string: parts.uri.query().map(|query| query.to_owned()),
+        query_string_parameters: parse_query(parts.uri.query()),
+        path_parameters,
+        headers: parts.headers,
+        body,
+        request_context: ApiGatewayV2httpRequestContext {
+            route_key: Some(route_key),
+            stage: Some("$default".to_owned()),
+            request_id: Some(format!(
+                "local-{}",
+                Local::now().timestamp_nanos_opt().unwrap_or_default()
+            )),
+            http: ApiGatewayV2httpRequestContextHttpDescription {
+                method: parts.method,
+                path: Some(parts.uri.path().to_owned()),
+                protocol: Some(format!("{:?}", parts.version)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+fn from_api_gateway_response(api_gateway_response: ApiGatewayV2httpResponse) -> Response<Body> {
+    let body = match api_gateway_response.body {
+        Some(ApiGatewayBody::Text(text)) => Body::from(text),
+        Some(ApiGatewayBody::Binary(bytes)) => Body::from(bytes),
+        Some(ApiGatewayBody::Empty) | None => Body::empty(),
+    };
+
+    let mut response = Response::new(body);
+    *response.status_mut() = u16::try_from(api_gateway_response.status_code)
+        .ok()
+        .and_then(|status_code| StatusCode::from_u16(status_code).ok())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    *response.headers_mut() = api_gateway_response.headers;
+
+    response
+}
+
+fn parse_query(query: Option<&str>) -> QueryMap {
+    let mut parameters: HashMap<String, Vec<String>> = HashMap::new();
+    for (key, value) in form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        parameters
+            .entry(key.into_owned())
+            .or_default()
+            .push(value.into_owned());
+    }
+
+    QueryMap::from(parameters)
+}
+
+fn text_response(status_code: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status_code;
+
+    response
+}
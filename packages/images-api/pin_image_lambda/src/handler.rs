@@ -0,0 +1,224 @@
+use aws_lambda_events::event::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpResponse};
+
+use chrono::{NaiveDate, ParseError};
+use lambda_utils::aws_sdk::api_gateway::{authorize_admin, resolve_group, ApiError};
+use lambda_utils::aws_sdk::aws_dynamodb::{DynamoDbUtil, DynamoDbUtilError};
+use lambda_utils::aws_sdk::aws_s3::S3Util;
+use lambda_utils::aws_sdk::router::{Request, Router};
+use lambda_utils::models::{SstBucket, SstSecret, SstTable};
+use lambda_utils::persistence::group_dao::GroupDao;
+use lambda_utils::persistence::image_dynamo_dao::{ImageDynamoDao, ImageDynamoDaoError};
+use lambda_utils::persistence::image_s3_dao::{group_prefix, ImageS3Dao, ImageS3DaoError};
+use serde::{Deserialize, Serialize};
+use sst_sdk::Resource;
+use tracing::{info, instrument};
+
+#[instrument(skip_all)]
+pub async fn handler<D: DynamoDbUtil, S: S3Util>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    s3_client: &S,
+    req: ApiGatewayV2httpRequest,
+) -> Result<ApiGatewayV2httpResponse, lambda_runtime::Error> {
+    // The request isn't logged since it holds the admin token
+    info!(route_key = ?req.route_key, "The route passed into the lambda is");
+
+    let image_dynamo_dao = ImageDynamoDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let image_s3_dao = ImageS3Dao {
+        bucket_name: &environment_variables.bucket_name,
+        s3_client,
+    };
+
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    if let Err(api_gateway_response) = authorize_admin(&req, &environment_variables.admin_token) {
+        return Ok(*api_gateway_response);
+    }
+
+    let group = match resolve_group(
+        &req,
+        environment_variables.default_group.as_deref(),
+        &group_dao,
+    )
+    .await
+    {
+        Ok(group) => group,
+        Err(api_gateway_response) => return Ok(api_gateway_response),
+    };
+
+    let today = group_dao.get_group_day(&group).await.today();
+
+    let router = Router::new().put("/pin", |request: Request<RequestBody>| {
+        handle_put(
+            request.body,
+            &group,
+            today,
+            &image_dynamo_dao,
+            &image_s3_dao,
+        )
+    });
+
+    Ok(router.handle(req).await)
+}
+
+// Body of the request to be recevied
+#[derive(Serialize, Deserialize, Debug)]
+struct RequestBody {
+    object_key: String,
+    // Formatted as YYYY-MM-DD
+    date: String,
+}
+
+// Body of the response for PUT
+#[derive(Serialize, Deserialize, Debug)]
+struct ResponseBody {
+    object_key: String,
+    date: String,
+}
+
+// Error enum for PUT
+#[derive(Debug)]
+pub enum PutHandlerError {
+    DateParseError(ParseError),
+    ImageDynamoDaoError(ImageDynamoDaoError),
+    ImageS3DaoError(ImageS3DaoError),
+    InvalidRequest(String),
+    ObjectNotFound(String),
+    LocalError(String),
+}
+
+impl From<ParseError> for PutHandlerError {
+    fn from(err: ParseError) -> Self {
+        Self::DateParseError(err)
+    }
+}
+
+impl From<ImageDynamoDaoError> for PutHandlerError {
+    fn from(err: ImageDynamoDaoError) -> Self {
+        Self::ImageDynamoDaoError(err)
+    }
+}
+
+impl From<ImageS3DaoError> for PutHandlerError {
+    fn from(err: ImageS3DaoError) -> Self {
+        Self::ImageS3DaoError(err)
+    }
+}
+
+impl From<String> for PutHandlerError {
+    fn from(err: String) -> Self {
+        Self::LocalError(err)
+    }
+}
+
+impl From<PutHandlerError> for ApiError {
+    fn from(err: PutHandlerError) -> Self {
+        match err {
+            PutHandlerError::DateParseError(err) => ApiError::BadRequest(format!(
+                "The date should be formatted as YYYY-MM-DD: {}",
+                err
+            )),
+            PutHandlerError::InvalidRequest(message) => ApiError::BadRequest(message),
+            PutHandlerError::ObjectNotFound(object_key) => {
+                ApiError::NotFound(format!("There is no image with the key {}", object_key))
+            }
+            // The date's image kept changing while it was being pinned
+            PutHandlerError::ImageDynamoDaoError(ImageDynamoDaoError::DynamoDbError(
+                DynamoDbUtilError::ConditionalCheckFailure(_),
+            )) => ApiError::Conflict(
+                "The date's image was changed by another request, try again".to_owned(),
+            ),
+            err => ApiError::internal(err),
+        }
+    }
+}
+
+async fn handle_put<D: DynamoDbUtil, S: S3Util>(
+    body: RequestBody,
+    group: &str,
+    today: NaiveDate,
+    image_dynamo_dao: &ImageDynamoDao<'_, D>,
+    image_s3_dao: &ImageS3Dao<'_, S>,
+) -> Result<ResponseBody, PutHandlerError> {
+    info!(body = ?body, "The parsed body value");
+
+    // A group's images are the objects under the group's prefix
+    if !body.object_key.starts_with(&group_prefix(group)) {
+        return Err(PutHandlerError::InvalidRequest(format!(
+            "The image {} does not belong to group {}",
+            body.object_key, group
+        )));
+    }
+
+    let date = NaiveDate::parse_from_str(&body.date, "%Y-%m-%d")?;
+
+    // Today's image may already have been seen so only upcoming dates can be pinned
+    if date <= today {
+        return Err(PutHandlerError::InvalidRequest(format!(
+            "Only dates after {} can be pinned",
+            today
+        )));
+    }
+
+    let object = image_s3_dao
+        .get_by_key(&body.object_key)
+        .await?
+        .ok_or_else(|| PutHandlerError::ObjectNotFound(body.object_key.to_owned()))?;
+
+    let object_key = image_dynamo_dao.pin_image(group, object, date).await?;
+
+    info!(
+        object_key = object_key,
+        date = ?date,
+        "Request to pin the image complete"
+    );
+
+    Ok(ResponseBody {
+        object_key,
+        date: date.format("%Y-%m-%d").to_string(),
+    })
+}
+
+pub struct EnvironmentVariables {
+    pub default_group: Option<String>,
+    pub admin_token: String,
+    pub bucket_name: String,
+    pub table_name: String,
+    pub table_primary_key: String,
+    pub table_sort_key: String,
+}
+
+impl EnvironmentVariables {
+    pub fn build() -> EnvironmentVariables {
+        let resource = Resource::init().expect("Should be able to initialize SST resource");
+        let table: SstTable = resource
+            .get("ImageTable")
+            .expect("Should be able to get ImageTable");
+        let bucket: SstBucket = resource
+            .get("ViewableBucketReadOnly")
+            .expect("Should have a ViewableBucketReadOnly resource");
+        let admin_token: SstSecret = resource
+            .get("AdminApiToken")
+            .expect("Should have an AdminApiToken resource");
+
+        EnvironmentVariables {
+            default_group: std::env::var("DEFAULT_GROUP").ok(),
+            admin_token: admin_token.value,
+            bucket_name: bucket.name,
+            table_name: table.name,
+            table_primary_key: table.primary_key,
+            table_sort_key: table.sort_key,
+        }
+    }
+}
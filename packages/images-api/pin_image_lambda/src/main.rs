@@ -0,0 +1,55 @@
+use aws_config::BehaviorVersion;
+use aws_lambda_events::event::apigw::ApiGatewayV2httpRequest;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use aws_sdk_s3::Client as S3Client;
+use lambda_runtime::{service_fn, LambdaEvent};
+use pin_image_lambda::handler::{handler, EnvironmentVariables};
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_runtime::Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        // disable printing the name of the module in every log line.
+        .with_target(false)
+        // disabling time is handy because CloudWatch will add the ingestion time.
+        .without_time()
+        .init();
+
+    let environment_variables = EnvironmentVariables::build();
+    let aws_clients = AwsClients::build().await;
+
+    lambda_runtime::run(service_fn(
+        |request: LambdaEvent<ApiGatewayV2httpRequest>| {
+            handler(
+                &environment_variables,
+                &aws_clients.dynamodb_client,
+                &aws_clients.s3_client,
+                request.payload,
+            )
+        },
+    ))
+    .await?;
+
+    Ok(())
+}
+
+struct AwsClients {
+    dynamodb_client: DynamoDbClient,
+    s3_client: S3Client,
+}
+
+impl AwsClients {
+    async fn build() -> AwsClients {
+        // No extra configuration is needed as long as your Lambda has
+        // the necessary permissions attached to its role.
+        let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+
+        let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+        let s3_client = aws_sdk_s3::Client::new(&config);
+
+        AwsClients {
+            dynamodb_client,
+            s3_client,
+        }
+    }
+}
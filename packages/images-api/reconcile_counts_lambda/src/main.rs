@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use aws_config::BehaviorVersion;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use chrono::{Duration, NaiveDate};
+use lambda_runtime::{run, service_fn, Error, LambdaEvent};
+use lambda_utils::{
+    models::SstTable,
+    persistence::{group_dao::GroupDao, user_reaction_dao::UserReactionDao},
+};
+use serde::{Deserialize, Serialize};
+use sst_sdk::Resource;
+use tracing::{error, info};
+
+// Keeps a typo in the dates from scanning years worth of partitions
+const MAX_DAYS: i64 = 366;
+
+///
+/// Invoked manually, e.g. `npx sst shell -- aws lambda invoke ...` or from the console, with:
+/// `{ "group": "discord", "start_date": "2024-01-01", "end_date": "2024-01-07", "apply": false }`
+///
+/// `group` defaults to every registered group, `end_date` defaults to `start_date` and `apply`
+/// defaults to false which only reports the differences without writing anything.
+///
+#[derive(Deserialize, Debug)]
+struct Request {
+    group: Option<String>,
+    start_date: String,
+    end_date: Option<String>,
+    #[serde(default)]
+    apply: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct Response {
+    reconciled: Vec<ReconciledDay>,
+    failed: Vec<FailedDay>,
+}
+
+#[derive(Serialize, Debug)]
+struct ReconciledDay {
+    group: String,
+    date: String,
+    applied: bool,
+    stored_counts: HashMap<String, String>,
+    recomputed_counts: HashMap<String, String>,
+    differences: Vec<Difference>,
+}
+
+#[derive(Serialize, Debug)]
+struct Difference {
+    reaction: String,
+    stored: Option<String>,
+    recomputed: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct FailedDay {
+    group: String,
+    date: String,
+    error: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        // disable printing the name of the module in every log line.
+        .with_target(false)
+        // disabling time is handy because CloudWatch will add the ingestion time.
+        .without_time()
+        .init();
+
+    let environment_variables = EnvironmentVariables::build();
+    let aws_clients = AwsClients::build().await;
+
+    run(service_fn(|request: LambdaEvent<Request>| {
+        function_handler(&environment_variables, &aws_clients, request.payload)
+    }))
+    .await
+}
+
+async fn function_handler(
+    environment_variables: &EnvironmentVariables,
+    aws_clients: &AwsClients,
+    event: Request,
+) -> Result<Response, Error> {
+    info!(event = ?event, "The event passed into the lambda is");
+
+    let start_date = NaiveDate::parse_from_str(&event.start_date, "%Y-%m-%d")?;
+    let end_date = match &event.end_date {
+        Some(end_date) => NaiveDate::parse_from_str(end_date, "%Y-%m-%d")?,
+        None => start_date,
+    };
+
+    let days = (end_date - start_date).num_days() + 1;
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err(format!(
+            "The end date must be on or after the start date and at most {} days later",
+            MAX_DAYS
+        )
+        .into());
+    }
+
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client: &aws_clients.dynamodb_client,
+    };
+
+    let user_reaction_dao = UserReactionDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client: &aws_clients.dynamodb_client,
+    };
+
+    let groups = match event.group {
+        Some(group) => {
+            group_dao
+                .validate_group(&group)
+                .await
+                .map_err(|err| format!("Failed to validate the group: {:?}", err))?;
+            vec![group]
+        }
+        None => group_dao
+            .list_groups()
+            .await
+            .map_err(|err| format!("Failed to list the registered groups: {:?}", err))?,
+    };
+
+    // Every day is attempted even if an earlier one fails so one bad day can't block the rest
+    let mut response = Response {
+        reconciled: Vec::new(),
+        failed: Vec::new(),
+    };
+    for group in groups {
+        for offset in 0..days {
+            let date = (start_date + Duration::days(offset))
+                .format("%Y-%m-%d")
+                .to_string();
+
+            match user_reaction_dao
+                .reconcile_counts(&group, &date, event.apply)
+                .await
+            {
+                Ok(reconciliation) => {
+                    info!(
+                        group = group,
+                        date = date,
+                        differences = ?reconciliation.differences,
+                        applied = reconciliation.applied,
+                        "Reconciled the counts"
+                    );
+
+                    response.reconciled.push(ReconciledDay {
+                        group: group.to_owned(),
+                        date,
+                        applied: reconciliation.applied,
+                        stored_counts: reconciliation.stored_counts,
+                        recomputed_counts: reconciliation.recomputed_counts,
+                        differences: reconciliation
+                            .differences
+                            .into_iter()
+                            .map(|difference| Difference {
+                                reaction: difference.reaction,
+                                stored: difference.stored,
+                                recomputed: difference.recomputed,
+                            })
+                            .collect(),
+                    });
+                }
+                Err(err) => {
+                    error!(group = group, date = date, error = ?err, "Failed to reconcile the counts");
+
+                    response.failed.push(FailedDay {
+                        group: group.to_owned(),
+                        date,
+                        error: format!("{:?}", err),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(response)
+}
+
+#[derive(Debug)]
+struct EnvironmentVariables {
+    table_name: String,
+    table_primary_key: String,
+    table_sort_key: String,
+}
+
+impl EnvironmentVariables {
+    fn build() -> EnvironmentVariables {
+        let resource =
+            Resource::init().expect("Should be able to initialize the SST resource object");
+
+        let table: SstTable = resource
+            .get("ImageTable")
+            .expect("Should have an ImageTable resource");
+
+        EnvironmentVariables {
+            table_name: table.name,
+            table_primary_key: table.primary_key,
+            table_sort_key: table.sort_key,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AwsClients {
+    dynamodb_client: DynamoDbClient,
+}
+
+impl AwsClients {
+    async fn build() -> AwsClients {
+        // No extra configuration is needed as long as your Lambda has
+        // the necessary permissions attached to its role.
+        let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+
+        let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+
+        AwsClients { dynamodb_client }
+    }
+}
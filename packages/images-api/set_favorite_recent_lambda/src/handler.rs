@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use aws_lambda_events::event::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpResponse};
+use chrono::NaiveDate;
+
+use lambda_utils::aws_sdk::api_gateway::{resolve_group, ApiError};
+use lambda_utils::aws_sdk::aws_dynamodb::{DynamoDbUtil, DynamoDbUtilError};
+use lambda_utils::aws_sdk::router::{Request, Router};
+use lambda_utils::models::SstTable;
+use lambda_utils::persistence::group_dao::{GroupDao, GroupSettings};
+use lambda_utils::persistence::image_dynamo_dao::{ImageDynamoDao, ImageDynamoDaoError};
+use lambda_utils::persistence::user_reaction_dao::{
+    object_key_from_favorite, UserReactionDao, UserReactionDaoError,
+};
+use serde::{Deserialize, Serialize};
+use sst_sdk::Resource;
+use tracing::{error, info, instrument};
+
+#[instrument(skip_all)]
+pub async fn handler<D: DynamoDbUtil>(
+    environment_variables: &EnvironmentVariables,
+    dynamodb_client: &D,
+    req: ApiGatewayV2httpRequest,
+) -> Result<ApiGatewayV2httpResponse, lambda_runtime::Error> {
+    info!(event = ?req, "The req passed into the lambda is");
+
+    let user_reaction_dao = UserReactionDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let image_dao = ImageDynamoDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let group_dao = GroupDao {
+        table_name: &environment_variables.table_name,
+        primary_key: &environment_variables.table_primary_key,
+        sort_key: &environment_variables.table_sort_key,
+        dynamodb_client,
+    };
+
+    let group = match resolve_group(
+        &req,
+        environment_variables.default_group.as_deref(),
+        &group_dao,
+    )
+    .await
+    {
+        Ok(group) => group,
+        Err(api_gateway_response) => return Ok(api_gateway_response),
+    };
+
+    let settings = group_dao.get_settings(&group).await.unwrap_or_else(|err| {
+        error!(error = ?err, "Failed to read the group's settings. Using the defaults");
+        GroupSettings::default()
+    });
+
+    let today = settings.group_day().today();
+
+    info!(today = ?today, "Today is");
+
+    let router = Router::new().put("/set-favorite", |request: Request<RequestBody>| {
+        handle_put(
+            request.body,
+            &group,
+            today,
+            settings.recap_period_days,
+            &image_dao,
+            &user_reaction_dao,
+        )
+    });
+
+    Ok(router.handle(req).await)
+}
+
+// Body of the request to be recevied
+#[derive(Serialize, Deserialize, Debug)]
+struct RequestBody {
+    uuid: String,
+    favorite_image: String,
+}
+
+// Body of the response for PUT
+#[derive(Serialize, Deserialize, Debug)]
+struct ResponseBody {
+    uuid: String,
+    favorite_image: String,
+    // How many users picked each image as their favorite, keyed by the image's key
+    favorite_counts: HashMap<String, String>,
+}
+
+// Error enum for PUT
+#[derive(Debug)]
+pub enum PutHandlerError {
+    ImageDynamoDaoError(ImageDynamoDaoError),
+    UserReactionDaoError(UserReactionDaoError),
+    InvalidRequest(String),
+    LocalError(String),
+}
+
+impl From<ImageDynamoDaoError> for PutHandlerError {
+    fn from(err: ImageDynamoDaoError) -> Self {
+        Self::ImageDynamoDaoError(err)
+    }
+}
+
+impl From<UserReactionDaoError> for PutHandlerError {
+    fn from(err: UserReactionDaoError) -> Self {
+        Self::UserReactionDaoError(err)
+    }
+}
+
+impl From<String> for PutHandlerError {
+    fn from(err: String) -> Self {
+        Self::LocalError(err)
+    }
+}
+
+impl From<PutHandlerError> for ApiError {
+    fn from(err: PutHandlerError) -> Self {
+        match err {
+            PutHandlerError::InvalidRequest(message) => ApiError::BadRequest(message),
+            // Another request kept updating the user's favorite at the same time
+            PutHandlerError::UserReactionDaoError(UserReactionDaoError::DynamoDbError(
+                DynamoDbUtilError::ConditionalCheckFailure(_),
+            )) => ApiError::Conflict(
+                "The favorite was changed by another request, try again".to_owned(),
+            ),
+            err => ApiError::internal(err),
+        }
+    }
+}
+
+async fn handle_put<D: DynamoDbUtil>(
+    body: RequestBody,
+    group: &str,
+    today: NaiveDate,
+    recap_period_days: i64,
+    image_dao: &ImageDynamoDao<'_, D>,
+    user_reaction_dao: &UserReactionDao<'_, D>,
+) -> Result<ResponseBody, PutHandlerError> {
+    info!(body = ?body, "The parsed body value");
+
+    let uuid = &body.uuid;
+    let favorite_image = &body.favorite_image;
+    let today_as_string = today.format("%Y-%m-%d").to_string();
+
+    // The empty string clears the favorite so only picked images need to be in the recap
+    if !favorite_image.is_empty() {
+        validate_favorite(group, today, recap_period_days, image_dao, favorite_image).await?;
+    }
+
+    // Set the favorite image
+    let old_favorite_image = user_reaction_dao
+        .set_favorite(group, &today_as_string, uuid, favorite_image)
+        .await?;
+
+    info!(
+        old_favorite = old_favorite_image,
+        "Request to update favorite image complete. The old favorite was"
+    );
+
+    // The counts were updated along with the favorite so they only need to be fetched
+    let favorite_counts = user_reaction_dao
+        .get_favorite_counts(group, &today_as_string)
+        .await?;
+
+    Ok(ResponseBody {
+        favorite_image: favorite_image.to_owned(),
+        uuid: uuid.to_owned(),
+        favorite_counts,
+    })
+}
+
+///
+/// Checks that the favorite is one of the images in today's recap. Favorites can only be picked on the
+/// days a recap is shown.
+///
+async fn validate_favorite<D: DynamoDbUtil>(
+    group: &str,
+    today: NaiveDate,
+    recap_period_days: i64,
+    image_dao: &ImageDynamoDao<'_, D>,
+    favorite_image: &str,
+) -> Result<(), PutHandlerError> {
+    let is_recap_day = image_dao
+        .get_image(group, today)
+        .await?
+        .is_some_and(|image| image.get_recents);
+    if !is_recap_day {
+        return Err(PutHandlerError::InvalidRequest(
+            "Favorites can only be picked on the days a recap is shown".to_owned(),
+        ));
+    }
+
+    let object_key = object_key_from_favorite(favorite_image);
+    let is_in_recap = image_dao
+        .get_recents(group, today, recap_period_days)
+        .await?
+        .iter()
+        .any(|image| image.object_key == object_key);
+    if !is_in_recap {
+        return Err(PutHandlerError::InvalidRequest(format!(
+            "The favorite {} is not one of the images in today's recap",
+            favorite_image
+        )));
+    }
+
+    Ok(())
+}
+
+pub struct EnvironmentVariables {
+    pub default_group: Option<String>,
+    pub table_name: String,
+    pub table_primary_key: String,
+    pub table_sort_key: String,
+}
+
+impl EnvironmentVariables {
+    pub fn build() -> EnvironmentVariables {
+        let resource = Resource::init().expect("Should be able to initialize SST resource");
+        let table: SstTable = resource
+            .get("ImageTable")
+            .expect("Should be able to get ImageTable");
+
+        EnvironmentVariables {
+            default_group: std::env::var("DEFAULT_GROUP").ok(),
+            table_name: table.name,
+            table_primary_key: table.primary_key,
+            table_sort_key: table.sort_key,
+        }
+    }
+}
@@ -74,7 +74,7 @@ const SubPage = ({ todaysImageResponse, todaysMetadataResponse }) => {
 
   useEffect(() => {
     if (todaysImageResponse.isSuccess && todaysImageResponse.data.weekly_recap != null) {
-      setWeeklyRecap(todaysImageResponse.data.weekly_recap);
+      setWeeklyRecap(todaysImageResponse.data.weekly_recap.images.map(image => image.url));
     }
   }, [todaysImageResponse]);
 
@@ -65,10 +65,10 @@ export default function DailyImageScreen() {
     if (!todaysImageResponse.data?.weekly_recap) return [];
 
     // Create an array of objects with url and date properties
-    return Object.entries(todaysImageResponse.data.weekly_recap).map(
-      ([date, url]) => ({
-        url: url as string,
-        date,
+    return todaysImageResponse.data.weekly_recap.images.map(
+      (image: { url: string; date: string }) => ({
+        url: image.url,
+        date: image.date,
       }),
     );
   }, [todaysImageResponse.data?.weekly_recap]);
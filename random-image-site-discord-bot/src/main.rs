@@ -252,11 +252,11 @@ async fn upload_image(ctx: &Context, dynamic_image: DynamicImage) -> Result<(),
         "Sucessfully converted the image to JPG and got the image as bytes."
     );
 
-    // Attempt to upload to S3
+    // Attempt to upload to S3. A group's images are the objects under its `{group}/` prefix
     let put_object_output = s3_client
         .put_object()
         .bucket(IMAGE_BUCKET_NAME)
-        .key(&file_name)
+        .key(format!("discord/{}.jpg", uuid_str))
         .content_type("image/jpeg")
         .body(body)
         .send()
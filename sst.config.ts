@@ -11,6 +11,11 @@ import { access } from "fs";
 // TODO: Try to see if its possible to protect API GW v2 by
 // only allowing calls originating from CloudFront distribution.
 
+// Group used by image API routes that don't include a {group} path parameter.
+// Every group (including this one) must be listed in the group registry record
+// (pk "Groups", sk "Registry") of the ImageTable.
+const DEFAULT_GROUP = "discord";
+
 interface MyRouter {
   router: sst.aws.Router;
   backendDomain: string;
@@ -63,6 +68,7 @@ export default $config({
       baseBucket: viewableBucket,
       postProcessBucketLink: viewableBucketPostProcessLink,
       listOnlyBucketLink: viewableBucketListOnlyLink,
+      readOnlyBucketLink: viewableBucketReadOnlyLink,
     } = await createViewableImagesBucket();
     const { imageTable } = await createImageTable();
 
@@ -93,7 +99,12 @@ export default $config({
 
     // Infra functions
     await imageSite(myRouter);
-    await imageApi(myRouter, imageTable);
+    await imageApi(
+      myRouter,
+      imageTable,
+      viewableBucketReadOnlyLink,
+      viewableBucketListOnlyLink,
+    );
     await mobileApi(myRouter, viewableBucket, viewableBucketPostProcessLink);
     await backgroundEvents(imageTable, viewableBucketListOnlyLink);
   },
@@ -145,42 +156,106 @@ async function imageSite(myRouter: MyRouter) {
   });
 }
 
-async function imageApi(myRouter: MyRouter, imageTable: sst.aws.Dynamo) {
+async function imageApi(
+  myRouter: MyRouter,
+  imageTable: sst.aws.Dynamo,
+  viewableBucketReadOnlyLink: sst.Linkable,
+  viewableBucketListOnlyLink: sst.Linkable,
+) {
   const imageApi = new sst.aws.ApiGatewayV2("ImageApi");
+  // Sent as a bearer token by admins to use the admin routes
+  const adminApiToken = new sst.Secret("AdminApiToken");
 
-  imageApi.route("GET /todays-image", {
-    handler: "./packages/images-api.get_image_lambda",
-    runtime: "rust",
-    architecture: "arm64",
-    memory: "128 MB",
-    environment: {
-      // TODO: make this a constant somehow so it's not defined twice.
-      // or maybe a function on myRouter
-      IMAGE_DOMAIN: `img.${myRouter.backendDomain}`,
-    },
-    link: [imageTable],
-  });
-  imageApi.route("GET /todays-metadata", {
-    handler: "./packages/images-api.get_or_set_reaction_lambda",
-    runtime: "rust",
-    architecture: "arm64",
-    memory: "128 MB",
-    link: [imageTable],
-  });
-  imageApi.route("PUT /todays-metadata", {
-    handler: "./packages/images-api.get_or_set_reaction_lambda",
-    runtime: "rust",
-    architecture: "arm64",
-    memory: "128 MB",
-    link: [imageTable],
-  });
-  imageApi.route("PUT /set-favorite", {
-    handler: "./packages/images-api.set_favorite_recent_lambda",
-    runtime: "rust",
-    architecture: "arm64",
-    memory: "128 MB",
-    link: [imageTable],
-  });
+  // Routes without a {group} path parameter fall back to the default group
+  // so existing clients keep working
+  const groupEnvironment = {
+    DEFAULT_GROUP: DEFAULT_GROUP,
+  };
+
+  for (const prefix of ["", "/{group}"]) {
+    imageApi.route(`GET ${prefix}/todays-image`, {
+      handler: "./packages/images-api.get_image_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: {
+        // TODO: make this a constant somehow so it's not defined twice.
+        // or maybe a function on myRouter
+        IMAGE_DOMAIN: `img.${myRouter.backendDomain}`,
+        ...groupEnvironment,
+      },
+      // The bucket is listed to pick today's image if the daily setup missed it
+      link: [imageTable, viewableBucketListOnlyLink],
+    });
+    imageApi.route(`GET ${prefix}/history`, {
+      handler: "./packages/images-api.get_image_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: {
+        IMAGE_DOMAIN: `img.${myRouter.backendDomain}`,
+        ...groupEnvironment,
+      },
+      // Served by the same lambda as todays-image so it needs the same links
+      link: [imageTable, viewableBucketListOnlyLink],
+    });
+    imageApi.route(`GET ${prefix}/todays-metadata`, {
+      handler: "./packages/images-api.get_or_set_reaction_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: groupEnvironment,
+      link: [imageTable],
+    });
+    imageApi.route(`PUT ${prefix}/todays-metadata`, {
+      handler: "./packages/images-api.get_or_set_reaction_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: groupEnvironment,
+      link: [imageTable],
+    });
+    imageApi.route(`PUT ${prefix}/set-favorite`, {
+      handler: "./packages/images-api.set_favorite_recent_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: groupEnvironment,
+      link: [imageTable],
+    });
+    imageApi.route(`PUT ${prefix}/archive`, {
+      handler: "./packages/images-api.archive_image_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: groupEnvironment,
+      link: [imageTable, adminApiToken],
+    });
+    imageApi.route(`PUT ${prefix}/pin`, {
+      handler: "./packages/images-api.pin_image_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: groupEnvironment,
+      link: [imageTable, viewableBucketReadOnlyLink, adminApiToken],
+    });
+    imageApi.route(`GET ${prefix}/queue`, {
+      handler: "./packages/images-api.image_queue_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: groupEnvironment,
+      link: [imageTable, adminApiToken],
+    });
+    imageApi.route(`PUT ${prefix}/queue`, {
+      handler: "./packages/images-api.image_queue_lambda",
+      runtime: "rust",
+      architecture: "arm64",
+      memory: "128 MB",
+      environment: groupEnvironment,
+      link: [imageTable, adminApiToken],
+    });
+  }
 
   myRouter.router.route(`api.${myRouter.backendDomain}`, imageApi.url);
 }
@@ -205,6 +280,17 @@ async function backgroundEvents(
       },
     },
   });
+
+  // Not scheduled. Invoked manually to recompute the reaction counts from the
+  // user records, see packages/images-api/reconcile_counts_lambda
+  new sst.aws.Function("ReconcileCountsFunction", {
+    handler: "./packages/images-api.reconcile_counts_lambda",
+    runtime: "rust",
+    architecture: "arm64",
+    memory: "128 MB",
+    timeout: "5 minutes",
+    link: [imageTable],
+  });
 }
 
 async function mobileApi(
@@ -389,6 +475,7 @@ async function createViewableImagesBucket(): Promise<{
   baseBucket: sst.aws.Bucket;
   postProcessBucketLink: sst.Linkable;
   listOnlyBucketLink: sst.Linkable;
+  readOnlyBucketLink: sst.Linkable;
 }> {
   const viewableImageBucket = new sst.aws.Bucket("ViewableBucket", {
     access: "cloudfront",
@@ -421,11 +508,27 @@ async function createViewableImagesBucket(): Promise<{
       }),
     ],
   });
+  // s3:ListBucket lets a missing object come back as a 404 instead of a 403
+  const viewableReadOnly = new sst.Linkable("ViewableBucketReadOnly", {
+    properties: {
+      name: viewableImageBucket.name,
+    },
+    include: [
+      sst.aws.permission({
+        actions: ["s3:GetObject", "s3:ListBucket"],
+        resources: [
+          viewableImageBucket.arn,
+          $interpolate`${viewableImageBucket.arn}/*`,
+        ],
+      }),
+    ],
+  });
 
   return {
     baseBucket: viewableImageBucket,
     postProcessBucketLink: viewablePostProcess,
     listOnlyBucketLink: viewableListOnly,
+    readOnlyBucketLink: viewableReadOnly,
   };
 }
 